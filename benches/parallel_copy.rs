@@ -0,0 +1,55 @@
+//! Copies a generated tree of small files with one thread, then with several,
+//! and prints the speedup.
+//!
+//! ```console
+//! cargo bench --bench parallel_copy
+//! IFOP_BENCH_FILES=200000 IFOP_BENCH_THREADS=16 IFOP_BENCH_DIR=/mnt/build cargo bench --bench parallel_copy
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use ifop::{copy_file_with, CopyOptions, OperationFlags};
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+/// `files` files of 1 to 4 KiB, 100 to a folder, two levels deep.
+fn generate(root: &Path, files: usize) {
+    for i in 0..files {
+        let folder = root.join(format!("{:03}", i / 10_000)).join(format!("{:03}", i / 100 % 100));
+        if i % 100 == 0 {
+            fs::create_dir_all(&folder).unwrap();
+        }
+        let size = 1024 + i * 37 % 3072;
+        fs::write(folder.join(format!("file{}.txt", i)), vec![b'a' + (i % 26) as u8; size]).unwrap();
+    }
+}
+
+fn run(src: &Path, dest: &Path, options: &CopyOptions) -> Duration {
+    let _ = fs::remove_dir_all(dest);
+    fs::create_dir_all(dest).unwrap();
+    let start = Instant::now();
+    copy_file_with(src.to_str().unwrap(), dest.to_str().unwrap(), options, Some(OperationFlags::FOF_NO_UI)).unwrap();
+    start.elapsed()
+}
+
+fn main() {
+    let files = env_or("IFOP_BENCH_FILES", 20_000);
+    let threads = env_or("IFOP_BENCH_THREADS", 8);
+    let root: PathBuf = env_or("IFOP_BENCH_DIR", std::env::temp_dir()).join("ifop-bench-parallel-copy");
+    let _ = fs::remove_dir_all(&root);
+    let (src, dest) = (root.join("src"), root.join("dest"));
+    generate(&src, files);
+
+    // Once to warm the cache, so both runs read from memory.
+    run(&src, &dest, &CopyOptions::new().threads(1));
+    let sequential = run(&src, &dest, &CopyOptions::new().threads(1));
+    let parallel = run(&src, &dest, &CopyOptions::new().threads(threads));
+    println!("{} files, 1 thread:   {:>8.3}s", files, sequential.as_secs_f64());
+    println!("{} files, {} threads: {:>8.3}s", files, threads, parallel.as_secs_f64());
+    println!("speedup: {:.2}x", sequential.as_secs_f64() / parallel.as_secs_f64());
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -1,29 +1,55 @@
-use std::collections::HashMap;
 use clap::{Parser, Subcommand};
-use windows::Win32::UI::Shell::*;
-use windows_core::Result;
 use ifop::*;
+#[cfg(windows)]
 use windows::Win32::System::Com::{COINIT_MULTITHREADED, CoInitializeEx};
 
+type ItemToDest = fn (file: &str, dest: &str, flags:Option<OperationFlags>) -> Result<()>;
+type ItemsToDest = fn (files: Vec<&str>, dest: &str, flags:Option<OperationFlags>) -> Result<()>;
+type ItemToDestAs = fn (file: &str, dest: &str, new_name: &str, flags:Option<OperationFlags>) -> Result<()>;
+type Item = fn (file: &str, flags:Option<OperationFlags>) -> Result<()>;
+type Items = fn (files: Vec<&str>, flags:Option<OperationFlags>) -> Result<()>;
+
+/// Exit code 1 when an item failed, 2 when the user aborted (part of) the operation.
 fn dump_result(res: Result<()>) {
     match res {
         Ok(_) => {
             print!("ok!");
         }
         Err(e) => {
-            print!("{}", e)
+            println!("{}", e);
+            std::process::exit(if matches!(e, Error::Aborted { .. }) { 2 } else { 1 });
         }
     }
 }
 
+/// Exit code from the report's status: 1 when an item failed, 2 when the user
+/// aborted (part of) the operation.
+fn dump_report(res: Result<OperationReport>) {
+    match res {
+        Ok(report) => {
+            let code = report.exit_code();
+            if code == 0 {
+                print!("ok!");
+                return;
+            }
+            match report.failures().next() {
+                Some(item) => println!("{}", item.result.as_ref().unwrap_err()),
+                None => println!("{}", Error::Aborted { path: None, op: None }),
+            }
+            std::process::exit(code);
+        }
+        Err(e) => dump_result(Err(e)),
+    }
+}
+
 fn apply_command(
-    target: &String, 
-    dest_op: Option<&String>, 
-    command1: Option<fn (file: &str, dest: &str, flags:Option<FILEOPERATION_FLAGS>) -> Result<()>>, 
-    command2: Option<fn (files: Vec<&str>, dest: &str, flags:Option<FILEOPERATION_FLAGS>) -> Result<()>>, 
-    command3: Option<fn (file: &str, flags:Option<FILEOPERATION_FLAGS>) -> Result<()>>, 
-    command4: Option<fn (files: Vec<&str>, flags:Option<FILEOPERATION_FLAGS>) -> Result<()>>,
-    flags: Option<FILEOPERATION_FLAGS>
+    target: &str, 
+    dest_op: Option<&str>, 
+    command1: Option<ItemToDest>, 
+    command2: Option<ItemsToDest>, 
+    command3: Option<Item>, 
+    command4: Option<Items>,
+    flags: Option<OperationFlags>
 ) {
     let target_files: Vec<&str> = target.split(",").collect();
 
@@ -60,7 +86,30 @@ fn apply_command(
     print!("No anything to do");
 }
 
-fn copy(src: &String, dest: &String, flags: Option<FILEOPERATION_FLAGS>) {
+/// `--src` / `--target` items, glob patterns expanded.
+fn sources(list: &str, ignore_case: bool) -> Result<String> {
+    let patterns: Vec<&str> = list.split(',').collect();
+    expand_patterns(&patterns, ignore_case).map(|items| items.join(","))
+}
+
+fn apply_named(src: &str, dest: &str, name: &str, command: ItemToDestAs, flags: Option<OperationFlags>) {
+    if src.contains(',') {
+        print!("--name needs a single --src");
+        return;
+    }
+    dump_result(command(src, dest, name, flags));
+}
+
+fn copy(src: &str, dest: &str, name: &Option<String>, flags: Option<OperationFlags>, ignore_case: bool) {
+    let src = match sources(src, ignore_case) {
+        Ok(src) => src,
+        Err(e) => return dump_result(Err(e)),
+    };
+    let src = src.as_str();
+    if let Some(name) = name {
+        apply_named(src, dest, name, copy_file_as, flags);
+        return;
+    }
     { 
         apply_command(
             src, 
@@ -74,10 +123,96 @@ fn copy(src: &String, dest: &String, flags: Option<FILEOPERATION_FLAGS>) {
     }
 }
 
-fn delete(target: &String, flags: Option<FILEOPERATION_FLAGS>) {
+/// One pair per line: `src<TAB>dest_folder[<TAB>new_name]`, or the same with
+/// commas. Fields may be double-quoted; blank lines and `#` comments are skipped.
+fn read_pairs(file: &str) -> std::io::Result<Vec<(String, String, Option<String>)>> {
+    let content = std::fs::read_to_string(file)?;
+    let mut pairs = Vec::new();
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let separator = if line.contains('\t') { '\t' } else { ',' };
+        let mut fields = split_fields(line, separator).into_iter();
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(src), Some(dest), name) if !src.is_empty() && !dest.is_empty() => {
+                pairs.push((src, dest, name.filter(|name| !name.is_empty())));
+            }
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{}:{}: expected src{}dest[{}name]", file, number + 1, separator.escape_default(), separator.escape_default())
+                ));
+            }
+        }
+    }
+
+    Ok(pairs)
+}
+
+fn split_fields(line: &str, separator: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => fields.push(std::mem::take(&mut field).trim().to_owned()),
+            c => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_owned());
+    fields
+}
+
+fn pairs(file: &str, moving: bool, flags: Option<OperationFlags>) {
+    let pairs = match read_pairs(file) {
+        Ok(pairs) => pairs,
+        Err(e) => {
+            print!("{}", e);
+            return;
+        }
+    };
+    let pairs: Vec<(&str, &str, Option<&str>)> = pairs.iter()
+        .map(|(src, dest, name)| (src.as_str(), dest.as_str(), name.as_deref()))
+        .collect();
+
+    if moving {
+        dump_result(move_pairs(pairs, flags));
+    } else {
+        dump_result(copy_pairs(pairs, flags));
+    }
+}
+
+fn copy_resumable(src: &str, dest: &str, flags: Option<OperationFlags>, ignore_case: bool) {
+    let src = match sources(src, ignore_case) {
+        Ok(src) => src,
+        Err(e) => return dump_result(Err(e)),
+    };
+    for src in src.split(',') {
+        if let Err(e) = copy_resume(src, dest, flags) {
+            return dump_result(Err(e));
+        }
+    }
+    dump_result(Ok(()));
+}
+
+fn delete(target: &str, flags: Option<OperationFlags>, ignore_case: bool) {
+    let target = match sources(target, ignore_case) {
+        Ok(target) => target,
+        Err(e) => return dump_result(Err(e)),
+    };
     { 
         apply_command(
-            target, 
+            &target, 
             None, 
             None, 
             None, 
@@ -88,7 +223,7 @@ fn delete(target: &String, flags: Option<FILEOPERATION_FLAGS>) {
     }
 }
 
-fn rename(target: &String, dest: &String, flags: Option<FILEOPERATION_FLAGS>) {
+fn rename(target: &str, dest: &str, flags: Option<OperationFlags>) {
     { 
         apply_command(
             target, 
@@ -102,7 +237,24 @@ fn rename(target: &String, dest: &String, flags: Option<FILEOPERATION_FLAGS>) {
     }
 }
 
-fn _move(src: &String, dest: &String, flags: Option<FILEOPERATION_FLAGS>) {
+fn rename_atomic(src: &str, dest: &str) {
+    if src.contains(',') {
+        print!("--no-replace needs a single --src");
+        return;
+    }
+    dump_result(rename_no_replace(src, dest));
+}
+
+fn _move(src: &str, dest: &str, name: &Option<String>, flags: Option<OperationFlags>, ignore_case: bool) {
+    let src = match sources(src, ignore_case) {
+        Ok(src) => src,
+        Err(e) => return dump_result(Err(e)),
+    };
+    let src = src.as_str();
+    if let Some(name) = name {
+        apply_named(src, dest, name, move_file_as, flags);
+        return;
+    }
     { 
         apply_command(
             src, 
@@ -116,7 +268,7 @@ fn _move(src: &String, dest: &String, flags: Option<FILEOPERATION_FLAGS>) {
     }
 }
 
-fn new_folder(target: &String, dest: &String, flags: Option<FILEOPERATION_FLAGS>) {
+fn new_folder(target: &str, dest: &str, flags: Option<OperationFlags>) {
     {
         apply_command(
             target, 
@@ -129,7 +281,7 @@ fn new_folder(target: &String, dest: &String, flags: Option<FILEOPERATION_FLAGS>
     }
 }
 
-fn new_file(target: &String, dest: &String, flags: Option<FILEOPERATION_FLAGS>) {
+fn new_file(target: &str, dest: &str, flags: Option<OperationFlags>) {
     {
         apply_command(
             target, 
@@ -142,12 +294,488 @@ fn new_file(target: &String, dest: &String, flags: Option<FILEOPERATION_FLAGS>)
     }
 }
 
+/// `30d`, `12h`, `45m`, `90s` or `2w`.
+fn parse_age(value: &str) -> std::result::Result<std::time::Duration, String> {
+    let split = value.len().saturating_sub(1);
+    let (number, unit) = value.split_at(split);
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("`{}`: expected a number followed by s, m, h, d or w", value)),
+    };
+    let number: u64 = number.parse().map_err(|_| format!("`{}`: expected a number followed by s, m, h, d or w", value))?;
+    Ok(std::time::Duration::from_secs(number * seconds))
+}
+
+/// `10k`, `5M`, `1G`: bytes with an optional binary unit.
+fn parse_size(value: &str) -> std::result::Result<u64, String> {
+    let error = || format!("`{}`: expected a number of bytes, optionally followed by k, M or G", value);
+    let (number, shift) = match value.chars().last() {
+        Some('k' | 'K') => (&value[..value.len() - 1], 10),
+        Some('m' | 'M') => (&value[..value.len() - 1], 20),
+        Some('g' | 'G') => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    let number: u64 = number.parse().map_err(|_| error())?;
+    number.checked_mul(1 << shift).ok_or_else(error)
+}
+
+/// `YYYY-MM-DD` in UTC, or an age like `7d` counted back from now.
+fn parse_time(value: &str) -> std::result::Result<std::time::SystemTime, String> {
+    if let Ok(age) = parse_age(value) {
+        return Ok(std::time::SystemTime::now() - age);
+    }
+    let error = || format!("`{}`: expected YYYY-MM-DD or an age like 7d", value);
+    let parts: Vec<i64> = value.split('-').map(|part| part.parse().map_err(|_| error())).collect::<std::result::Result<_, _>>()?;
+    let [year, month, day] = parts[..] else {
+        return Err(error());
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(error());
+    }
+    // Civil date to days since 1970-01-01.
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    let days = u64::try_from(days).map_err(|_| error())?;
+    Ok(std::time::UNIX_EPOCH + std::time::Duration::from_secs(days * 86400))
+}
+
+/// `YYYY-MM-DD hh:mm:ss` in UTC.
+fn format_time(time: std::time::SystemTime) -> String {
+    let secs = time.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rest) = (secs / 86400, secs % 86400);
+    // Days since 1970-01-01 to a civil date.
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, rest / 3600, rest % 3600 / 60, rest % 60)
+}
+
+fn trash(command: &TrashCommands) {
+    match command {
+        TrashCommands::List => match list_trash() {
+            Ok(items) => {
+                for item in items {
+                    let deleted = item.deleted.map(format_time).unwrap_or_else(|| "-".to_owned());
+                    println!("{}\t{}\t{}\t{}", deleted, item.size, item.original_path.display(), item.id);
+                }
+            }
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        TrashCommands::Restore { item, flags } => match restore_trash_item(item, *flags) {
+            Ok(path) => print!("restored to {}", path.display()),
+            Err(e) => dump_result(Err(e)),
+        },
+        TrashCommands::Empty { older_than } => match empty_trash(*older_than) {
+            Ok(count) => print!("removed {} items", count),
+            Err(e) => dump_result(Err(e)),
+        },
+    }
+}
+
+fn undo_entries(count: usize) {
+    match undo(count) {
+        Ok(entries) if entries.is_empty() => print!("nothing to undo"),
+        Ok(entries) => {
+            for entry in entries {
+                println!("undone #{} {} ({} items)", entry.id, entry.command, entry.actions.len());
+            }
+        }
+        Err(e) => dump_result(Err(e)),
+    }
+}
+
+fn redo_entries(count: usize) {
+    match redo(count) {
+        Ok(entries) if entries.is_empty() => print!("nothing to redo"),
+        Ok(entries) => {
+            for entry in entries {
+                println!("redone #{} {} ({} items)", entry.id, entry.command, entry.actions.len());
+            }
+        }
+        Err(e) => dump_result(Err(e)),
+    }
+}
+
+fn print_history() {
+    match history() {
+        Ok(entries) => {
+            for entry in entries {
+                let time = format_time(std::time::UNIX_EPOCH + std::time::Duration::from_secs(entry.time));
+                let status = format!("{:?}", entry.status).to_lowercase();
+                let undone = if entry.undone { " (undone)" } else { "" };
+                println!("#{}\t{}\t{}\t{} items\t{}{}", entry.id, time, entry.command, entry.actions.len(), status, undone);
+            }
+        }
+        Err(e) => dump_result(Err(e)),
+    }
+}
+
+/// The steps `command` would queue, for `--dry-run` and `--on-conflict`.
+fn operation(command: &Commands, option: &str, ignore_case: bool) -> std::result::Result<FileOperation, String> {
+    let queue = |src: &str, dest: &str, name: &Option<String>, flags: Option<OperationFlags>, moving: bool| {
+        let mut operation = FileOperation::new(flags);
+        for src in sources(src, ignore_case).map_err(|e| e.to_string())?.split(',') {
+            match (name, moving) {
+                (Some(name), false) => operation.copy_as(src, dest, name),
+                (Some(name), true) => operation.move_as(src, dest, name),
+                (None, false) => operation.copy(src, dest),
+                (None, true) => operation.move_to(src, dest),
+            };
+        }
+        Ok(operation)
+    };
+    let from_pairs = |file: &str, flags: Option<OperationFlags>, moving: bool| {
+        let mut operation = FileOperation::new(flags);
+        for (src, dest, name) in read_pairs(file).map_err(|e| e.to_string())? {
+            match (name, moving) {
+                (Some(name), false) => operation.copy_as(&src, &dest, &name),
+                (Some(name), true) => operation.move_as(&src, &dest, &name),
+                (None, false) => operation.copy(&src, &dest),
+                (None, true) => operation.move_to(&src, &dest),
+            };
+        }
+        Ok(operation)
+    };
+    match command {
+        Commands::Copy { pairs: Some(file), flags, .. } => from_pairs(file, *flags, false),
+        Commands::Copy { src, dest, name, flags, .. } => {
+            queue(src.as_deref().unwrap_or_default(), dest.as_deref().unwrap_or_default(), name, *flags, false)
+        }
+        Commands::Move { pairs: Some(file), flags, .. } => from_pairs(file, *flags, true),
+        Commands::Move { src, dest, name, flags, .. } => {
+            queue(src.as_deref().unwrap_or_default(), dest.as_deref().unwrap_or_default(), name, *flags, true)
+        }
+        Commands::Delete { target, flags } => {
+            let mut operation = FileOperation::new(*flags);
+            for target in sources(target, ignore_case).map_err(|e| e.to_string())?.split(',') {
+                operation.delete(target);
+            }
+            Ok(operation)
+        }
+        Commands::Rename { no_replace: true, .. } => Err(format!("{} does not apply to rename --no-replace", option)),
+        Commands::Rename { src, dest, flags, .. } => {
+            let mut operation = FileOperation::new(*flags);
+            operation.rename(src, dest);
+            Ok(operation)
+        }
+        Commands::NewFolder { root, name, flags } => {
+            let mut operation = FileOperation::new(*flags);
+            operation.new_folder(root, name);
+            Ok(operation)
+        }
+        Commands::NewFile { root, name, flags } => {
+            let mut operation = FileOperation::new(*flags);
+            operation.new_file(root, name);
+            Ok(operation)
+        }
+        _ => Err(format!("{} applies to copy, move, delete, rename, new-folder and new-file", option)),
+    }
+}
+
+/// Exit code 1 when the plan has conflicts.
+fn dry_run(command: &Commands, on_conflict: Option<ConflictPolicy>, filter: Option<Filter>, json: bool, ignore_case: bool) {
+    let plan = match operation(command, "--dry-run", ignore_case) {
+        Ok(mut operation) => {
+            if let Some(policy) = on_conflict {
+                operation.on_conflict(policy);
+            }
+            if let Some(filter) = filter {
+                operation.filter(filter);
+            }
+            operation.plan()
+        }
+        Err(message) => {
+            println!("{}", message);
+            std::process::exit(1);
+        }
+    };
+    print_plan(plan, json);
+}
+
+/// Exit code 1 when the plan has conflicts.
+fn print_plan(plan: Result<Plan>, json: bool) {
+    match plan {
+        Ok(plan) => {
+            if json {
+                println!("{}", serde_json::to_string_pretty(&plan).unwrap_or_default());
+            } else {
+                println!("{}", plan);
+            }
+            if plan.has_conflicts() {
+                std::process::exit(1);
+            }
+        }
+        Err(e) => dump_result(Err(e)),
+    }
+}
+
+/// What changed, one item per line. Exit code 1 when an item failed.
+fn sync_folders(command: &Commands, on_conflict: Option<ConflictPolicy>, filter: Option<Filter>, dry_run: bool, json: bool) {
+    let Commands::Sync { src, dest, content, delete, bidirectional, state, flags } = command else {
+        return;
+    };
+    let mut options = SyncOptions::new()
+        .compare(if *content { Difference::Content } else { Difference::SizeAndTime })
+        .delete_extraneous(*delete);
+    if let Some(filter) = filter {
+        options = options.filter(filter);
+    }
+    if let Some(policy) = on_conflict {
+        options = options.on_conflict(policy);
+    }
+    if let Some(state) = state {
+        options = options.state_file(state);
+    }
+    if dry_run {
+        let operation = match bidirectional {
+            true => sync_bidirectional_operation(src, dest, &options, *flags),
+            false => sync_operation(src, dest, &options, *flags),
+        };
+        return match operation {
+            Ok(operation) => print_plan(operation.plan(), json),
+            Err(e) => dump_result(Err(e)),
+        };
+    }
+    let report = match bidirectional {
+        true => sync_bidirectional(src, dest, &options, *flags),
+        false => sync(src, dest, &options, *flags),
+    };
+    match report {
+        Ok(report) => {
+            for item in &report.items {
+                match &item.result {
+                    Ok(()) => println!("{}\t{}", item.op, item.path.display()),
+                    Err(e) => println!("{}\t{}\t{}", item.op, item.path.display(), e),
+                }
+            }
+            if report.exit_code() != 0 {
+                std::process::exit(report.exit_code());
+            }
+        }
+        Err(e) => dump_result(Err(e)),
+    }
+}
+
+/// Missing, extra and corrupted files, one per line. Exit code 1 when there is any.
+fn verify_manifest(manifest: &str, root: &Option<String>, checksum: Option<Checksum>) {
+    let root = root.clone().unwrap_or_else(|| {
+        std::path::Path::new(manifest).parent().map(|parent| parent.to_string_lossy().into_owned()).unwrap_or_default()
+    });
+    let report = Manifest::read(manifest).and_then(|mut manifest| {
+        if let Some(checksum) = checksum {
+            manifest.checksum = checksum;
+        }
+        manifest.verify(if root.is_empty() { "." } else { &root })
+    });
+    match report {
+        Ok(report) => {
+            let problems = [("missing", &report.missing), ("extra", &report.extra), ("corrupted", &report.corrupted)];
+            for (problem, paths) in problems {
+                for path in paths {
+                    println!("{}\t{}", problem, path.display());
+                }
+            }
+            println!(
+                "{} verified, {} missing, {} extra, {} corrupted",
+                report.verified, report.missing.len(), report.extra.len(), report.corrupted.len()
+            );
+            if !report.is_ok() {
+                std::process::exit(1);
+            }
+        }
+        Err(e) => dump_result(Err(e)),
+    }
+}
+
+/// What `--on-conflict`, a filter, `--verify` and `--write-manifest` apply to.
+struct Options {
+    on_conflict: Option<ConflictPolicy>,
+    filter: Option<Filter>,
+    verify: Option<(Checksum, u32)>,
+    manifest: Option<(String, Checksum)>,
+    copy_options: Option<CopyOptions>,
+}
+
+fn with_options(command: &Commands, options: Options, ignore_case: bool) {
+    let Options { on_conflict, filter, verify, manifest, copy_options } = options;
+    let option = match (on_conflict, &filter, verify, &manifest) {
+        (Some(_), _, _, _) => "--on-conflict",
+        (None, Some(_), _, _) => "a filter",
+        (None, None, Some(_), _) => "--verify",
+        (None, None, None, Some(_)) => "--write-manifest",
+        (None, None, None, None) => "--threads or --reflink",
+    };
+    match operation(command, option, ignore_case) {
+        Ok(mut operation) => {
+            if let Some(policy) = on_conflict {
+                operation.on_conflict(policy);
+            }
+            if let Some(filter) = filter {
+                operation.filter(filter);
+            }
+            if let Some((checksum, retries)) = verify {
+                operation.verify(checksum, retries);
+            }
+            if let Some((path, checksum)) = &manifest {
+                operation.manifest(path, *checksum);
+            }
+            if let Some(copy_options) = copy_options {
+                operation.copy_options(copy_options);
+            }
+            dump_report(operation.perform())
+        }
+        Err(message) => {
+            println!("{}", message);
+            std::process::exit(1);
+        }
+    }
+}
+
 #[derive(Parser)]
 #[command(author, version, about)]
 struct Args {
     #[command(subcommand)]
     command: Commands,
 
+    /// Print what the command would do, file by file, without doing it
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// With --dry-run, print the plan as JSON
+    #[arg(long, global = true, requires = "dry_run")]
+    json: bool,
+
+    /// --on-conflict overwrite|skip|keep-both|keep-both-dated|overwrite-if-newer|
+    /// overwrite-if-different|overwrite-if-different-content|fail
+    #[arg(long, global = true)]
+    on_conflict: Option<ConflictPolicy>,
+
+    /// Match wildcards in --src / --target without regard to case
+    #[arg(long, global = true)]
+    ignore_case: bool,
+
+    /// Check copied files against their source: --verify [blake3|sha256], blake3 by default
+    #[arg(long, global = true, value_name = "checksum", num_args = 0..=1, default_missing_value = "blake3")]
+    verify: Option<Checksum>,
+
+    /// With --verify, copy a mismatching file again up to <N> times
+    #[arg(long, global = true, value_name = "N", default_value_t = 0, requires = "verify")]
+    retries: u32,
+
+    /// Write a manifest of the copied or moved files: SHA256SUMS, B3SUMS, or <name>.json with sizes
+    #[arg(long, global = true, value_name = "file")]
+    write_manifest: Option<String>,
+
+    /// Checksum of --write-manifest and `verify --manifest`, taken from the manifest's name by default
+    #[arg(long, global = true, value_name = "blake3|sha256")]
+    checksum: Option<Checksum>,
+
+    /// Copy the files of folders with <N> threads, one per CPU by default; the shell copies on its own on Windows
+    #[arg(long, global = true, value_name = "N")]
+    threads: Option<usize>,
+
+    /// Clone files on Btrfs, XFS and the like (Linux): auto by default, or only by cloning, or never
+    #[arg(long, global = true, value_name = "auto|always-reflink|never-reflink")]
+    reflink: Option<Reflink>,
+
+    /// Inside folders, only files matching <pattern> (gitignore syntax, repeatable)
+    #[arg(long, global = true, value_name = "pattern")]
+    include: Vec<String>,
+
+    /// Inside folders, leave out files and folders matching <pattern>: target/, *.tmp... (repeatable)
+    #[arg(long, global = true, value_name = "pattern")]
+    exclude: Vec<String>,
+
+    /// Inside folders, only files of at least <size>: 100, 10k, 5M, 1G
+    #[arg(long, global = true, value_name = "size", value_parser = parse_size)]
+    min_size: Option<u64>,
+
+    /// Inside folders, only files of at most <size>
+    #[arg(long, global = true, value_name = "size", value_parser = parse_size)]
+    max_size: Option<u64>,
+
+    /// Inside folders, only files modified since <YYYY-MM-DD|age>: 2024-01-31, 7d
+    #[arg(long, global = true, value_name = "time", value_parser = parse_time)]
+    modified_after: Option<std::time::SystemTime>,
+
+    /// Inside folders, only files modified before <YYYY-MM-DD|age>
+    #[arg(long, global = true, value_name = "time", value_parser = parse_time)]
+    modified_before: Option<std::time::SystemTime>,
+
+    /// Inside folders, leave out hidden files and folders
+    #[arg(long, global = true)]
+    skip_hidden: bool,
+
+    /// Inside folders, leave out read-only files
+    #[arg(long, global = true)]
+    skip_read_only: bool,
+
+    /// Read the .ifopignore files (gitignore syntax) of the folders
+    #[arg(long, global = true)]
+    ifopignore: bool,
+}
+
+impl Args {
+    /// --threads and --reflink, if any was given.
+    fn copy_options(&self) -> Option<CopyOptions> {
+        if self.threads.is_none() && self.reflink.is_none() {
+            return None;
+        }
+        let options = CopyOptions::new().reflink(self.reflink.unwrap_or_default());
+        Some(match self.threads {
+            Some(threads) => options.threads(threads),
+            None => options,
+        })
+    }
+
+    /// The filter options, if any was given.
+    fn filter(&self) -> std::result::Result<Option<Filter>, String> {
+        let given = !self.include.is_empty() || !self.exclude.is_empty() || self.min_size.is_some() || self.max_size.is_some()
+            || self.modified_after.is_some() || self.modified_before.is_some() || self.skip_hidden || self.skip_read_only || self.ifopignore;
+        if !given {
+            return Ok(None);
+        }
+        let mut filter = Filter::new()
+            .skip_hidden(self.skip_hidden)
+            .skip_read_only(self.skip_read_only)
+            .ignore_files(self.ifopignore);
+        for pattern in &self.include {
+            filter = filter.try_include(pattern).map_err(|e| e.to_string())?;
+        }
+        for pattern in &self.exclude {
+            filter = filter.try_exclude(pattern).map_err(|e| e.to_string())?;
+        }
+        if let Some(size) = self.min_size {
+            filter = filter.min_size(size);
+        }
+        if let Some(size) = self.max_size {
+            filter = filter.max_size(size);
+        }
+        if let Some(time) = self.modified_after {
+            filter = filter.modified_after(time);
+        }
+        if let Some(time) = self.modified_before {
+            filter = filter.modified_before(time);
+        }
+        Ok(Some(filter))
+    }
 }
 #[derive(Subcommand)]
 enum Commands {
@@ -156,30 +784,45 @@ enum Commands {
     Copy {
 
         /// --src <filename|folder> or <filename|folder>,<filename|folder>,<filename|folder>...
-        #[arg(short, long)]
-        src: String,
+        /// Wildcards allowed: *.log, **/*.tmp, file?.[ch]
+        #[arg(short, long, required_unless_present = "pairs")]
+        src: Option<String>,
 
         /// --dest <folder>
-        #[arg(short, long)]
-        dest: String,
+        #[arg(short, long, required_unless_present = "pairs")]
+        dest: Option<String>,
+
+        /// --name <new_name> for the single --src inside --dest
+        #[arg(short, long, conflicts_with = "pairs")]
+        name: Option<String>,
+
+        /// --pairs <file> with one src<TAB>dest_folder[<TAB>new_name] per line (TSV or CSV)
+        #[arg(short, long, conflicts_with_all = ["src", "dest"])]
+        pairs: Option<String>,
+
+        /// --resume: copy with a checkpoint next to the target, run again to go on
+        /// after an interruption
+        #[arg(long, conflicts_with_all = ["pairs", "name"])]
+        resume: bool,
 
         /// --flags FOF_ALLOWUNDO|FOF_CONFIRMMOUSE|....
         /// More refer in microsoft doc
         #[arg(short, long)]
-        flags: Option<String>
+        flags: Option<OperationFlags>
     },
 
     /// Delete files from --target
     Delete {
 
         /// --target <filename|folder> or <filename|folder>,<filename|folder>...
+        /// Wildcards allowed: *.log, **/*.tmp, file?.[ch]
         #[arg(short, long)]
         target: String,
 
         /// --flags FOF_ALLOWUNDO|FOF_CONFIRMMOUSE|....
         /// More refer in microsoft doc
         #[arg(short, long)]
-        flags: Option<String>
+        flags: Option<OperationFlags>
     },
 
     /// Rename file from --src to --dest
@@ -192,27 +835,51 @@ enum Commands {
         #[arg(short, long)]
         dest: String,
 
+        /// --no-replace: fail in the same step if "New name" is taken, instead of asking
+        #[arg(long, conflicts_with = "flags")]
+        no_replace: bool,
+
         /// --flags FOF_ALLOWUNDO|FOF_CONFIRMMOUSE|....
         /// More refer in microsoft doc
         #[arg(short, long)]
-        flags: Option<String>
+        flags: Option<OperationFlags>
+    },
+
+    /// Exchange --src and --dest in one step (Linux)
+    Swap {
+        /// --src <filename|folder>
+        #[arg(short, long)]
+        src: String,
+
+        /// --dest <filename|folder> to trade places with
+        #[arg(short, long)]
+        dest: String,
     },
 
     /// Move files from --src to --dest
     Move {
 
         /// --src <filename|folder> or <filename|folder>,<filename|folder>,<filename|folder>...
-        #[arg(short, long)]
-        src: String,
+        /// Wildcards allowed: *.log, **/*.tmp, file?.[ch]
+        #[arg(short, long, required_unless_present = "pairs")]
+        src: Option<String>,
 
         /// --dest <folder>
-        #[arg(short, long)]
-        dest: String,
+        #[arg(short, long, required_unless_present = "pairs")]
+        dest: Option<String>,
+
+        /// --name <new_name> for the single --src inside --dest
+        #[arg(short, long, conflicts_with = "pairs")]
+        name: Option<String>,
+
+        /// --pairs <file> with one src<TAB>dest_folder[<TAB>new_name] per line (TSV or CSV)
+        #[arg(short, long, conflicts_with_all = ["src", "dest"])]
+        pairs: Option<String>,
 
         /// --flags FOF_ALLOWUNDO|FOF_CONFIRMMOUSE|....
         /// More refer in microsoft doc
         #[arg(short, long)]
-        flags: Option<String>
+        flags: Option<OperationFlags>
     },
 
     /// Create folder --root <root_path> --name <name>
@@ -228,7 +895,74 @@ enum Commands {
         /// --flags FOF_ALLOWUNDO|FOF_CONFIRMMOUSE|....
         /// More refer in microsoft doc
         #[arg(short, long)]
-        flags: Option<String>
+        flags: Option<OperationFlags>
+    },
+
+    /// List, restore or empty the Recycle Bin / Trash
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommands,
+    },
+
+    /// Undo the last [N] operations recorded in the journal
+    Undo {
+        /// How many operations, newest first
+        #[arg(default_value_t = 1)]
+        count: usize,
+    },
+
+    /// Redo the last [N] undone operations
+    Redo {
+        /// How many operations, in the order they first ran
+        #[arg(default_value_t = 1)]
+        count: usize,
+    },
+
+    /// Operations recorded in the journal: id, time, command, item count, status
+    History,
+
+    /// Make --dest match --src: copy new and changed files, optionally delete the others
+    Sync {
+        /// --src <folder>
+        #[arg(short, long)]
+        src: String,
+
+        /// --dest <folder>, created when missing
+        #[arg(short, long)]
+        dest: String,
+
+        /// Compare file content instead of size and modification time
+        #[arg(long)]
+        content: bool,
+
+        /// Delete what --dest has and --src does not (to the trash with FOF_ALLOWUNDO)
+        #[arg(long, conflicts_with = "bidirectional")]
+        delete: bool,
+
+        /// Both ways: pass on what changed on either side since the last run,
+        /// conflicts decided by --on-conflict (default keep-both)
+        #[arg(long)]
+        bidirectional: bool,
+
+        /// --state <file> for the last synced tree of --bidirectional
+        #[arg(long, requires = "bidirectional")]
+        state: Option<String>,
+
+        /// --flags FOF_ALLOWUNDO|FOF_CONFIRMMOUSE|....
+        /// More refer in microsoft doc
+        #[arg(short, long)]
+        flags: Option<OperationFlags>
+    },
+
+    /// Check the files of a folder against a manifest: missing, extra and corrupted files
+    Verify {
+        /// --manifest <file>: SHA256SUMS, B3SUMS or a .json manifest
+        #[arg(short, long)]
+        manifest: String,
+
+        /// --root <folder> the paths in the manifest are relative to, the manifest's folder by default
+        #[arg(short, long)]
+        root: Option<String>,
     },
 
     /// Create file --root <root_path> --name <name>
@@ -244,68 +978,127 @@ enum Commands {
         /// --flags FOF_ALLOWUNDO|FOF_CONFIRMMOUSE|....
         /// More refer in microsoft doc
         #[arg(short, long)]
-        flags: Option<String>
-    }
-}
-
-fn query_flags(cli_flags: &Option<String>) -> Option<FILEOPERATION_FLAGS> {
-    let flags_map: HashMap<&str, FILEOPERATION_FLAGS> = HashMap::from([
-        ("FOF_ALLOWUNDO", FOF_ALLOWUNDO),
-        ("FOF_CONFIRMMOUSE", FOF_CONFIRMMOUSE),
-        ("FOF_FILESONLY", FOF_FILESONLY),
-        ("FOF_MULTIDESTFILES", FOF_MULTIDESTFILES),
-        ("FOF_NOCONFIRMATION", FOF_NOCONFIRMATION),
-        ("FOF_NOCONFIRMMKDIR", FOF_NOCONFIRMMKDIR),
-        ("FOF_NO_CONNECTED_ELEMENTS", FOF_NO_CONNECTED_ELEMENTS),
-        ("FOF_NOCOPYSECURITYATTRIBS", FOF_NOCOPYSECURITYATTRIBS),
-        ("FOF_NOERRORUI", FOF_NOERRORUI),
-        ("FOF_NORECURSEREPARSE", FOF_NORECURSEREPARSE),
-        ("FOF_NORECURSION", FOF_NORECURSION),
-        ("FOF_NO_UI", FOF_NO_UI),
-        ("FOF_RENAMEONCOLLISION", FOF_RENAMEONCOLLISION),
-        ("FOF_SILENT", FOF_SILENT),
-        ("FOF_SIMPLEPROGRESS", FOF_SIMPLEPROGRESS),
-        ("FOF_WANTMAPPINGHANDLE", FOF_WANTMAPPINGHANDLE),
-        ("FOF_WANTNUKEWARNING", FOF_WANTNUKEWARNING)
-    ]);
-
-    if let Some(flags_pattern) = cli_flags {
-        let mut flags = FILEOPERATION_FLAGS(0);
-        let flags_arr: Vec<&str> = flags_pattern.split("|").collect();
-
-        for flag in flags_arr {
-            if flags_map.contains_key(flag) {
-                flags.0 = flags.0 | flags_map[flag].0;
-            }
-        }
-
-        return Some(flags)
+        flags: Option<OperationFlags>
     }
+}
+
+#[derive(Subcommand)]
+enum TrashCommands {
+    /// Deletion date, size, original path and id of every item
+    List,
 
-    None
+    /// Put <item> back where it was deleted from
+    Restore {
+        /// Id from `trash list`, or the original path
+        item: String,
+
+        /// --flags FOF_RENAMEONCOLLISION to restore under a free name,
+        /// FOF_NOCONFIRMATION to replace what is at the original path
+        #[arg(short, long)]
+        flags: Option<OperationFlags>
+    },
+
+    /// Permanently delete the items in the trash
+    Empty {
+        /// --older-than 30d: only items deleted longer ago (s, m, h, d, w)
+        #[arg(long, value_parser = parse_age)]
+        older_than: Option<std::time::Duration>
+    }
 }
 
 fn main() {
     let cli = Args::parse();
+    #[cfg(windows)]
     let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+    let filter = match cli.filter() {
+        Ok(filter) => filter,
+        Err(message) => {
+            println!("{}", message);
+            std::process::exit(1);
+        }
+    };
+    if let Commands::Sync { bidirectional, .. } = &cli.command {
+        if cli.on_conflict.is_some() && !bidirectional {
+            println!("--on-conflict applies to sync --bidirectional: one way, changed files are overwritten");
+            std::process::exit(1);
+        }
+        enable_journal(!cli.dry_run);
+        sync_folders(&cli.command, cli.on_conflict, filter, cli.dry_run, cli.json);
+        return;
+    }
+    if let Commands::Copy { src, dest, resume: true, flags, .. } = &cli.command {
+        if cli.dry_run || cli.on_conflict.is_some() || filter.is_some() || cli.verify.is_some() || cli.write_manifest.is_some() || cli.copy_options().is_some() {
+            println!("--resume copies every file as it is: no --dry-run, --on-conflict, filters, --verify, --write-manifest, --threads or --reflink");
+            std::process::exit(1);
+        }
+        copy_resumable(src.as_deref().unwrap_or_default(), dest.as_deref().unwrap_or_default(), *flags, cli.ignore_case);
+        return;
+    }
+    if cli.dry_run {
+        dry_run(&cli.command, cli.on_conflict, filter, cli.json, cli.ignore_case);
+        return;
+    }
+    enable_journal(true);
+    if cli.on_conflict.is_some() || filter.is_some() || cli.verify.is_some() || cli.write_manifest.is_some() || cli.copy_options().is_some() {
+        let options = Options {
+            on_conflict: cli.on_conflict,
+            filter,
+            verify: cli.verify.map(|checksum| (checksum, cli.retries)),
+            manifest: cli.write_manifest.clone().map(|path| {
+                let checksum = cli.checksum.unwrap_or_else(|| Manifest::checksum_for(&path));
+                (path, checksum)
+            }),
+            copy_options: cli.copy_options(),
+        };
+        with_options(&cli.command, options, cli.ignore_case);
+        return;
+    }
     match &cli.command {
-        Commands::Copy { src, dest, flags } => {
-            copy(src, dest, query_flags(flags))
+        Commands::Copy { pairs: Some(file), flags, .. } => {
+            pairs(file, false, *flags)
+        }
+        Commands::Copy { src, dest, name, pairs: None, flags, .. } => {
+            copy(src.as_deref().unwrap_or_default(), dest.as_deref().unwrap_or_default(), name, *flags, cli.ignore_case)
         }
         Commands::Delete { target, flags } => {
-            delete(target, query_flags(flags))
+            delete(target, *flags, cli.ignore_case)
+        }
+        Commands::Rename { src, dest, no_replace: true, .. } => {
+            rename_atomic(src, dest)
         }
-        Commands::Rename { src, dest, flags } => {
-            rename(src, dest, query_flags(flags))
+        Commands::Rename { src, dest, flags, .. } => {
+            rename(src, dest, *flags)
         }
-        Commands::Move { src, dest, flags } => {
-            _move(src, dest, query_flags(flags))
+        Commands::Swap { src, dest } => {
+            dump_result(swap(src, dest))
+        }
+        Commands::Move { pairs: Some(file), flags, .. } => {
+            pairs(file, true, *flags)
+        }
+        Commands::Move { src, dest, name, pairs: None, flags } => {
+            _move(src.as_deref().unwrap_or_default(), dest.as_deref().unwrap_or_default(), name, *flags, cli.ignore_case)
         }
         Commands::NewFolder { root, name, flags } => {
-            new_folder(root, name, query_flags(flags))
+            new_folder(root, name, *flags)
         }
         Commands::NewFile { root, name, flags } => {
-            new_file(root, name, query_flags(flags))
+            new_file(root, name, *flags)
+        }
+        Commands::Trash { command } => {
+            trash(command)
+        }
+        Commands::Undo { count } => {
+            undo_entries(*count)
+        }
+        Commands::Redo { count } => {
+            redo_entries(*count)
+        }
+        Commands::History => {
+            print_history()
+        }
+        Commands::Sync { .. } => {}
+        Commands::Verify { manifest, root } => {
+            verify_manifest(manifest, root, cli.checksum)
         }
     }
 }
\ No newline at end of file
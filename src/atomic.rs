@@ -0,0 +1,154 @@
+//! Renames that happen whole or not at all, done straight by the file system:
+//! no shell, no progress. Both are recorded in the journal when it is on.
+
+use std::io;
+use std::path::Path;
+use crate::journal::{self, Before};
+use crate::operation::is_file_name;
+use crate::{Error, OperationKind, OperationReport, Result};
+
+#[cfg(target_os = "linux")]
+fn rename_at(src: &Path, target: &Path, flags: libc::c_uint) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let src = CString::new(src.as_os_str().as_bytes())?;
+    let target = CString::new(target.as_os_str().as_bytes())?;
+    match unsafe { libc::renameat2(libc::AT_FDCWD, src.as_ptr(), libc::AT_FDCWD, target.as_ptr(), flags) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported(call: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("{} is not supported on this platform", call))
+}
+
+/// Errors are about `src`, except a taken name, which is about `target`.
+fn with_target(error: Error, target: &Path) -> Error {
+    match error {
+        Error::Conflict { op, .. } => Error::Conflict { path: target.to_owned(), op },
+        error => error,
+    }
+}
+
+/// ### Rename `src` to `new_name`, failing if the name is taken
+/// Checking the name and renaming are one step, so nothing that appears in
+/// between gets replaced: `renameat2` with `RENAME_NOREPLACE` on Linux,
+/// `MoveFileExW` without `MOVEFILE_REPLACE_EXISTING` on Windows. A taken name
+/// is an [`Error::Conflict`] about it, a `new_name` with a separator or `..`
+/// an [`Error::InvalidPath`]. Not supported on other platforms.
+/// ```rust
+/// match ifop::rename_no_replace("/srv/app/config.next", "config") {
+///     Ok(_) => {
+///         println!("Success");
+///     }
+///     Err(ifop::Error::Conflict { path, .. }) => {
+///         println!("{} is there already", path.display());
+///     }
+///     Err(e) => {
+///         println!("{}", e);
+///     }
+/// }
+/// ```
+pub fn rename_no_replace(src: &str, new_name: &str) -> Result<()> {
+    let src = Path::new(src);
+    let op = OperationKind::Rename;
+    if !is_file_name(new_name) {
+        return Err(Error::InvalidPath { path: src.to_owned(), op });
+    }
+    let target = src.with_file_name(new_name);
+
+    #[cfg(target_os = "linux")]
+    let result = rename_at(src, &target, libc::RENAME_NOREPLACE).map_err(|e| Error::from_io(e, src, op));
+    #[cfg(windows)]
+    let result = unsafe {
+        use windows::core::HSTRING;
+        use windows::Win32::Storage::FileSystem::{MoveFileExW, MOVE_FILE_FLAGS};
+
+        // Both made of `str`s, nothing is lost.
+        let (from, to) = (src.to_string_lossy(), target.to_string_lossy());
+        MoveFileExW(&HSTRING::from(from.as_ref()), &HSTRING::from(to.as_ref()), MOVE_FILE_FLAGS(0))
+            .map_err(|e| Error::from_windows(&e, Some(src), Some(op)))
+    };
+    #[cfg(not(any(target_os = "linux", windows)))]
+    let result = Err(Error::from_io(unsupported("rename without replace"), src, op));
+
+    result.map_err(|e| with_target(e, &target))?;
+    // Nothing was at the target, or the rename would have failed.
+    let mut report = OperationReport::default();
+    report.push(op, src.to_owned(), Some(target), Ok(()));
+    journal::record(&report, &Before::default());
+    Ok(())
+}
+
+/// ### Exchange `a` and `b` in one step
+/// Both paths must exist; each takes the other's place at once, so that
+/// anything opening either path finds the old item or the new one, never
+/// nothing: a blue/green switch of two config folders. `renameat2` with
+/// `RENAME_EXCHANGE` on Linux; not supported on Windows and other platforms.
+/// ```rust
+/// // /srv/app/config now has the new config, /srv/app/config.next the old one.
+/// ifop::swap("/srv/app/config", "/srv/app/config.next").unwrap();
+/// ```
+pub fn swap(a: &str, b: &str) -> Result<()> {
+    let (a, b) = (Path::new(a), Path::new(b));
+    swap_paths(a, b)?;
+    journal::record_swap(a, b);
+    Ok(())
+}
+
+/// [`swap`] without recording it, for undo and redo.
+pub(crate) fn swap_paths(a: &Path, b: &Path) -> Result<()> {
+    let op = OperationKind::Rename;
+
+    #[cfg(target_os = "linux")]
+    let result = rename_at(a, b, libc::RENAME_EXCHANGE);
+    #[cfg(not(target_os = "linux"))]
+    let result = Err(unsupported("swap"));
+
+    result.map_err(|e| {
+        // Either one can be missing.
+        let path = if e.kind() == io::ErrorKind::NotFound && a.symlink_metadata().is_ok() { b } else { a };
+        Error::from_io(e, path, op)
+    })
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_atomic_renames() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_atomic_renames");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("blue")).unwrap();
+        fs::write(folder.join("blue").join("config"), "blue").unwrap();
+        fs::write(folder.join("green"), "green").unwrap();
+        let path = |name: &str| folder.join(name).to_str().unwrap().to_owned();
+
+        assert_eq!(
+            rename_no_replace(&path("green"), "blue"),
+            Err(Error::Conflict { path: folder.join("blue"), op: OperationKind::Rename })
+        );
+        assert_eq!(
+            rename_no_replace(&path("green"), "../next"),
+            Err(Error::InvalidPath { path: folder.join("green"), op: OperationKind::Rename })
+        );
+        rename_no_replace(&path("green"), "next").unwrap();
+        assert_eq!(fs::read_to_string(folder.join("next")).unwrap(), "green");
+
+        // A folder and a file trade places.
+        swap(&path("blue"), &path("next")).unwrap();
+        assert_eq!(fs::read_to_string(folder.join("blue")).unwrap(), "green");
+        assert_eq!(fs::read_to_string(folder.join("next").join("config")).unwrap(), "blue");
+        assert_eq!(
+            swap(&path("blue"), &path("missing")),
+            Err(Error::NotFound { path: folder.join("missing"), op: OperationKind::Rename })
+        );
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,467 @@
+//! Conflict policies: what a copy, move or rename does when its target
+//! already exists, decided by ifop itself instead of the shell's prompt or
+//! the collision flags, so both backends behave the same.
+//!
+//! The steps are resolved before they run. Items that conflict are skipped,
+//! failed, renamed, or kept for overwriting; a folder that meets an existing
+//! folder is merged and the policy applies to every file inside.
+
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::operation::Step;
+use crate::plan::{children, View};
+use crate::{OperationFlags, OperationKind};
+
+/// Name given to the new item by [`ConflictPolicy::KeepBoth`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum KeepBothNaming {
+    /// `name (2).ext`, `name (3).ext`... like Explorer.
+    #[default]
+    Numbered,
+    /// `name_2026-10-18.ext` with today's date in UTC, numbered as well if
+    /// that is taken too.
+    Dated,
+}
+
+/// How [`ConflictPolicy::OverwriteIfDifferent`] compares two files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Difference {
+    /// Size or modification time.
+    #[default]
+    SizeAndTime,
+    /// Content, byte by byte.
+    Content,
+}
+
+/// ### What a copy, move or rename does when its target exists
+/// Set with [`crate::FileOperation::on_conflict`]. Skipped items are in the
+/// report as `Ok` without a `result_path`; with `Fail` they are
+/// [`crate::Error::Conflict`]. A folder merged into an existing one is reported file
+/// by file.
+/// ```rust
+/// ifop::FileOperation::new(None)
+///     .on_conflict(ifop::ConflictPolicy::OverwriteIfNewer)
+///     .copy("c:\\src\\folder", "c:\\dest")
+///     .perform()
+///     .unwrap();
+///
+/// let policy: ifop::ConflictPolicy = "keep-both-dated".parse().unwrap();
+/// assert_eq!(policy, ifop::ConflictPolicy::KeepBoth(ifop::KeepBothNaming::Dated));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConflictPolicy {
+    Overwrite,
+    Skip,
+    /// The new item gets another name.
+    KeepBoth(KeepBothNaming),
+    /// Overwrite when the source was modified later, skip otherwise.
+    OverwriteIfNewer,
+    /// Overwrite when the files differ, skip otherwise.
+    OverwriteIfDifferent(Difference),
+    /// The item fails with [`crate::Error::Conflict`].
+    Fail,
+}
+
+const POLICIES: [(&str, ConflictPolicy); 8] = [
+    ("overwrite", ConflictPolicy::Overwrite),
+    ("skip", ConflictPolicy::Skip),
+    ("keep-both", ConflictPolicy::KeepBoth(KeepBothNaming::Numbered)),
+    ("keep-both-dated", ConflictPolicy::KeepBoth(KeepBothNaming::Dated)),
+    ("overwrite-if-newer", ConflictPolicy::OverwriteIfNewer),
+    ("overwrite-if-different", ConflictPolicy::OverwriteIfDifferent(Difference::SizeAndTime)),
+    ("overwrite-if-different-content", ConflictPolicy::OverwriteIfDifferent(Difference::Content)),
+    ("fail", ConflictPolicy::Fail),
+];
+
+/// A name that is not one of the policies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseConflictPolicyError {
+    pub name: String,
+}
+
+impl fmt::Display for ParseConflictPolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = POLICIES.iter().map(|(name, _)| *name).collect();
+        write!(f, "unknown conflict policy `{}`, expected one of {}", self.name, names.join(", "))
+    }
+}
+
+impl std::error::Error for ParseConflictPolicyError {}
+
+impl FromStr for ConflictPolicy {
+    type Err = ParseConflictPolicyError;
+
+    /// `overwrite`, `skip`, `keep-both`, `keep-both-dated`, `overwrite-if-newer`,
+    /// `overwrite-if-different`, `overwrite-if-different-content` or `fail`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        POLICIES.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s.trim()))
+            .map(|(_, policy)| *policy)
+            .ok_or_else(|| ParseConflictPolicyError { name: s.to_owned() })
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = POLICIES.iter().find(|(_, policy)| policy == self).map_or("", |(name, _)| *name);
+        f.write_str(name)
+    }
+}
+
+/// A step after its conflicts were decided.
+#[derive(Clone, Debug)]
+pub(crate) enum Resolved {
+    /// Run by the backend; conflicts left in it are meant to be overwritten.
+    Run(Step),
+    /// Not run, the existing target stays.
+    Skipped { op: OperationKind, src: PathBuf, target: PathBuf },
+    /// Not run, [`ConflictPolicy::Fail`]: an [`crate::Error::Conflict`] on `target`.
+    Failed { op: OperationKind, src: PathBuf, target: PathBuf },
+    /// A source folder merged into an existing one, removed once the steps
+    /// ran if they left it empty.
+    Emptied { src: PathBuf },
+}
+
+/// Flags for the resolved steps: every conflict left is to be overwritten.
+pub(crate) fn flags(flags: Option<OperationFlags>) -> Option<OperationFlags> {
+    let flags = flags.unwrap_or_default() - OperationFlags::FOF_RENAMEONCOLLISION - OperationFlags::FOFX_KEEPNEWERFILE;
+    Some(flags | OperationFlags::FOF_NOCONFIRMATION)
+}
+
+/// Days since 1970-01-01 to `YYYY-MM-DD`.
+fn civil_date(time: SystemTime) -> String {
+    let days = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / 86400) as i64;
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub(crate) fn keep_both_name(view: &View, target: &Path, naming: KeepBothNaming) -> PathBuf {
+    if naming == KeepBothNaming::Numbered {
+        return view.unique_name(target);
+    }
+    let stem = target.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = target.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    let dated = target.with_file_name(format!("{}_{}{}", stem, civil_date(SystemTime::now()), ext));
+    if view.exists(&dated) {
+        return view.unique_name(&dated);
+    }
+    dated
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn same_content(a: &Path, b: &Path) -> bool {
+    let (Ok(mut a), Ok(mut b)) = (fs::File::open(a), fs::File::open(b)) else {
+        return false;
+    };
+    let (mut buf_a, mut buf_b) = (vec![0; 64 * 1024], vec![0; 64 * 1024]);
+    loop {
+        let Ok(read) = a.read(&mut buf_a) else {
+            return false;
+        };
+        if read == 0 {
+            return b.read(&mut buf_b[..1]).is_ok_and(|n| n == 0);
+        }
+        if b.read_exact(&mut buf_b[..read]).is_err() || buf_a[..read] != buf_b[..read] {
+            return false;
+        }
+    }
+}
+
+pub(crate) fn different(src: &Path, target: &Path, difference: Difference) -> bool {
+    let (Ok(src_meta), Ok(target_meta)) = (fs::metadata(src), fs::metadata(target)) else {
+        return true;
+    };
+    if src_meta.len() != target_meta.len() {
+        return true;
+    }
+    match difference {
+        Difference::SizeAndTime => modified(src) != modified(target),
+        Difference::Content => !same_content(src, target),
+    }
+}
+
+/// Overwrite the existing file at `target` with `src`?
+fn overwrite(src: &Path, target: &Path, policy: ConflictPolicy) -> bool {
+    match policy {
+        ConflictPolicy::OverwriteIfNewer => match (modified(src), modified(target)) {
+            (Some(src), Some(target)) => src > target,
+            _ => true,
+        },
+        ConflictPolicy::OverwriteIfDifferent(difference) => different(src, target, difference),
+        ConflictPolicy::Overwrite => true,
+        _ => false,
+    }
+}
+
+fn text(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// The same step for `src` with `target` as its new path.
+fn retarget(op: OperationKind, src: &Path, target: &Path) -> Step {
+    let name = target.file_name().map(|name| name.to_string_lossy().into_owned());
+    match op {
+        OperationKind::Rename => Step::Rename { src: text(src), new_name: name.unwrap_or_default() },
+        OperationKind::Copy => Step::Copy { src: text(src), dest: target.parent().map(text).unwrap_or_default(), name },
+        _ => Step::Move { src: text(src), dest: target.parent().map(text).unwrap_or_default(), name },
+    }
+}
+
+struct Resolver {
+    policy: ConflictPolicy,
+    view: View,
+    resolved: Vec<Resolved>,
+}
+
+impl Resolver {
+    fn run(&mut self, step: Step, src: &Path, target: &Path, op: OperationKind) {
+        if op != OperationKind::Copy {
+            self.view.remove(src);
+        }
+        self.view.create(target);
+        self.resolved.push(Resolved::Run(step));
+    }
+
+    /// `src` goes to `target`, which exists. Returns whether everything of
+    /// `src` was taken, so a merged folder can be removed after a move.
+    fn conflict(&mut self, op: OperationKind, src: &Path, target: &Path) -> bool {
+        let (src_dir, target_dir) = (self.view.is_dir(src), self.view.is_dir(target));
+        match self.policy {
+            ConflictPolicy::Fail => {
+                self.resolved.push(Resolved::Failed { op, src: src.to_owned(), target: target.to_owned() });
+                false
+            }
+            ConflictPolicy::KeepBoth(naming) => {
+                let free = keep_both_name(&self.view, target, naming);
+                self.run(retarget(op, src, &free), src, &free, op);
+                true
+            }
+            _ if src_dir && target_dir => {
+                // Merged: each item inside meets the policy on its own.
+                let children = children(&self.view, src, op).unwrap_or_default();
+                let mut all = true;
+                for child in children {
+                    let child_target = target.join(child.file_name().unwrap_or_default());
+                    let child_op = if op == OperationKind::Rename { OperationKind::Move } else { op };
+                    all &= self.item(child_op, &child, &child_target);
+                }
+                if all && op != OperationKind::Copy {
+                    self.resolved.push(Resolved::Emptied { src: src.to_owned() });
+                    self.view.remove(src);
+                }
+                all
+            }
+            policy if overwrite(src, target, policy) => {
+                self.run(retarget(op, src, target), src, target, op);
+                true
+            }
+            _ => {
+                self.resolved.push(Resolved::Skipped { op, src: src.to_owned(), target: target.to_owned() });
+                false
+            }
+        }
+    }
+
+    fn item(&mut self, op: OperationKind, src: &Path, target: &Path) -> bool {
+        if self.view.exists(target) && target != src {
+            return self.conflict(op, src, target);
+        }
+        self.run(retarget(op, src, target), src, target, op);
+        true
+    }
+
+    fn step(&mut self, step: &Step, flags: OperationFlags) {
+        let op = step.kind();
+        let (src, target) = match step {
+            Step::Copy { src, dest, name } | Step::Move { src, dest, name } => {
+                let src = PathBuf::from(src);
+                let name = name.as_deref().map(Path::new).or_else(|| src.file_name().map(Path::new));
+                (src.clone(), name.map(|name| Path::new(dest).join(name)))
+            }
+            Step::Rename { src, new_name } => (PathBuf::from(src), Some(Path::new(src).with_file_name(new_name))),
+            Step::Delete { target } => {
+                self.view.remove(Path::new(target));
+                self.resolved.push(Resolved::Run(step.clone()));
+                return;
+            }
+            Step::NewFolder { root, name } | Step::NewFile { root, name } => {
+                let path = Path::new(root).join(name);
+                let free = match self.view.exists(&path) {
+                    // The collision flag keeps its meaning for new items.
+                    true if flags.contains(OperationFlags::FOF_RENAMEONCOLLISION) => self.view.unique_name(&path),
+                    true => {
+                        self.resolved.push(Resolved::Failed { op, src: path.clone(), target: path });
+                        return;
+                    }
+                    false => path,
+                };
+                let name = free.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+                self.view.create(&free);
+                self.resolved.push(Resolved::Run(match op {
+                    OperationKind::NewFolder => Step::NewFolder { root: root.clone(), name },
+                    _ => Step::NewFile { root: root.clone(), name },
+                }));
+                return;
+            }
+        };
+        match target {
+            // Missing sources and destinations, and copies onto themselves,
+            // are left to the backend.
+            Some(target) if self.view.exists(&src) && self.view.is_dir(target.parent().unwrap_or(&target)) && target != src => {
+                self.item(op, &src, &target);
+            }
+            _ => self.resolved.push(Resolved::Run(step.clone())),
+        }
+    }
+}
+
+/// Decide every conflict of `steps` with `policy`, in order: what one step
+/// creates or removes is seen by the next.
+pub(crate) fn resolve(steps: &[Step], policy: ConflictPolicy, flags: Option<OperationFlags>) -> Vec<Resolved> {
+    let flags = flags.unwrap_or_default();
+    let mut resolver = Resolver { policy, view: View::default(), resolved: Vec::new() };
+    for step in steps {
+        resolver.step(step, flags);
+    }
+    let mut resolved = resolver.resolved;
+    if flags.contains(OperationFlags::FOFX_EARLYFAILURE) {
+        if let Some(failed) = resolved.iter().position(|resolved| matches!(resolved, Resolved::Failed { .. })) {
+            resolved.truncate(failed + 1);
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::{Error, FileOperation};
+
+    fn setup(name: &str) -> PathBuf {
+        let folder = std::env::current_dir().unwrap().join("test").join(name);
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("src")).unwrap();
+        fs::create_dir_all(folder.join("dest").join("src")).unwrap();
+        fs::write(folder.join("src").join("a"), "new").unwrap();
+        fs::write(folder.join("src").join("b"), "same").unwrap();
+        fs::write(folder.join("src").join("c"), "only").unwrap();
+        fs::write(folder.join("dest").join("src").join("a"), "old").unwrap();
+        fs::write(folder.join("dest").join("src").join("b"), "same").unwrap();
+        let past = SystemTime::now() - Duration::from_secs(3600);
+        let file = fs::File::options().write(true).open(folder.join("dest").join("src").join("a")).unwrap();
+        file.set_modified(past).unwrap();
+        folder
+    }
+
+    fn copy(folder: &Path, policy: ConflictPolicy) -> crate::OperationReport {
+        FileOperation::new(None)
+            .on_conflict(policy)
+            .copy(folder.join("src").to_str().unwrap(), folder.join("dest").to_str().unwrap())
+            .perform()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_conflict_policies() {
+        assert_eq!("Keep-Both".parse(), Ok(ConflictPolicy::KeepBoth(KeepBothNaming::Numbered)));
+        assert_eq!(ConflictPolicy::OverwriteIfDifferent(Difference::Content).to_string(), "overwrite-if-different-content");
+        assert!("newest".parse::<ConflictPolicy>().is_err());
+
+        // Merged file by file: the newer `a` replaces the old one, `b` is
+        // skipped as the same, `c` is new.
+        let folder = setup("test_conflict_merge");
+        let dest = folder.join("dest").join("src");
+        let report = copy(&folder, ConflictPolicy::OverwriteIfDifferent(Difference::Content));
+        assert_eq!(report.items.len(), 3);
+        assert_eq!(report.items[1].result_path, None);
+        assert_eq!(fs::read_to_string(dest.join("a")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(dest.join("c")).unwrap(), "only");
+        fs::remove_dir_all(&folder).unwrap();
+
+        let folder = setup("test_conflict_skip");
+        let report = copy(&folder, ConflictPolicy::Skip);
+        assert!(report.is_ok());
+        assert_eq!(fs::read_to_string(folder.join("dest").join("src").join("a")).unwrap(), "old");
+        assert!(folder.join("dest").join("src").join("c").exists());
+        fs::remove_dir_all(&folder).unwrap();
+
+        let folder = setup("test_conflict_keep_both");
+        copy(&folder, ConflictPolicy::KeepBoth(KeepBothNaming::Numbered));
+        assert_eq!(fs::read_to_string(folder.join("dest").join("src (2)").join("a")).unwrap(), "new");
+        let dated = format!("a_{}", civil_date(SystemTime::now()));
+        FileOperation::new(None)
+            .on_conflict(ConflictPolicy::KeepBoth(KeepBothNaming::Dated))
+            .copy(folder.join("src").join("a").to_str().unwrap(), folder.join("dest").join("src").to_str().unwrap())
+            .perform()
+            .unwrap();
+        assert!(folder.join("dest").join("src").join(dated).exists());
+        fs::remove_dir_all(&folder).unwrap();
+
+        // Moves leave the skipped items behind; a failure is a conflict.
+        let folder = setup("test_conflict_move");
+        let report = FileOperation::new(None)
+            .on_conflict(ConflictPolicy::Fail)
+            .move_to(folder.join("src").join("a").to_str().unwrap(), folder.join("dest").join("src").to_str().unwrap())
+            .move_to(folder.join("src").join("c").to_str().unwrap(), folder.join("dest").join("src").to_str().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(report.items[0].result, Err(Error::Conflict { path: folder.join("dest").join("src").join("a"), op: OperationKind::Move }));
+        assert!(report.items[1].result.is_ok());
+        let report = FileOperation::new(None)
+            .on_conflict(ConflictPolicy::OverwriteIfNewer)
+            .move_to(folder.join("src").to_str().unwrap(), folder.join("dest").to_str().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(report.items.len(), 2);
+        assert!(!folder.join("src").join("a").exists());
+        assert!(folder.join("src").join("b").exists());
+        fs::remove_dir_all(&folder).unwrap();
+
+        // A merged folder is only removed once empty: moves that fail when
+        // they run leave it, and what is in it, where it is.
+        let folder = setup("test_conflict_move_failed");
+        let steps = [Step::Move { src: text(&folder.join("src")), dest: text(&folder.join("dest")), name: None }];
+        let resolved = resolve(&steps, ConflictPolicy::Overwrite, None);
+        assert!(matches!(resolved.last(), Some(Resolved::Emptied { .. })));
+        fs::remove_dir_all(folder.join("dest").join("src")).unwrap();
+        let run: Vec<Step> = resolved.iter()
+            .filter_map(|resolved| match resolved {
+                Resolved::Run(step) => Some(step.clone()),
+                _ => None,
+            })
+            .collect();
+        let performed = crate::imp::perform(&run, flags(Some(OperationFlags::FOF_ALLOWUNDO)), &crate::CopyOptions::default(), &mut ()).unwrap();
+        let report = crate::operation::merge(&resolved, performed);
+        assert_eq!(report.items.len(), 3);
+        assert!(report.items.iter().all(|item| item.op == OperationKind::Move && item.result.is_err()));
+        assert_eq!(fs::read_to_string(folder.join("src").join("a")).unwrap(), "new");
+        assert_eq!(fs::read_dir(folder.join("src")).unwrap().count(), 3);
+
+        // Once everything moved, it goes.
+        fs::create_dir_all(folder.join("dest").join("src")).unwrap();
+        let report = FileOperation::new(None)
+            .on_conflict(ConflictPolicy::Overwrite)
+            .move_to(folder.join("src").to_str().unwrap(), folder.join("dest").to_str().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(report.items.last().map(|item| item.op), Some(OperationKind::Delete));
+        assert!(report.is_ok() && !folder.join("src").exists());
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,126 @@
+//! How the `std::fs` backend copies: [`crate::copy_file`] and
+//! [`crate::copy_files`] use the defaults, [`crate::copy_files_with`] and
+//! [`crate::FileOperation::copy_options`] take others.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Whether a copy on Linux may share the blocks of its source, with a
+/// reflink clone on file systems such as Btrfs and XFS. A clone is instant
+/// and takes no space until one of the files changes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Reflink {
+    /// Clone when the file system can, copy otherwise.
+    #[default]
+    Auto,
+    /// Clone or fail.
+    Always,
+    /// Always copy the bytes.
+    Never,
+}
+
+const REFLINKS: [(&str, Reflink); 3] = [
+    ("auto", Reflink::Auto),
+    ("always-reflink", Reflink::Always),
+    ("never-reflink", Reflink::Never),
+];
+
+/// A name that is not one of the reflink policies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseReflinkError {
+    pub name: String,
+}
+
+impl fmt::Display for ParseReflinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = REFLINKS.iter().map(|(name, _)| *name).collect();
+        write!(f, "unknown reflink policy `{}`, expected one of {}", self.name, names.join(", "))
+    }
+}
+
+impl std::error::Error for ParseReflinkError {}
+
+impl FromStr for Reflink {
+    type Err = ParseReflinkError;
+
+    /// `auto`, `always-reflink` or `never-reflink`; `always` and `never` too.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let name = s.trim();
+        REFLINKS.iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(name) || known.strip_suffix("-reflink").is_some_and(|known| known.eq_ignore_ascii_case(name)))
+            .map(|(_, reflink)| *reflink)
+            .ok_or_else(|| ParseReflinkError { name: s.to_owned() })
+    }
+}
+
+impl fmt::Display for Reflink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = REFLINKS.iter().find(|(_, reflink)| reflink == self).map_or("", |(name, _)| *name);
+        f.write_str(name)
+    }
+}
+
+/// ### How copies are done
+/// With more than one thread, one per CPU by default, the files of a copied
+/// folder are copied by a pool of workers: its folders are created first, in
+/// order, and the files are handed to the workers through a bounded queue.
+/// This pays off for trees of many small files, where each file costs more
+/// in latency than in bytes.
+///
+/// On Linux, file contents are cloned as [`Reflink`] allows, or copied with
+/// `copy_file_range`, keeping the holes of sparse files.
+///
+/// On Windows, `IFileOperation` does the copy and the options do not apply.
+/// ```rust
+/// // One file after the other.
+/// let options = ifop::CopyOptions::new().threads(1);
+/// ifop::copy_files_with(vec!["c:\\src\\node_modules"], "d:\\cache", &options, None).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CopyOptions {
+    pub(crate) threads: usize,
+    pub(crate) queue: usize,
+    pub(crate) reflink: Reflink,
+}
+
+impl Default for CopyOptions {
+    /// One worker per CPU, files cloned when they can be.
+    fn default() -> Self {
+        CopyOptions { threads: 0, queue: 1024, reflink: Reflink::Auto }
+    }
+}
+
+impl CopyOptions {
+    pub fn new() -> Self {
+        CopyOptions::default()
+    }
+
+    /// Copy the files of a folder with `threads` workers, `0` for one per
+    /// CPU as by default, `1` for one file after the other.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// How many files may wait for a worker, 1024 by default. The walk of the
+    /// source waits while the queue is full.
+    pub fn queue(mut self, capacity: usize) -> Self {
+        self.queue = capacity.max(1);
+        self
+    }
+
+    /// Whether files may be reflink clones of their source, see [`Reflink`].
+    pub fn reflink(mut self, reflink: Reflink) -> Self {
+        self.reflink = reflink;
+        self
+    }
+
+    /// The number of workers to start.
+    #[cfg(not(windows))]
+    pub(crate) fn workers(&self) -> usize {
+        match self.threads {
+            0 => std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get),
+            threads => threads,
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use crate::OperationKind;
+
+/// Error of an operation, with the item and the kind of step that failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The path could not be parsed or names something that cannot be used here.
+    InvalidPath { path: PathBuf, op: OperationKind },
+    NotFound { path: PathBuf, op: OperationKind },
+    AccessDenied { path: PathBuf, op: OperationKind },
+    /// Another process holds the item open or locked.
+    InUse { path: PathBuf, op: OperationKind },
+    /// The user cancelled, from the progress dialog or a conflict prompt.
+    Aborted { path: Option<PathBuf>, op: Option<OperationKind> },
+    /// The target already exists and the flags do not allow replacing it.
+    Conflict { path: PathBuf, op: OperationKind },
+    /// The target's checksum differs from the source's after the copy.
+    Mismatch { path: PathBuf, op: OperationKind },
+    /// The item was modified since the journal recorded it, undo leaves it alone.
+    Changed { path: PathBuf },
+    /// The operation merged into or replaced what was already there, undo
+    /// cannot take it apart.
+    Irreversible { path: PathBuf },
+    /// A source pattern that does not parse or matches nothing.
+    Pattern { pattern: PathBuf, message: String },
+    /// Anything else reported by the OS: `HRESULT` on Windows, `errno` elsewhere.
+    Platform { path: Option<PathBuf>, op: Option<OperationKind>, code: i32, message: String },
+}
+
+/// Result type of every ifop function.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// The item the error is about, if any.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::InvalidPath { path, .. }
+            | Error::NotFound { path, .. }
+            | Error::AccessDenied { path, .. }
+            | Error::InUse { path, .. }
+            | Error::Conflict { path, .. }
+            | Error::Mismatch { path, .. }
+            | Error::Changed { path }
+            | Error::Irreversible { path }
+            | Error::Pattern { pattern: path, .. } => Some(path),
+            Error::Aborted { path, .. } | Error::Platform { path, .. } => path.as_deref(),
+        }
+    }
+
+    /// The kind of step that failed, if the failure belongs to one.
+    pub fn operation(&self) -> Option<OperationKind> {
+        match self {
+            Error::InvalidPath { op, .. }
+            | Error::NotFound { op, .. }
+            | Error::AccessDenied { op, .. }
+            | Error::InUse { op, .. }
+            | Error::Conflict { op, .. }
+            | Error::Mismatch { op, .. } => Some(*op),
+            Error::Aborted { op, .. } | Error::Platform { op, .. } => *op,
+            Error::Changed { .. } | Error::Irreversible { .. } | Error::Pattern { .. } => None,
+        }
+    }
+
+    pub(crate) fn from_io(error: std::io::Error, path: &Path, op: OperationKind) -> Self {
+        use std::io::ErrorKind;
+
+        let path = path.to_owned();
+        match error.kind() {
+            ErrorKind::InvalidInput | ErrorKind::InvalidFilename => Error::InvalidPath { path, op },
+            ErrorKind::NotFound => Error::NotFound { path, op },
+            ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem => Error::AccessDenied { path, op },
+            ErrorKind::ResourceBusy | ErrorKind::ExecutableFileBusy => Error::InUse { path, op },
+            ErrorKind::AlreadyExists | ErrorKind::DirectoryNotEmpty => Error::Conflict { path, op },
+            _ => Error::Platform {
+                path: Some(path),
+                op: Some(op),
+                code: error.raw_os_error().unwrap_or(0),
+                message: error.to_string(),
+            },
+        }
+    }
+
+    #[cfg(windows)]
+    pub(crate) fn from_windows(error: &windows_core::Error, path: Option<&Path>, op: Option<OperationKind>) -> Self {
+        // HRESULT_FROM_WIN32 values and copy engine codes from winerror.h.
+        const NOT_FOUND: &[u32] = &[0x8007_0002, 0x8007_0003, 0x8007_000F];
+        const ACCESS_DENIED: &[u32] = &[0x8007_0005, 0x8007_0013];
+        const IN_USE: &[u32] = &[0x8007_0020, 0x8007_0021];
+        const ABORTED: &[u32] = &[0x8007_04C7, 0x8027_0000];
+        const CONFLICT: &[u32] = &[0x8007_0050, 0x8007_00B7];
+        const INVALID_PATH: &[u32] = &[0x8007_0057, 0x8007_007B, 0x8007_00A1, 0x8007_00CE];
+
+        let code = error.code().0 as u32;
+        let path = path.map(Path::to_owned);
+        match (path, op) {
+            (path, op) if ABORTED.contains(&code) => Error::Aborted { path, op },
+            (Some(path), Some(op)) if NOT_FOUND.contains(&code) => Error::NotFound { path, op },
+            (Some(path), Some(op)) if ACCESS_DENIED.contains(&code) => Error::AccessDenied { path, op },
+            (Some(path), Some(op)) if IN_USE.contains(&code) => Error::InUse { path, op },
+            (Some(path), Some(op)) if CONFLICT.contains(&code) => Error::Conflict { path, op },
+            (Some(path), Some(op)) if INVALID_PATH.contains(&code) => Error::InvalidPath { path, op },
+            (path, op) => Error::Platform { path, op, code: error.code().0, message: error.message().to_string() },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(op) = self.operation() {
+            write!(f, "{} ", op)?;
+        }
+        if let Some(path) = self.path() {
+            write!(f, "{}: ", path.display())?;
+        }
+        match self {
+            Error::InvalidPath { .. } => f.write_str("invalid path"),
+            Error::NotFound { .. } => f.write_str("not found"),
+            Error::AccessDenied { .. } => f.write_str("access denied"),
+            Error::InUse { .. } => f.write_str("in use by another process"),
+            Error::Aborted { .. } => f.write_str("aborted by user"),
+            Error::Conflict { .. } => f.write_str("target already exists"),
+            Error::Mismatch { .. } => f.write_str("checksum mismatch"),
+            Error::Changed { .. } => f.write_str("changed since the operation"),
+            Error::Irreversible { .. } => f.write_str("merged into what was there before, cannot be undone"),
+            Error::Pattern { message, .. } => f.write_str(message),
+            Error::Platform { code, message, .. } => write!(f, "{} ({:#x})", message.trim_end(), code),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, ErrorKind};
+
+    #[test]
+    fn test_from_io() {
+        let path = Path::new("file");
+        assert_eq!(
+            Error::from_io(io::Error::from(ErrorKind::NotFound), path, OperationKind::Copy),
+            Error::NotFound { path: path.to_owned(), op: OperationKind::Copy }
+        );
+        // A system call cut short by a signal, not the user declining anything.
+        let interrupted = Error::from_io(io::Error::from_raw_os_error(4), path, OperationKind::Copy);
+        assert!(matches!(interrupted, Error::Platform { code: 4, op: Some(OperationKind::Copy), .. }), "{:?}", interrupted);
+    }
+}
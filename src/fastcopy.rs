@@ -0,0 +1,167 @@
+//! File content copies on Linux: a reflink clone (`FICLONE`) when the file
+//! system shares blocks, else `copy_file_range`, else a read/write loop.
+//! Holes found with `SEEK_DATA` / `SEEK_HOLE` are left as holes.
+
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use crate::Reflink;
+
+fn check(result: libc::c_int) -> Result<libc::c_int> {
+    match result {
+        -1 => Err(Error::last_os_error()),
+        result => Ok(result),
+    }
+}
+
+/// `true` when the call is not there for these files, as opposed to failing.
+fn unsupported(e: &Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::EXDEV | libc::ENOSYS | libc::EOPNOTSUPP | libc::EINVAL | libc::ENOTTY | libc::EPERM))
+}
+
+fn clone(reader: &fs::File, writer: &fs::File) -> Result<()> {
+    check(unsafe { libc::ioctl(writer.as_raw_fd(), libc::FICLONE, reader.as_raw_fd()) }).map(|_| ())
+}
+
+/// The next `(start, end)` of data from `offset`, `None` past the last one.
+/// A file system without `SEEK_DATA` has all of it as data.
+fn next_data(reader: &fs::File, offset: u64, len: u64) -> Result<Option<(u64, u64)>> {
+    let seek = |whence| unsafe { libc::lseek(reader.as_raw_fd(), offset as libc::off_t, whence) };
+    let start = match seek(libc::SEEK_DATA) {
+        -1 => match Error::last_os_error() {
+            e if e.raw_os_error() == Some(libc::ENXIO) => return Ok(None),
+            e if unsupported(&e) => return Ok((offset < len).then_some((offset, len))),
+            e => return Err(e),
+        },
+        start => start as u64,
+    };
+    let end = match unsafe { libc::lseek(reader.as_raw_fd(), start as libc::off_t, libc::SEEK_HOLE) } {
+        -1 => len,
+        end => (end as u64).min(len),
+    };
+    Ok((start < end).then_some((start, end)))
+}
+
+/// Copy `start..end` with `copy_file_range`, or with reads and writes once
+/// it turns out not to work for these files.
+fn copy_range(reader: &fs::File, writer: &fs::File, start: u64, end: u64, kernel: &mut bool) -> Result<u64> {
+    let mut offset = start;
+    while *kernel && offset < end {
+        let (mut from, mut to) = (offset as libc::loff_t, offset as libc::loff_t);
+        let chunk = (end - offset).min(1 << 30) as usize;
+        match unsafe { libc::copy_file_range(reader.as_raw_fd(), &mut from, writer.as_raw_fd(), &mut to, chunk, 0) } {
+            -1 => match Error::last_os_error() {
+                e if offset == start && unsupported(&e) => *kernel = false,
+                e => return Err(e),
+            },
+            // The source got shorter.
+            0 => return Ok(offset - start),
+            copied => offset += copied as u64,
+        }
+    }
+    let mut buf = vec![0; 1024 * 1024];
+    while offset < end {
+        let len = (end - offset).min(buf.len() as u64) as usize;
+        let read = reader.read_at(&mut buf[..len], offset)?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all_at(&buf[..read], offset)?;
+        offset += read as u64;
+    }
+    Ok(offset - start)
+}
+
+/// [`fs::copy`] with a reflink clone first, as `reflink` allows, and holes
+/// kept. Returns the number of bytes.
+pub(crate) fn copy(src: &Path, target: &Path, reflink: Reflink) -> Result<u64> {
+    let reader = fs::File::open(src)?;
+    let meta = reader.metadata()?;
+    if !meta.is_file() {
+        return fs::copy(src, target);
+    }
+    let writer = fs::OpenOptions::new().write(true).create(true).truncate(true).open(target)?;
+    let len = meta.len();
+
+    let cloned = match reflink {
+        Reflink::Never => false,
+        Reflink::Auto => clone(&reader, &writer).is_ok(),
+        Reflink::Always => {
+            if let Err(e) = clone(&reader, &writer) {
+                // No empty file left behind.
+                drop(writer);
+                let _ = fs::remove_file(target);
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    format!("{}: no reflink to {}: {}", src.display(), target.display(), e)
+                ));
+            }
+            true
+        }
+    };
+    if !cloned {
+        // Fewer blocks than bytes: there are holes to keep.
+        let sparse = meta.blocks() * 512 < len;
+        let mut kernel = true;
+        let mut offset = 0;
+        while offset < len {
+            let Some((start, end)) = (if sparse { next_data(&reader, offset, len)? } else { Some((offset, len)) }) else {
+                break;
+            };
+            let copied = copy_range(&reader, &writer, start, end, &mut kernel)?;
+            if copied < end - start {
+                break;
+            }
+            offset = end;
+        }
+        // A hole at the end is only a length.
+        writer.set_len(len)?;
+    }
+    writer.set_permissions(meta.permissions())?;
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fast_copy() {
+        assert_eq!("never".parse(), Ok(Reflink::Never));
+        assert_eq!("Always-Reflink".parse(), Ok(Reflink::Always));
+        assert!("sometimes".parse::<Reflink>().is_err());
+
+        let folder = std::env::current_dir().unwrap().join("test").join("test_fast_copy");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+
+        // 8 MiB with data at 1 MiB and 5 MiB, holes around.
+        let sparse = folder.join("sparse");
+        let file = fs::File::create(&sparse).unwrap();
+        file.write_all_at(&[1; 4096], 1 << 20).unwrap();
+        file.write_all_at(&[2; 4096], 5 << 20).unwrap();
+        file.set_len(8 << 20).unwrap();
+        drop(file);
+
+        for reflink in [Reflink::Auto, Reflink::Never] {
+            let target = folder.join(format!("copy-{}", reflink));
+            assert_eq!(copy(&sparse, &target, reflink).unwrap(), 8 << 20);
+            assert_eq!(fs::read(&target).unwrap(), fs::read(&sparse).unwrap());
+            let (src_meta, target_meta) = (fs::metadata(&sparse).unwrap(), fs::metadata(&target).unwrap());
+            assert!(target_meta.blocks() <= src_meta.blocks().max(64));
+        }
+
+        fs::write(folder.join("small"), "content").unwrap();
+        match copy(&folder.join("small"), &folder.join("cloned"), Reflink::Always) {
+            Ok(len) => assert_eq!(len, 7),
+            Err(e) => {
+                assert_eq!(e.kind(), ErrorKind::Unsupported);
+                assert!(!folder.join("cloned").exists());
+            }
+        }
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
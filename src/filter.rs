@@ -0,0 +1,448 @@
+//! Filters for the content of copied, moved and deleted folders.
+//!
+//! A filtered folder step is expanded before it runs: folders whose content
+//! all passes stay one step, the others become one step per item that passes,
+//! so the Windows shell and the `std::fs` backend touch the same items.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use glob::{MatchOptions, Pattern};
+use crate::operation::Step;
+use crate::plan::View;
+use crate::{Error, Result};
+
+/// File names that `.ifopignore` files are read from.
+pub const IGNORE_FILE: &str = ".ifopignore";
+
+const MATCH: MatchOptions = MatchOptions {
+    case_sensitive: !cfg!(windows),
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// One line of gitignore syntax.
+#[derive(Clone, Debug)]
+struct Rule {
+    pattern: Pattern,
+    /// `!pattern`: brings back what an earlier rule left out.
+    negated: bool,
+    /// `pattern/`: folders only.
+    dir_only: bool,
+    /// Has a `/` inside: matched against the path from the base folder,
+    /// otherwise against the name at any depth.
+    anchored: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<std::result::Result<Rule, glob::PatternError>> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        Some(Pattern::new(line).map(|pattern| Rule { pattern, negated, dir_only, anchored }))
+    }
+
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            return self.pattern.matches_with(relative, MATCH);
+        }
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        self.pattern.matches_with(name, MATCH)
+    }
+}
+
+/// Rules that apply below `base`.
+#[derive(Clone, Debug)]
+struct Rules {
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    /// `Some(true)` when the last rule that matches `path` leaves it out.
+    fn excludes(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.base).ok()?;
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        self.rules.iter().rev().find(|rule| rule.matches(&relative, is_dir)).map(|rule| !rule.negated)
+    }
+}
+
+fn read_ignore_file(folder: &Path) -> Option<Rules> {
+    let content = fs::read_to_string(folder.join(IGNORE_FILE)).ok()?;
+    // A broken line is skipped, like git does.
+    let rules = content.lines().filter_map(Rule::parse).flatten().collect();
+    Some(Rules { base: folder.to_owned(), rules })
+}
+
+/// ### Which items inside folders an operation touches
+/// Set with [`crate::FileOperation::filter`]. The queued items themselves are
+/// always taken; the filter decides about what is inside the folders among
+/// them. Patterns use gitignore syntax and are relative to the queued folder:
+/// `target/` leaves out every folder named `target`, `/docs/*.md` only the
+/// ones at the top. Sizes, dates, and attributes apply to files, except that
+/// hidden folders are left out with hidden files. Folders left with nothing
+/// to copy or move are not created.
+/// ```rust
+/// let filter = ifop::Filter::new()
+///     .exclude("target/")
+///     .exclude("node_modules/")
+///     .exclude(".git/")
+///     .max_size(100 * 1024 * 1024)
+///     .ignore_files(true);
+///
+/// ifop::FileOperation::new(None)
+///     .filter(filter)
+///     .copy("c:\\src\\project", "c:\\backup")
+///     .perform()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    include: Vec<Rule>,
+    exclude: Vec<Rule>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after: Option<SystemTime>,
+    modified_before: Option<SystemTime>,
+    skip_hidden: bool,
+    skip_read_only: bool,
+    ignore_files: bool,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Filter::default()
+    }
+
+    /// Only files matching one of the include patterns, when there is any.
+    /// Panics on a pattern that does not parse, see [`Filter::try_include`].
+    pub fn include(self, pattern: &str) -> Self {
+        self.try_include(pattern).expect("invalid include pattern")
+    }
+
+    /// Leave out files and folders matching `pattern`. Panics on a pattern
+    /// that does not parse, see [`Filter::try_exclude`].
+    pub fn exclude(self, pattern: &str) -> Self {
+        self.try_exclude(pattern).expect("invalid exclude pattern")
+    }
+
+    /// [`Filter::include`] that fails with [`Error::Pattern`].
+    pub fn try_include(mut self, pattern: &str) -> Result<Self> {
+        self.include.push(rule(pattern)?);
+        Ok(self)
+    }
+
+    /// [`Filter::exclude`] that fails with [`Error::Pattern`].
+    pub fn try_exclude(mut self, pattern: &str) -> Result<Self> {
+        self.exclude.push(rule(pattern)?);
+        Ok(self)
+    }
+
+    /// Files of at least `bytes`.
+    pub fn min_size(mut self, bytes: u64) -> Self {
+        self.min_size = Some(bytes);
+        self
+    }
+
+    /// Files of at most `bytes`.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Files modified at or after `time`.
+    pub fn modified_after(mut self, time: SystemTime) -> Self {
+        self.modified_after = Some(time);
+        self
+    }
+
+    /// Files modified before `time`.
+    pub fn modified_before(mut self, time: SystemTime) -> Self {
+        self.modified_before = Some(time);
+        self
+    }
+
+    /// Leave out hidden files and folders: a name starting with `.`, or the
+    /// hidden attribute on Windows.
+    pub fn skip_hidden(mut self, skip: bool) -> Self {
+        self.skip_hidden = skip;
+        self
+    }
+
+    /// Leave out read-only files.
+    pub fn skip_read_only(mut self, skip: bool) -> Self {
+        self.skip_read_only = skip;
+        self
+    }
+
+    /// Read `.ifopignore` files, gitignore syntax, in every folder on the
+    /// way; their rules apply below the folder they are in.
+    pub fn ignore_files(mut self, read: bool) -> Self {
+        self.ignore_files = read;
+        self
+    }
+
+    fn passes_file(&self, path: &Path, meta: &fs::Metadata, relative: &str) -> bool {
+        if !self.include.is_empty() && !self.include.iter().any(|rule| rule.matches(relative, false)) {
+            return false;
+        }
+        let size = meta.len();
+        if self.min_size.is_some_and(|min| size < min) || self.max_size.is_some_and(|max| size > max) {
+            return false;
+        }
+        let modified = meta.modified().ok();
+        if self.modified_after.is_some_and(|after| modified.is_some_and(|modified| modified < after))
+            || self.modified_before.is_some_and(|before| modified.is_some_and(|modified| modified >= before)) {
+            return false;
+        }
+        if self.skip_read_only && meta.permissions().readonly() {
+            return false;
+        }
+        !(self.skip_hidden && hidden(path, meta))
+    }
+}
+
+fn rule(pattern: &str) -> Result<Rule> {
+    let error = |message: String| Error::Pattern { pattern: PathBuf::from(pattern), message };
+    match Rule::parse(pattern) {
+        Some(rule) => rule.map_err(|e| error(e.to_string())),
+        None => Err(error("empty pattern".to_owned())),
+    }
+}
+
+#[cfg(windows)]
+fn hidden(path: &Path, meta: &fs::Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    meta.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0 || path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+#[cfg(not(windows))]
+fn hidden(path: &Path, _meta: &fs::Metadata) -> bool {
+    path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// What passes the filter inside one folder.
+struct Node {
+    path: PathBuf,
+    files: Vec<PathBuf>,
+    folders: Vec<Node>,
+    /// Nothing inside was left out.
+    complete: bool,
+}
+
+impl Node {
+    fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.folders.is_empty()
+    }
+}
+
+fn walk(filter: &Filter, root: &Path, folder: &Path, rules: &mut Vec<Rules>) -> Node {
+    let pushed = filter.ignore_files && read_ignore_file(folder).map(|found| rules.push(found)).is_some();
+    let mut node = Node { path: folder.to_owned(), files: Vec::new(), folders: Vec::new(), complete: true };
+    let mut entries: Vec<_> = fs::read_dir(folder).map(|entries| entries.flatten().collect()).unwrap_or_default();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let Ok(meta) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        let is_dir = meta.is_dir();
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+        let ignored = rules.iter().rev().find_map(|rules| rules.excludes(&path, is_dir)).unwrap_or(false);
+        let excluded = ignored
+            || filter.exclude.iter().rev().find(|rule| rule.matches(&relative, is_dir)).is_some_and(|rule| !rule.negated)
+            || (filter.skip_hidden && hidden(&path, &meta));
+        if excluded {
+            node.complete = false;
+        } else if is_dir {
+            let child = walk(filter, root, &path, rules);
+            node.complete &= child.complete;
+            // Not created when everything inside was left out.
+            if child.complete || !child.is_empty() {
+                node.folders.push(child);
+            }
+        } else if filter.passes_file(&path, &meta, &relative) {
+            node.files.push(path);
+        } else {
+            node.complete = false;
+        }
+    }
+    if pushed {
+        rules.pop();
+    }
+    node
+}
+
+/// Every file and folder below `root` that passes `filter`, relative to
+/// `root`, with `true` for folders.
+pub(crate) fn entries(filter: &Filter, root: &Path) -> Vec<(PathBuf, bool)> {
+    fn flatten(node: Node, root: &Path, entries: &mut Vec<(PathBuf, bool)>) {
+        let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_owned();
+        entries.extend(node.files.iter().map(|file| (relative(file), false)));
+        for folder in node.folders {
+            entries.push((relative(&folder.path), true));
+            flatten(folder, root, entries);
+        }
+    }
+    let mut entries = Vec::new();
+    flatten(walk(filter, root, root, &mut Vec::new()), root, &mut entries);
+    entries
+}
+
+fn text(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+fn name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+struct Expander {
+    view: View,
+    steps: Vec<Step>,
+}
+
+impl Expander {
+    /// `node` copied or moved to exactly `target`.
+    fn transfer(&mut self, node: &Node, target: &Path, moving: bool) {
+        let step = |src: &Path, target: &Path| {
+            let (src, dest, name) = (text(src), target.parent().map(text).unwrap_or_default(), Some(name(target)));
+            if moving { Step::Move { src, dest, name } } else { Step::Copy { src, dest, name } }
+        };
+        if node.complete && !self.view.exists(target) {
+            self.steps.push(step(&node.path, target));
+            self.view.create(target);
+            return;
+        }
+        if !self.view.is_dir(target) {
+            self.steps.push(Step::NewFolder { root: target.parent().map(text).unwrap_or_default(), name: name(target) });
+            self.view.create(target);
+        }
+        for file in &node.files {
+            let file_target = target.join(name(file));
+            self.steps.push(step(file, &file_target));
+            self.view.create(&file_target);
+        }
+        for folder in &node.folders {
+            self.transfer(folder, &target.join(name(&folder.path)), moving);
+        }
+    }
+
+    fn delete(&mut self, node: &Node) {
+        if node.complete {
+            self.steps.push(Step::Delete { target: text(&node.path) });
+            return;
+        }
+        for file in &node.files {
+            self.steps.push(Step::Delete { target: text(file) });
+        }
+        for folder in &node.folders {
+            self.delete(folder);
+        }
+    }
+}
+
+/// The steps with their folders expanded to what passes `filter`.
+pub(crate) fn expand(steps: &[Step], filter: &Filter) -> Vec<Step> {
+    let mut expander = Expander { view: View::default(), steps: Vec::new() };
+    for step in steps {
+        let folder = match step {
+            Step::Copy { src, .. } | Step::Move { src, .. } | Step::Delete { target: src } => Path::new(src),
+            _ => Path::new(""),
+        };
+        if !folder.is_dir() || fs::symlink_metadata(folder).is_ok_and(|meta| meta.file_type().is_symlink()) {
+            expander.steps.push(step.clone());
+            continue;
+        }
+        let node = walk(filter, folder, folder, &mut Vec::new());
+        match step {
+            Step::Copy { dest, name: new_name, .. } | Step::Move { dest, name: new_name, .. } => {
+                let target = Path::new(dest).join(new_name.clone().unwrap_or_else(|| name(folder)));
+                if node.complete || target == folder {
+                    // Left as it is: the backend handles collisions and copies onto itself.
+                    expander.steps.push(step.clone());
+                    expander.view.create(&target);
+                } else {
+                    expander.transfer(&node, &target, matches!(step, Step::Move { .. }));
+                }
+            }
+            _ => expander.delete(&node),
+        }
+    }
+    expander.steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileOperation;
+
+    #[test]
+    fn test_filter() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_filter");
+        let _ = fs::remove_dir_all(&folder);
+        let project = folder.join("project");
+        for dir in ["src/target", "target/debug", ".git", "docs"] {
+            fs::create_dir_all(project.join(dir)).unwrap();
+        }
+        for (file, content) in [
+            ("src/main.rs", "fn main() {}"),
+            ("src/target/keep.rs", ""),
+            ("src/big.bin", "0123456789"),
+            ("target/debug/app", ""),
+            (".git/HEAD", ""),
+            ("docs/a.md", ""),
+            ("docs/b.tmp", ""),
+            (".ifopignore", "# build output\n/target/\n*.tmp\n"),
+        ] {
+            fs::write(project.join(file), content).unwrap();
+        }
+        fs::create_dir_all(folder.join("backup")).unwrap();
+
+        let filter = Filter::new().exclude(".git/").max_size(5).ignore_files(true);
+        let report = FileOperation::new(None)
+            .filter(filter)
+            .copy(project.to_str().unwrap(), folder.join("backup").to_str().unwrap())
+            .perform()
+            .unwrap();
+        assert!(report.is_ok());
+        let backup = folder.join("backup").join("project");
+        // `/target/` is anchored to the folder of the ignore file.
+        assert!(backup.join("src").join("target").join("keep.rs").exists());
+        assert!(!backup.join("target").exists());
+        assert!(!backup.join(".git").exists());
+        assert!(!backup.join("src").join("big.bin").exists());
+        assert!(!backup.join("docs").join("b.tmp").exists());
+        assert!(backup.join("docs").join("a.md").exists());
+
+        // Deletes keep what is left out, folders included.
+        let report = FileOperation::new(None)
+            .filter(Filter::new().include("*.rs"))
+            .delete(project.to_str().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(report.items.len(), 2);
+        assert!(!project.join("src").join("main.rs").exists());
+        assert!(project.join("src").join("big.bin").exists());
+        assert!(project.join(".git").join("HEAD").exists());
+
+        assert!(Filter::new().try_exclude("[").is_err());
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
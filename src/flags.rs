@@ -0,0 +1,159 @@
+//! Operation flags, the same bits as `IFileOperation::SetOperationFlags` takes.
+//!
+//! On Windows the value is passed to the shell unchanged. The `std::fs` backend
+//! gives the following flags a meaning and ignores the ones that only affect
+//! Explorer's UI:
+//!
+//! | Flag                        | `std::fs` backend                                            |
+//! | --------------------------- | ------------------------------------------------------------ |
+//! | `FOF_ALLOWUNDO`             | delete moves items to the FreeDesktop Trash                  |
+//! | `FOF_NOCONFIRMATION`        | replace existing files, merge existing folders               |
+//! | `FOF_RENAMEONCOLLISION`     | give the new item a free name, `name (2).ext`                |
+//! | `FOF_NORECURSION`           | copying a folder takes its files but not its sub folders     |
+//! | `FOF_NOCOPYSECURITYATTRIBS` | copies get default permissions instead of the source's       |
+//! | `FOFX_KEEPNEWERFILE`        | an existing file newer than the source is kept               |
+//! | `FOFX_PREFERHARDLINK`       | files are hard linked instead of copied when possible        |
+//! | `FOFX_RECYCLEONDELETE`      | same as `FOF_ALLOWUNDO` for deletes                          |
+//! | `FOFX_EARLYFAILURE`         | the first failed step ends the batch                         |
+//!
+//! With a [`crate::ConflictPolicy`] on both backends, `FOF_NOCONFIRMATION`,
+//! `FOF_RENAMEONCOLLISION` and `FOFX_KEEPNEWERFILE` no longer decide conflicts.
+
+use std::fmt;
+use std::str::FromStr;
+
+bitflags::bitflags! {
+    /// `FOF_*` and `FOFX_*` flags, written and parsed as `FOF_ALLOWUNDO|FOF_NOCONFIRMATION`.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct OperationFlags: u32 {
+        const FOF_MULTIDESTFILES = 0x0001;
+        const FOF_CONFIRMMOUSE = 0x0002;
+        const FOF_SILENT = 0x0004;
+        const FOF_RENAMEONCOLLISION = 0x0008;
+        const FOF_NOCONFIRMATION = 0x0010;
+        const FOF_WANTMAPPINGHANDLE = 0x0020;
+        const FOF_ALLOWUNDO = 0x0040;
+        const FOF_FILESONLY = 0x0080;
+        const FOF_SIMPLEPROGRESS = 0x0100;
+        const FOF_NOCONFIRMMKDIR = 0x0200;
+        const FOF_NOERRORUI = 0x0400;
+        const FOF_NOCOPYSECURITYATTRIBS = 0x0800;
+        const FOF_NORECURSION = 0x1000;
+        const FOF_NO_CONNECTED_ELEMENTS = 0x2000;
+        const FOF_WANTNUKEWARNING = 0x4000;
+        const FOF_NORECURSEREPARSE = 0x8000;
+        const FOFX_NOSKIPJUNCTIONS = 0x0001_0000;
+        const FOFX_PREFERHARDLINK = 0x0002_0000;
+        const FOFX_SHOWELEVATIONPROMPT = 0x0004_0000;
+        const FOFX_RECYCLEONDELETE = 0x0008_0000;
+        const FOFX_EARLYFAILURE = 0x0010_0000;
+        const FOFX_PRESERVEFILEEXTENSIONS = 0x0020_0000;
+        const FOFX_KEEPNEWERFILE = 0x0040_0000;
+        const FOFX_NOCOPYHOOKS = 0x0080_0000;
+        const FOFX_NOMINIMIZEBOX = 0x0100_0000;
+        const FOFX_MOVEACLSACROSSVOLUMES = 0x0200_0000;
+        const FOFX_DONTDISPLAYSOURCEPATH = 0x0400_0000;
+        const FOFX_DONTDISPLAYDESTPATH = 0x0800_0000;
+        const FOFX_REQUIREELEVATION = 0x1000_0000;
+        const FOFX_ADDUNDORECORD = 0x2000_0000;
+        const FOFX_COPYASDOWNLOAD = 0x4000_0000;
+        const FOFX_DONTDISPLAYLOCATIONS = 0x8000_0000;
+        /// `FOF_SILENT|FOF_NOCONFIRMATION|FOF_NOERRORUI|FOF_NOCONFIRMMKDIR`
+        const FOF_NO_UI = Self::FOF_SILENT.bits()
+            | Self::FOF_NOCONFIRMATION.bits()
+            | Self::FOF_NOERRORUI.bits()
+            | Self::FOF_NOCONFIRMMKDIR.bits();
+    }
+}
+
+/// A name in a flags string that is not a known `FOF_*`/`FOFX_*` flag.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseFlagsError {
+    pub name: String,
+}
+
+impl fmt::Display for ParseFlagsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown operation flag `{}`", self.name)
+    }
+}
+
+impl std::error::Error for ParseFlagsError {}
+
+impl FromStr for OperationFlags {
+    type Err = ParseFlagsError;
+
+    /// `FOF_ALLOWUNDO|FOF_NOCONFIRMATION`, spaces around `|` allowed. Raw values
+    /// such as `0x40` are accepted too.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = OperationFlags::empty();
+
+        for name in s.split('|').map(str::trim) {
+            if name.is_empty() {
+                continue;
+            }
+            let flag = match name.strip_prefix("0x").or_else(|| name.strip_prefix("0X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().map(OperationFlags::from_bits_retain),
+                None => OperationFlags::from_name(name),
+            };
+            match flag {
+                Some(flag) => flags |= flag,
+                None => return Err(ParseFlagsError { name: name.to_owned() }),
+            }
+        }
+
+        Ok(flags)
+    }
+}
+
+impl fmt::Display for OperationFlags {
+    /// Every bit has a name, so the output always parses back.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, _)) in self.iter_names().enumerate() {
+            if i > 0 {
+                f.write_str("|")?;
+            }
+            f.write_str(name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl From<OperationFlags> for windows::Win32::UI::Shell::FILEOPERATION_FLAGS {
+    fn from(flags: OperationFlags) -> Self {
+        windows::Win32::UI::Shell::FILEOPERATION_FLAGS(flags.bits())
+    }
+}
+
+#[cfg(windows)]
+impl From<windows::Win32::UI::Shell::FILEOPERATION_FLAGS> for OperationFlags {
+    fn from(flags: windows::Win32::UI::Shell::FILEOPERATION_FLAGS) -> Self {
+        OperationFlags::from_bits_retain(flags.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_flags() {
+        let flags: OperationFlags = "FOF_ALLOWUNDO|FOF_NOCONFIRMATION | FOFX_KEEPNEWERFILE".parse().unwrap();
+        assert_eq!(flags, OperationFlags::FOF_ALLOWUNDO | OperationFlags::FOF_NOCONFIRMATION | OperationFlags::FOFX_KEEPNEWERFILE);
+        assert_eq!("0x40".parse::<OperationFlags>().unwrap(), OperationFlags::FOF_ALLOWUNDO);
+        assert_eq!("".parse::<OperationFlags>().unwrap(), OperationFlags::empty());
+        assert_eq!(
+            "FOF_ALLOWUNDO|FOF_ALOWUNDO".parse::<OperationFlags>(),
+            Err(ParseFlagsError { name: "FOF_ALOWUNDO".to_owned() })
+        );
+    }
+
+    #[test]
+    fn test_display_flags() {
+        let flags = OperationFlags::FOF_ALLOWUNDO | OperationFlags::FOF_NORECURSION;
+        assert_eq!(flags.to_string(), "FOF_ALLOWUNDO|FOF_NORECURSION");
+        assert_eq!(flags.to_string().parse::<OperationFlags>().unwrap(), flags);
+        assert_eq!(OperationFlags::FOF_NO_UI.to_string().parse::<OperationFlags>().unwrap(), OperationFlags::FOF_NO_UI);
+    }
+}
@@ -0,0 +1,855 @@
+//! Journal of completed operations, so they can be undone and redone after the fact.
+//!
+//! One JSON object per line in `journal.jsonl`, under `%LOCALAPPDATA%\ifop` on
+//! Windows and `$XDG_STATE_HOME/ifop` (`~/.local/state/ifop`) elsewhere, or
+//! wherever `IFOP_JOURNAL` points. Recording is off until [`enable_journal`].
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::operation::Step;
+use crate::{imp, CopyOptions, Error, OperationFlags, OperationKind, OperationReport, OperationStatus, Result};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// ### Record every completed operation in the journal
+/// Applies to the whole process. The `ifop` command turns it on.
+/// ```rust
+/// ifop::enable_journal(true);
+/// ifop::move_file("c:\\src\\file.txt", "c:\\dest", None).unwrap();
+/// ifop::undo_last().unwrap();
+/// ```
+pub fn enable_journal(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// What an item looked like right after the operation: undo refuses to touch
+/// it when this no longer matches.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub dir: bool,
+    /// Bytes of file content, folders included.
+    pub size: u64,
+    /// Number of files, folders included.
+    pub files: u64,
+    /// Latest modification time of a file, in nanoseconds since 1970.
+    pub modified: u64,
+}
+
+fn fingerprint(path: &Path) -> Option<Fingerprint> {
+    let meta = fs::symlink_metadata(path).ok()?;
+    let modified = |meta: &fs::Metadata| {
+        meta.modified().ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |time| time.as_nanos() as u64)
+    };
+    if !meta.is_dir() {
+        return Some(Fingerprint { dir: false, size: meta.len(), files: 1, modified: modified(&meta) });
+    }
+    let mut print = Fingerprint { dir: true, ..Fingerprint::default() };
+    for entry in fs::read_dir(path).ok()?.flatten() {
+        let child = fingerprint(&entry.path())?;
+        print.size += child.size;
+        print.files += child.files;
+        print.modified = print.modified.max(child.modified);
+    }
+    Some(print)
+}
+
+/// `path` joined with `relative`, without the trailing separator `join`
+/// adds for an empty one.
+fn inside(path: &Path, relative: &Path) -> PathBuf {
+    match relative.as_os_str().is_empty() {
+        true => path.to_owned(),
+        false => path.join(relative),
+    }
+}
+
+/// Every item inside `path`, `path` itself as the empty path, with the
+/// fingerprints of files. Folders count as empty folders.
+fn items(path: &Path) -> HashMap<PathBuf, Fingerprint> {
+    let mut items = HashMap::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let full = inside(path, &relative);
+        let Ok(meta) = fs::symlink_metadata(&full) else {
+            continue;
+        };
+        if meta.is_dir() {
+            if let Ok(entries) = fs::read_dir(&full) {
+                pending.extend(entries.flatten().map(|entry| relative.join(entry.file_name())));
+            }
+            items.insert(relative, Fingerprint { dir: true, ..Fingerprint::default() });
+        } else {
+            items.insert(relative, fingerprint(&full).unwrap_or_default());
+        }
+    }
+    items
+}
+
+/// What an operation did to a target that was there before it ran.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Merged {
+    /// Items the operation added inside the target, outermost first; their
+    /// contents are not listed.
+    pub created: Vec<PathBuf>,
+    /// Items that were there got replaced, undo cannot bring them back.
+    pub replaced: bool,
+}
+
+/// Compare `result` with what was there before, as [`items`] saw it.
+fn merged(result: &Path, before: &HashMap<PathBuf, Fingerprint>) -> Merged {
+    let mut merged = Merged::default();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let full = inside(result, &relative);
+        let Ok(meta) = fs::symlink_metadata(&full) else {
+            continue;
+        };
+        match before.get(&relative) {
+            None => merged.created.push(full),
+            Some(old) if old.dir && meta.is_dir() => {
+                if let Ok(entries) = fs::read_dir(&full) {
+                    pending.extend(entries.flatten().map(|entry| relative.join(entry.file_name())));
+                }
+            }
+            Some(old) => merged.replaced |= old.dir || meta.is_dir() || fingerprint(&full).as_ref() != Some(old),
+        }
+    }
+    merged.created.sort();
+    merged
+}
+
+/// How targets are looked up: case-insensitively on Windows, like the file system.
+#[cfg(windows)]
+fn key(path: &Path) -> PathBuf {
+    imp::normalize(path)
+}
+
+#[cfg(not(windows))]
+fn key(path: &Path) -> PathBuf {
+    path.to_owned()
+}
+
+/// What was at the targets of an operation before it ran, so undo only
+/// removes what the operation added. Empty while the journal is off.
+#[derive(Debug, Default)]
+pub(crate) struct Before {
+    targets: HashMap<PathBuf, HashMap<PathBuf, Fingerprint>>,
+}
+
+impl Before {
+    fn of(steps: &[Step]) -> Self {
+        let mut before = Before::default();
+        for target in steps.iter().filter_map(Step::target) {
+            if fs::symlink_metadata(&target).is_ok() {
+                before.targets.entry(key(&target)).or_insert_with(|| items(&target));
+            }
+        }
+        before
+    }
+
+    /// `None` when nothing was at `result`.
+    fn merged(&self, result: &Path) -> Option<Merged> {
+        self.targets.get(&key(result)).map(|items| merged(result, items))
+    }
+}
+
+/// Taken before every [`crate::FileOperation`], for [`record`].
+pub(crate) fn before(steps: &[Step]) -> Before {
+    match ENABLED.load(Ordering::Relaxed) {
+        true => Before::of(steps),
+        false => Before::default(),
+    }
+}
+
+/// One completed item of a journal entry. `merged` is set when the item's
+/// target was there before the operation.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum JournalAction {
+    Copy {
+        src: PathBuf,
+        result: PathBuf,
+        fingerprint: Fingerprint,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        merged: Option<Merged>,
+    },
+    Move {
+        src: PathBuf,
+        result: PathBuf,
+        fingerprint: Fingerprint,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        merged: Option<Merged>,
+    },
+    Rename {
+        src: PathBuf,
+        result: PathBuf,
+        fingerprint: Fingerprint,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        merged: Option<Merged>,
+    },
+    /// New folder or new file.
+    Create {
+        result: PathBuf,
+        folder: bool,
+        fingerprint: Fingerprint,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        merged: Option<Merged>,
+    },
+    /// Deleted into the trash, `result` is the item in the trash.
+    Trash { src: PathBuf, result: PathBuf },
+    /// `a` and `b` traded places, see [`crate::swap`].
+    Swap { a: PathBuf, b: PathBuf, a_fingerprint: Fingerprint, b_fingerprint: Fingerprint },
+    /// Deleted permanently, cannot be undone.
+    Delete { src: PathBuf },
+}
+
+/// One operation in the journal.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: u64,
+    /// Seconds since 1970.
+    pub time: u64,
+    /// `copy`, `move`, `delete`... or `batch` for mixed steps.
+    pub command: String,
+    pub status: OperationStatus,
+    pub undone: bool,
+    pub actions: Vec<JournalAction>,
+    /// Id of the newest entry when this one was undone: a newer operation
+    /// means it can no longer be redone.
+    #[serde(default)]
+    undo_mark: u64,
+}
+
+fn action(op: OperationKind, src: &Path, result: Option<&Path>, before: &Before) -> Option<JournalAction> {
+    let (src, result) = (src.to_owned(), result.map(Path::to_owned));
+    let print = |result: &Path| fingerprint(result).unwrap_or_default();
+    Some(match op {
+        OperationKind::Copy => {
+            let result = result?;
+            JournalAction::Copy { fingerprint: print(&result), merged: before.merged(&result), src, result }
+        }
+        OperationKind::Move => {
+            let result = result?;
+            JournalAction::Move { fingerprint: print(&result), merged: before.merged(&result), src, result }
+        }
+        OperationKind::Rename => {
+            let result = result?;
+            JournalAction::Rename { fingerprint: print(&result), merged: before.merged(&result), src, result }
+        }
+        OperationKind::NewFolder | OperationKind::NewFile => {
+            let result = result?;
+            let folder = op == OperationKind::NewFolder;
+            JournalAction::Create { fingerprint: print(&result), merged: before.merged(&result), folder, result }
+        }
+        OperationKind::Delete => match result {
+            Some(result) => JournalAction::Trash { src, result },
+            None => JournalAction::Delete { src },
+        },
+    })
+}
+
+/// The entry for a finished operation, `None` when nothing got done.
+fn entry(report: &OperationReport, before: &Before) -> Option<JournalEntry> {
+    let done: Vec<_> = report.items.iter().filter(|item| item.result.is_ok()).collect();
+    let actions: Vec<JournalAction> = done.iter()
+        .filter_map(|item| action(item.op, &item.path, item.result_path.as_deref(), before))
+        .collect();
+    if actions.is_empty() {
+        return None;
+    }
+    let command = match done.first() {
+        Some(first) if done.iter().all(|item| item.op == first.op) => first.op.to_string(),
+        _ => "batch".to_owned(),
+    };
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    Some(JournalEntry { id: 0, time, command, status: report.status(), undone: false, actions, undo_mark: 0 })
+}
+
+fn journal_path() -> PathBuf {
+    if let Some(path) = std::env::var_os("IFOP_JOURNAL") {
+        return PathBuf::from(path);
+    }
+    state_dir().join("journal.jsonl")
+}
+
+/// `%LOCALAPPDATA%\ifop` or `$XDG_STATE_HOME/ifop`.
+pub(crate) fn state_dir() -> PathBuf {
+    #[cfg(windows)]
+    let dir = std::env::var_os("LOCALAPPDATA").map(PathBuf::from);
+    #[cfg(not(windows))]
+    let dir = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state")));
+    dir.unwrap_or_else(std::env::temp_dir).join("ifop")
+}
+
+fn journal_error(e: io::Error, path: &Path) -> Error {
+    Error::Platform { path: Some(path.to_owned()), op: None, code: e.raw_os_error().unwrap_or(0), message: e.to_string() }
+}
+
+/// Every entry, oldest first. Lines that do not parse are skipped.
+fn read(path: &Path) -> io::Result<Vec<JournalEntry>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn append(path: &Path, mut entry: JournalEntry) -> io::Result<JournalEntry> {
+    entry.id = read(path)?.last().map_or(1, |last| last.id + 1);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+    Ok(entry)
+}
+
+/// Replace the journal with `entries`, through a temporary file so a crash
+/// never leaves half of it.
+fn write(path: &Path, entries: &[JournalEntry]) -> io::Result<()> {
+    let temp = path.with_extension("jsonl.tmp");
+    let mut file = fs::File::create(&temp)?;
+    for entry in entries {
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    }
+    file.sync_all()?;
+    fs::rename(temp, path)
+}
+
+/// Called after every [`crate::FileOperation`], and after the renames and
+/// copies done without one. Recording is best effort: a journal that cannot
+/// be written does not fail an operation that succeeded.
+pub(crate) fn record(report: &OperationReport, before: &Before) {
+    if ENABLED.load(Ordering::Relaxed) {
+        if let Some(entry) = entry(report, before) {
+            let _ = append(&journal_path(), entry);
+        }
+    }
+}
+
+/// Called after [`crate::swap`], best effort as [`record`].
+pub(crate) fn record_swap(a: &Path, b: &Path) {
+    if ENABLED.load(Ordering::Relaxed) {
+        let _ = append(&journal_path(), swap_entry(a, b));
+    }
+}
+
+fn swap_entry(a: &Path, b: &Path) -> JournalEntry {
+    let print = |path: &Path| fingerprint(path).unwrap_or_default();
+    let action = JournalAction::Swap { a: a.to_owned(), b: b.to_owned(), a_fingerprint: print(a), b_fingerprint: print(b) };
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    JournalEntry { id: 0, time, command: "swap".to_owned(), status: OperationStatus::Done, undone: false, actions: vec![action], undo_mark: 0 }
+}
+
+/// `Ok` when `path` still looks like it did after the operation.
+fn unchanged(path: &Path, expected: &Fingerprint) -> Result<()> {
+    match fingerprint(path) {
+        Some(actual) if actual == *expected => Ok(()),
+        _ => Err(Error::Changed { path: path.to_owned() }),
+    }
+}
+
+/// `Ok` when nothing is at `path`, where undo wants to put an item back.
+fn free(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(_) => Err(Error::Conflict { path: path.to_owned(), op: OperationKind::Move }),
+        Err(_) => Ok(()),
+    }
+}
+
+fn check_undo(action: &JournalAction) -> Result<()> {
+    match action {
+        JournalAction::Copy { result, fingerprint, merged, .. } | JournalAction::Create { result, fingerprint, merged, .. } => {
+            // What the operation added can go, what it replaced cannot come back.
+            if merged.as_ref().is_some_and(|merged| merged.replaced) {
+                return Err(Error::Irreversible { path: result.clone() });
+            }
+            unchanged(result, fingerprint)
+        }
+        JournalAction::Move { src, result, fingerprint, merged } | JournalAction::Rename { src, result, fingerprint, merged } => {
+            // Moving the whole target back would take what was there along.
+            if merged.is_some() {
+                return Err(Error::Irreversible { path: result.clone() });
+            }
+            unchanged(result, fingerprint)?;
+            free(src)
+        }
+        JournalAction::Trash { src, result } => {
+            fs::symlink_metadata(result).map_err(|_| Error::Changed { path: result.clone() })?;
+            free(src)
+        }
+        JournalAction::Swap { a, b, a_fingerprint, b_fingerprint } => {
+            unchanged(a, a_fingerprint)?;
+            unchanged(b, b_fingerprint)
+        }
+        JournalAction::Delete { .. } => Ok(()),
+    }
+}
+
+fn text(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// Run one step without recording it, returns where the item ended up.
+/// `FOF_NO_UI`: every target was checked beforehand, the shell has nothing to ask.
+fn run(step: Step, flags: OperationFlags) -> Result<Option<PathBuf>> {
+    let mut report = imp::perform(&[step], Some(flags | OperationFlags::FOF_NO_UI), &CopyOptions::default(), &mut ())?;
+    let result_path = report.items.first_mut().and_then(|item| item.result_path.take());
+    report.into_result().map(|_| result_path)
+}
+
+/// Steps that put `item` at `to`: a rename in the same folder, a move otherwise.
+fn move_step(item: &Path, to: &Path, rename: bool) -> Step {
+    let name = to.file_name().map(|name| name.to_string_lossy().into_owned());
+    if rename && item.parent() == to.parent() {
+        return Step::Rename { src: text(item), new_name: name.unwrap_or_default() };
+    }
+    Step::Move { src: text(item), dest: to.parent().map(text).unwrap_or_default(), name }
+}
+
+fn undo_action(action: &JournalAction) -> Result<()> {
+    match action {
+        JournalAction::Copy { result, merged: None, .. } | JournalAction::Create { result, merged: None, .. } => {
+            run(Step::Delete { target: text(result) }, OperationFlags::empty()).map(|_| ())
+        }
+        JournalAction::Copy { merged: Some(merged), .. } | JournalAction::Create { merged: Some(merged), .. } => {
+            for item in &merged.created {
+                run(Step::Delete { target: text(item) }, OperationFlags::empty())?;
+            }
+            Ok(())
+        }
+        JournalAction::Move { src, result, .. } => {
+            // A merged folder the item came from is gone once it was emptied.
+            if let Some(parent) = src.parent() {
+                fs::create_dir_all(parent).map_err(|e| Error::from_io(e, parent, OperationKind::Move))?;
+            }
+            run(move_step(result, src, false), OperationFlags::empty()).map(|_| ())
+        }
+        JournalAction::Rename { src, result, .. } => run(move_step(result, src, true), OperationFlags::empty()).map(|_| ()),
+        JournalAction::Trash { result, .. } => restore(result),
+        // Swapped back without being recorded again.
+        JournalAction::Swap { a, b, .. } => crate::atomic::swap_paths(a, b),
+        // Gone for good.
+        JournalAction::Delete { .. } => Ok(()),
+    }
+}
+
+#[cfg(any(unix, windows))]
+fn restore(item: &Path) -> Result<()> {
+    crate::restore_trash_item(&text(item), None).map(|_| ())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn restore(item: &Path) -> Result<()> {
+    Err(Error::NotFound { path: item.to_owned(), op: OperationKind::Move })
+}
+
+/// Undo the `count` most recent entries that are not undone yet, newest first.
+fn undo_in(path: &Path, count: usize) -> Result<Vec<JournalEntry>> {
+    let mut entries = read(path).map_err(|e| journal_error(e, path))?;
+    let newest = entries.last().map_or(0, |entry| entry.id);
+    let mut undone = Vec::new();
+    for index in (0..entries.len()).rev().filter(|&index| !entries[index].undone).take(count).collect::<Vec<_>>() {
+        let entry = &mut entries[index];
+        // Everything is checked before anything is touched.
+        for action in entry.actions.iter().rev() {
+            check_undo(action)?;
+        }
+        for at in (0..entry.actions.len()).rev() {
+            if let Err(e) = undo_action(&entry.actions[at]) {
+                // Redo what was undone so the entry stays in effect as a whole,
+                // with the fingerprints of the items put back.
+                for action in &mut entry.actions[at + 1..] {
+                    let _ = redo_action(action);
+                }
+                let _ = write(path, &entries);
+                return Err(e);
+            }
+        }
+        entry.undone = true;
+        entry.undo_mark = newest;
+        undone.push(entry.clone());
+        write(path, &entries).map_err(|e| journal_error(e, path))?;
+    }
+    Ok(undone)
+}
+
+/// ### Undo the last `count` operations recorded in the journal
+/// Moves and renames are moved back, copies and new items deleted, and items
+/// deleted into the trash restored. Permanent deletes stay deleted. A copy
+/// merged into a folder that was there only has what it added deleted. An
+/// entry whose items changed since, whose original paths are taken again, or
+/// that replaced or moved into what was there before, is refused before
+/// anything is touched; one that fails halfway has its undone items redone.
+/// Returns the undone entries, newest first.
+/// ```rust
+/// match ifop::undo(2) {
+///     Ok(entries) => {
+///         println!("undid {} operations", entries.len());
+///     }
+///     Err(e) => {
+///         println!("{}", e);
+///     }
+/// }
+/// ```
+pub fn undo(count: usize) -> Result<Vec<JournalEntry>> {
+    undo_in(&journal_path(), count)
+}
+
+/// ### Undo the last operation recorded in the journal
+/// `None` when there is nothing left to undo.
+/// ```rust
+/// ifop::undo_last().unwrap();
+/// ```
+pub fn undo_last() -> Result<Option<JournalEntry>> {
+    undo(1).map(|entries| entries.into_iter().next())
+}
+
+/// `Ok` when `path` is there again after an undo.
+fn present(path: &Path) -> Result<()> {
+    fs::symlink_metadata(path).map(|_| ()).map_err(|_| Error::NotFound { path: path.to_owned(), op: OperationKind::Move })
+}
+
+fn check_redo(action: &JournalAction) -> Result<()> {
+    match action {
+        JournalAction::Copy { src, merged: Some(merged), .. } => {
+            present(src)?;
+            merged.created.iter().try_for_each(|item| free(item))
+        }
+        JournalAction::Create { merged: Some(_), .. } => Ok(()),
+        JournalAction::Copy { src, result, .. }
+        | JournalAction::Move { src, result, .. }
+        | JournalAction::Rename { src, result, .. } => {
+            present(src)?;
+            free(result)
+        }
+        JournalAction::Create { result, .. } => free(result),
+        JournalAction::Trash { src, .. } => present(src),
+        JournalAction::Swap { a, b, .. } => {
+            present(a)?;
+            present(b)
+        }
+        JournalAction::Delete { .. } => Ok(()),
+    }
+}
+
+/// Apply `action` again, updating where its item ends up and its fingerprint.
+fn redo_action(action: &mut JournalAction) -> Result<()> {
+    let print = |result: &Path| fingerprint(result).unwrap_or_default();
+    match action {
+        JournalAction::Copy { src, result, fingerprint, merged: Some(merged) } => {
+            // Only what the copy added, from the same place in `src`.
+            for item in &merged.created {
+                let from = src.join(item.strip_prefix(&*result).unwrap_or(item));
+                let name = item.file_name().map(|name| name.to_string_lossy().into_owned());
+                run(Step::Copy { src: text(&from), dest: item.parent().map(text).unwrap_or_default(), name }, OperationFlags::empty())?;
+            }
+            *fingerprint = print(result);
+        }
+        JournalAction::Copy { src, result, fingerprint, merged: None } => {
+            let name = result.file_name().map(|name| name.to_string_lossy().into_owned());
+            let dest = result.parent().map(text).unwrap_or_default();
+            run(Step::Copy { src: text(src), dest, name }, OperationFlags::empty())?;
+            *fingerprint = print(result);
+        }
+        JournalAction::Move { src, result, fingerprint, .. } => {
+            run(move_step(src, result, false), OperationFlags::empty())?;
+            *fingerprint = print(result);
+        }
+        JournalAction::Rename { src, result, fingerprint, .. } => {
+            run(move_step(src, result, true), OperationFlags::empty())?;
+            *fingerprint = print(result);
+        }
+        JournalAction::Create { merged: Some(_), .. } => {}
+        JournalAction::Create { result, folder, fingerprint, merged: None } => {
+            let root = result.parent().map(text).unwrap_or_default();
+            let name = result.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            run(if *folder { Step::NewFolder { root, name } } else { Step::NewFile { root, name } }, OperationFlags::empty())?;
+            *fingerprint = print(result);
+        }
+        JournalAction::Trash { src, result } => {
+            // The item gets a new place in the trash.
+            if let Some(trashed) = run(Step::Delete { target: text(src) }, OperationFlags::FOF_ALLOWUNDO)? {
+                *result = trashed;
+            }
+        }
+        JournalAction::Swap { a, b, a_fingerprint, b_fingerprint } => {
+            crate::atomic::swap_paths(a, b)?;
+            (*a_fingerprint, *b_fingerprint) = (print(a), print(b));
+        }
+        JournalAction::Delete { .. } => {}
+    }
+    Ok(())
+}
+
+/// Redo the `count` most recently undone entries, in the order they first ran.
+fn redo_in(path: &Path, count: usize) -> Result<Vec<JournalEntry>> {
+    let mut entries = read(path).map_err(|e| journal_error(e, path))?;
+    let newest = entries.last().map_or(0, |entry| entry.id);
+    let redoable: Vec<usize> = (0..entries.len())
+        .filter(|&index| entries[index].undone && entries[index].undo_mark == newest)
+        .collect();
+    let mut redone = Vec::new();
+    // Undone newest first, so the oldest was undone last.
+    for index in redoable.into_iter().take(count) {
+        let entry = &mut entries[index];
+        for action in &entry.actions {
+            check_redo(action)?;
+        }
+        for at in 0..entry.actions.len() {
+            if let Err(e) = redo_action(&mut entry.actions[at]) {
+                // Undo what was redone so the entry stays undone as a whole.
+                for action in entry.actions[..at].iter().rev() {
+                    let _ = undo_action(action);
+                }
+                return Err(e);
+            }
+        }
+        entry.undone = false;
+        entry.undo_mark = 0;
+        redone.push(entry.clone());
+        write(path, &entries).map_err(|e| journal_error(e, path))?;
+    }
+    Ok(redone)
+}
+
+/// ### Redo the last `count` undone operations
+/// Applies them again in the order they first ran, once their items are back
+/// where the operation found them and their targets are free; one that fails
+/// halfway is undone again. A new operation drops what was undone before it.
+/// Returns the redone entries.
+/// ```rust
+/// ifop::undo_last().unwrap();
+/// ifop::redo(1).unwrap();
+/// ```
+pub fn redo(count: usize) -> Result<Vec<JournalEntry>> {
+    redo_in(&journal_path(), count)
+}
+
+/// ### Redo the last undone operation
+/// `None` when there is nothing to redo.
+/// ```rust
+/// ifop::redo_last().unwrap();
+/// ```
+pub fn redo_last() -> Result<Option<JournalEntry>> {
+    redo(1).map(|entries| entries.into_iter().next())
+}
+
+/// ### Operations recorded in the journal, oldest first
+/// ```rust
+/// for entry in ifop::history().unwrap() {
+///     println!("#{} {} {} items {:?}", entry.id, entry.command, entry.actions.len(), entry.status);
+/// }
+/// ```
+pub fn history() -> Result<Vec<JournalEntry>> {
+    let path = journal_path();
+    read(&path).map_err(|e| journal_error(e, &path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileOperation;
+
+    fn text(path: &Path) -> &str {
+        path.to_str().unwrap()
+    }
+
+    #[test]
+    fn test_undo() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_undo");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("src")).unwrap();
+        fs::write(folder.join("src").join("file1"), "one").unwrap();
+        fs::write(folder.join("src").join("file2"), "two").unwrap();
+        let journal = folder.join("journal.jsonl");
+
+        let report = FileOperation::new(None)
+            .new_folder(text(&folder), "dest")
+            .move_to(text(&folder.join("src").join("file1")), text(&folder.join("dest")))
+            .copy(text(&folder.join("src").join("file2")), text(&folder.join("dest")))
+            .perform()
+            .unwrap();
+        let recorded = append(&journal, entry(&report, &Before::default()).unwrap()).unwrap();
+        assert_eq!((recorded.id, recorded.command.as_str(), recorded.actions.len()), (1, "batch", 3));
+        let report = FileOperation::new(None)
+            .rename(text(&folder.join("dest").join("file2")), "renamed")
+            .perform()
+            .unwrap();
+        append(&journal, entry(&report, &Before::default()).unwrap()).unwrap();
+
+        // The rename is undone, then the batch is refused as the copy changed.
+        let undone = undo_in(&journal, 1).unwrap();
+        assert_eq!(undone[0].id, 2);
+        assert!(folder.join("dest").join("file2").exists());
+        let time = fs::metadata(folder.join("dest").join("file2")).unwrap().modified().unwrap();
+        fs::write(folder.join("dest").join("file2"), "changed").unwrap();
+        assert_eq!(undo_in(&journal, 1), Err(Error::Changed { path: folder.join("dest").join("file2") }));
+        assert!(folder.join("dest").join("file1").exists());
+
+        fs::write(folder.join("dest").join("file2"), "two").unwrap();
+        fs::File::options().write(true).open(folder.join("dest").join("file2")).unwrap().set_modified(time).unwrap();
+        let undone = undo_in(&journal, 5).unwrap();
+        assert_eq!(undone.len(), 1);
+        assert!(!folder.join("dest").exists());
+        assert_eq!(fs::read_to_string(folder.join("src").join("file1")).unwrap(), "one");
+        assert!(read(&journal).unwrap().iter().all(|entry| entry.undone));
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn test_undo_merged() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_undo_merged");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("src").join("proj").join("sub")).unwrap();
+        fs::create_dir_all(folder.join("backup").join("proj").join("sub")).unwrap();
+        fs::write(folder.join("src").join("proj").join("new"), "new").unwrap();
+        fs::write(folder.join("src").join("proj").join("sub").join("deep"), "deep").unwrap();
+        fs::write(folder.join("backup").join("proj").join("precious"), "precious").unwrap();
+        let journal = folder.join("journal.jsonl");
+        let (src, backup) = (folder.join("src").join("proj"), folder.join("backup"));
+        let steps = [Step::Copy { src: text(&src).to_owned(), dest: text(&backup).to_owned(), name: None }];
+        let copy = || FileOperation::new(Some(OperationFlags::FOF_NOCONFIRMATION)).copy(text(&src), text(&backup)).perform().unwrap();
+
+        // Only what the copy added goes, the folder and what was in it stay.
+        let before = Before::of(&steps);
+        let recorded = append(&journal, entry(&copy(), &before).unwrap()).unwrap();
+        let created = vec![backup.join("proj").join("new"), backup.join("proj").join("sub").join("deep")];
+        assert!(matches!(&recorded.actions[0], JournalAction::Copy { merged: Some(merged), .. } if merged.created == created && !merged.replaced));
+        undo_in(&journal, 1).unwrap();
+        assert_eq!(fs::read_to_string(backup.join("proj").join("precious")).unwrap(), "precious");
+        assert!(backup.join("proj").join("sub").is_dir());
+        assert!(created.iter().all(|item| !item.exists()));
+        redo_in(&journal, 1).unwrap();
+        assert_eq!(fs::read_to_string(&created[1]).unwrap(), "deep");
+
+        // A replaced file cannot come back: refused without touching anything.
+        fs::write(src.join("precious"), "replaced").unwrap();
+        let before = Before::of(&steps);
+        append(&journal, entry(&copy(), &before).unwrap()).unwrap();
+        assert_eq!(undo_in(&journal, 1), Err(Error::Irreversible { path: backup.join("proj") }));
+        assert_eq!(fs::read_to_string(backup.join("proj").join("precious")).unwrap(), "replaced");
+        assert!(created.iter().all(|item| item.exists()));
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn test_redo() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_redo");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("file"), "content").unwrap();
+        let journal = folder.join("journal.jsonl");
+
+        let report = FileOperation::new(None).new_folder(text(&folder), "dest").perform().unwrap();
+        append(&journal, entry(&report, &Before::default()).unwrap()).unwrap();
+        let report = FileOperation::new(None)
+            .move_to(text(&folder.join("file")), text(&folder.join("dest")))
+            .perform()
+            .unwrap();
+        append(&journal, entry(&report, &Before::default()).unwrap()).unwrap();
+        assert_eq!(undo_in(&journal, 2).unwrap().len(), 2);
+        assert!(!folder.join("dest").exists());
+
+        // Oldest first, and nothing left once everything is in effect again.
+        let redone = redo_in(&journal, 5).unwrap();
+        assert_eq!(redone.iter().map(|entry| entry.id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(fs::read_to_string(folder.join("dest").join("file")).unwrap(), "content");
+        assert!(redo_in(&journal, 1).unwrap().is_empty());
+
+        // The target is taken: refused without touching anything.
+        undo_in(&journal, 1).unwrap();
+        fs::write(folder.join("dest").join("file"), "other").unwrap();
+        assert_eq!(redo_in(&journal, 1), Err(Error::Conflict { path: folder.join("dest").join("file"), op: OperationKind::Move }));
+        assert_eq!(fs::read_to_string(folder.join("file")).unwrap(), "content");
+        let history = read(&journal).unwrap();
+        assert_eq!(history.iter().map(|entry| entry.undone).collect::<Vec<_>>(), vec![false, true]);
+        let report = FileOperation::new(None).new_file(text(&folder), "new").perform().unwrap();
+        append(&journal, entry(&report, &Before::default()).unwrap()).unwrap();
+        assert!(redo_in(&journal, 1).unwrap().is_empty());
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn test_undo_rolled_back() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_undo_rolled_back");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("src")).unwrap();
+        fs::create_dir_all(folder.join("dest")).unwrap();
+        fs::write(folder.join("file"), "content").unwrap();
+        let journal = folder.join("journal.jsonl");
+        let (file, renamed, dest) = (folder.join("file"), folder.join("renamed"), folder.join("dest"));
+
+        // The rename goes through, then the copy fails on an item that is gone.
+        let report = FileOperation::new(None).rename(text(&file), "renamed").perform().unwrap();
+        let mut recorded = entry(&report, &Before::default()).unwrap();
+        let merged = Merged { created: vec![dest.join("gone")], replaced: false };
+        let copy = JournalAction::Copy { src: folder.join("src"), result: dest.clone(), fingerprint: fingerprint(&dest).unwrap(), merged: Some(merged) };
+        recorded.actions.insert(0, copy);
+        append(&journal, recorded).unwrap();
+        assert!(undo_in(&journal, 1).is_err());
+        assert_eq!(fs::read_to_string(&renamed).unwrap(), "content");
+        assert!(!file.exists());
+        assert!(!read(&journal).unwrap()[0].undone);
+
+        // Redone the other way round: the rename is undone again.
+        let mut entries = read(&journal).unwrap();
+        entries[0].actions.swap(0, 1);
+        entries[0].undone = true;
+        entries[0].undo_mark = entries[0].id;
+        fs::rename(&renamed, &file).unwrap();
+        write(&journal, &entries).unwrap();
+        assert!(redo_in(&journal, 1).is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "content");
+        assert!(!renamed.exists());
+        assert!(read(&journal).unwrap()[0].undone);
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_undo_swap() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_undo_swap");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let (blue, green) = (folder.join("blue"), folder.join("green"));
+        fs::write(&blue, "blue").unwrap();
+        fs::write(&green, "green").unwrap();
+        let journal = folder.join("journal.jsonl");
+
+        crate::atomic::swap_paths(&blue, &green).unwrap();
+        append(&journal, swap_entry(&blue, &green)).unwrap();
+        undo_in(&journal, 1).unwrap();
+        assert_eq!(fs::read_to_string(&blue).unwrap(), "blue");
+        redo_in(&journal, 1).unwrap();
+        assert_eq!(fs::read_to_string(&blue).unwrap(), "green");
+
+        // Changed since: refused without swapping back.
+        fs::write(&green, "changed").unwrap();
+        assert_eq!(undo_in(&journal, 1), Err(Error::Changed { path: green.clone() }));
+        assert_eq!(fs::read_to_string(&blue).unwrap(), "green");
+
+        // A rename without replace is undone as a rename.
+        let mut report = OperationReport::default();
+        crate::rename_no_replace(text(&blue), "next").unwrap();
+        report.push(OperationKind::Rename, blue.clone(), Some(folder.join("next")), Ok(()));
+        append(&journal, entry(&report, &Before::default()).unwrap()).unwrap();
+        undo_in(&journal, 1).unwrap();
+        assert_eq!(fs::read_to_string(&blue).unwrap(), "green");
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
\ No newline at end of file
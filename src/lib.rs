@@ -1,6 +1,8 @@
 //! - You can use this crate to copy|move|delete|rename file or folder like Explorer that.
 //! - When you move a file to another folder, then you can undo or redo in explorer.
 //! - Show progress window if this is big file.
+//! - On Linux and other non-Windows targets the same API is backed by `std::fs`, so one crate serves both.
+//! - Deleting with `FOF_ALLOWUNDO` sends items to the Recycle Bin on Windows and to the FreeDesktop Trash on Linux; without it they are removed permanently.
 //! - The `--flags` options Refer [`IFileOperation`](https://learn.microsoft.com/en-us/windows/win32/api/shobjidl_core/nn-shobjidl_core-ifileoperation)::[`SetOperationFlags`](https://learn.microsoft.com/en-us/windows/win32/api/shobjidl_core/nf-shobjidl_core-ifileoperation-setoperationflags)
 //! 
 //! ## Installation
@@ -34,8 +36,8 @@
 //! 
 //! // With flags
 //! ifop::copy_file("c:\\src\\file.text", "c:\\dest", Some(
-//!     windows::Win32::UI::Shell::FOF_ALLOWUNDO |
-//!     windows::Win32::UI::Shell::FOF_NORECURSION
+//!     ifop::OperationFlags::FOF_ALLOWUNDO |
+//!     ifop::OperationFlags::FOF_NORECURSION
 //! )).unwrap();
 //! ```
 //! 
@@ -46,11 +48,24 @@
 //! 
 //! // With flags
 //! ifop::copy_files(vec!["c:\\src\\file1.txt", "c:\\src\\file2.txt"], "c:\\dest", Some(
-//!     windows::Win32::UI::Shell::FOF_ALLOWUNDO |
-//!     windows::Win32::UI::Shell::FOF_NORECURSION
+//!     ifop::OperationFlags::FOF_ALLOWUNDO |
+//!     ifop::OperationFlags::FOF_NORECURSION
 //! )).unwrap();
 //! ```
 //! 
+//! - `Copy` single file under a new name
+//! ```rust
+//! ifop::copy_file_as("c:\\src\\report.xlsx", "c:\\dest", "report-2026-10.xlsx", None).unwrap();
+//! ```
+//! 
+//! - `Copy` to many destinations, one `(src, dest_folder, new_name)` per item
+//! ```rust
+//! ifop::copy_pairs(vec![
+//!     ("c:\\build\\app.exe", "c:\\deploy", None),
+//!     ("c:\\build\\app.cfg", "c:\\config", Some("app.prod.cfg")),
+//! ], None).unwrap();
+//! ```
+//! 
 //! - `Move` single file
 //! ```rust
 //! // No flags
@@ -58,8 +73,8 @@
 //! 
 //! // With flags
 //! ifop::move_file("c:\\src\\file.text", "c:\\dest", Some(
-//!     windows::Win32::UI::Shell::FOF_ALLOWUNDO |
-//!     windows::Win32::UI::Shell::FOF_NORECURSION
+//!     ifop::OperationFlags::FOF_ALLOWUNDO |
+//!     ifop::OperationFlags::FOF_NORECURSION
 //! )).unwrap();
 //! ```
 //! 
@@ -70,11 +85,24 @@
 //! 
 //! // With flags
 //! ifop::move_files(vec!["c:\\src\\file1.txt", "c:\\src\\file2.txt"], "c:\\dest", Some(
-//!     windows::Win32::UI::Shell::FOF_ALLOWUNDO |
-//!     windows::Win32::UI::Shell::FOF_NORECURSION
+//!     ifop::OperationFlags::FOF_ALLOWUNDO |
+//!     ifop::OperationFlags::FOF_NORECURSION
 //! )).unwrap();
 //! ```
 //! 
+//! - `Move` single file under a new name
+//! ```rust
+//! ifop::move_file_as("c:\\src\\report.xlsx", "c:\\dest", "report-2026-10.xlsx", None).unwrap();
+//! ```
+//! 
+//! - `Move` to many destinations, one `(src, dest_folder, new_name)` per item
+//! ```rust
+//! ifop::move_pairs(vec![
+//!     ("c:\\inbox\\a.pdf", "c:\\invoices", None),
+//!     ("c:\\inbox\\b.pdf", "c:\\receipts", Some("2026-10-18.pdf")),
+//! ], None).unwrap();
+//! ```
+//! 
 //! - `Rename` single file
 //! ```rust
 //! // No flags
@@ -82,8 +110,8 @@
 //! 
 //! // With flags
 //! ifop::rename_file("c:\\src\\folder1", "folder2", Some(
-    //! windows::Win32::UI::Shell::FOF_ALLOWUNDO |
-    //! windows::Win32::UI::Shell::FOF_NORECURSION
+//!     ifop::OperationFlags::FOF_ALLOWUNDO |
+//!     ifop::OperationFlags::FOF_NORECURSION
 //! )).unwrap();
 //! ```
 //! 
@@ -94,8 +122,8 @@
 //! 
 //! // With flags
 //! ifop::rename_files(vec!["c:\\src\\folder1\\file1.txt", "c:\\src\\folder2\\file2.txt"], "file3.txt", Some(
-//!     windows::Win32::UI::Shell::FOF_ALLOWUNDO |
-//!     windows::Win32::UI::Shell::FOF_NORECURSION
+//!     ifop::OperationFlags::FOF_ALLOWUNDO |
+//!     ifop::OperationFlags::FOF_NORECURSION
 //! )).unwrap();
 //! ```
 //! 
@@ -105,8 +133,8 @@
 //! ifop::delete_file("c:\\src\\folder1", None).unwrap();
 //! // With flags
 //! ifop::delete_file("c:\\src\\folder1", Some(
-//!     windows::Win32::UI::Shell::FOF_ALLOWUNDO |
-//!     windows::Win32::UI::Shell::FOF_NORECURSION
+//!     ifop::OperationFlags::FOF_ALLOWUNDO |
+//!     ifop::OperationFlags::FOF_NORECURSION
 //! )).unwrap();
 //! ```
 //! 
@@ -117,8 +145,8 @@
 //! 
 //! // With flags
 //! ifop::delete_files(vec!["c:\\src\\folder1\\file1.txt", "c:\\src\\folder2\\file2.txt"], Some(
-//!     windows::Win32::UI::Shell::FOF_ALLOWUNDO |
-//!     windows::Win32::UI::Shell::FOF_NORECURSION
+//!     ifop::OperationFlags::FOF_ALLOWUNDO |
+//!     ifop::OperationFlags::FOF_NORECURSION
 //! )).unwrap();
 //! ```
 //! 
@@ -129,8 +157,8 @@
 //! 
 //! // With flags
 //! ifop::create_file("c:\\", "file.txt", Some(
-//!     windows::Win32::UI::Shell::FOF_ALLOWUNDO |
-//!     windows::Win32::UI::Shell::FOF_NORECURSION
+//!     ifop::OperationFlags::FOF_ALLOWUNDO |
+//!     ifop::OperationFlags::FOF_NORECURSION
 //! )).unwrap();
 //! ```
 //! 
@@ -141,11 +169,208 @@
 //! 
 //! // With flags
 //! ifop::create_folder("c:\\", "folder", Some(
-//!     windows::Win32::UI::Shell::FOF_ALLOWUNDO |
-//!     windows::Win32::UI::Shell::FOF_NORECURSION
+//!     ifop::OperationFlags::FOF_ALLOWUNDO |
+//!     ifop::OperationFlags::FOF_NORECURSION
 //! )).unwrap();
 //! ```
 //! 
+//! - Batch of mixed steps, performed as one operation (one progress window, one undo record)
+//! ```rust
+//! ifop::FileOperation::new(None)
+//!     .new_folder("c:\\dest", "archive")
+//!     .copy("c:\\src\\file1.txt", "c:\\dest\\archive")
+//!     .move_to("c:\\src\\file2.txt", "c:\\dest\\archive")
+//!     .rename("c:\\dest\\archive\\file1.txt", "file3.txt")
+//!     .delete("c:\\src\\old")
+//!     .perform().unwrap();
+//! ```
+//! 
+//! - Errors name the item and the step that failed; a batch reports every item instead of stopping at the first failure
+//! ```rust
+//! let report = ifop::FileOperation::new(None)
+//!     .delete("c:\\src\\file1.txt")
+//!     .delete("c:\\src\\file2.txt")
+//!     .perform().unwrap();
+//! match report.status() {
+//!     ifop::OperationStatus::Done => println!("done"),
+//!     ifop::OperationStatus::Partial => println!("partially done"),
+//!     ifop::OperationStatus::Aborted => println!("cancelled by the user"),
+//!     ifop::OperationStatus::Failed => println!("nothing done"),
+//! }
+//! for item in report.failures() {
+//!     match &item.result {
+//!         Err(ifop::Error::NotFound { path, .. }) => println!("{} is already gone", path.display()),
+//!         Err(e) => println!("{}", e),
+//!         Ok(_) => {}
+//!     }
+//! }
+//! ```
+//! 
+//! - Progress of long copies and moves through a `ProgressSink`
+//! ```rust
+//! struct Bar;
+//! 
+//! impl ifop::ProgressSink for Bar {
+//!     fn progress(&mut self, progress: &ifop::Progress) {
+//!         println!("{}/{} items, {}/{} bytes", progress.items_done, progress.items_total, progress.bytes_done, progress.bytes_total);
+//!     }
+//! }
+//! 
+//! ifop::FileOperation::new(None)
+//!     .copy("c:\\src\\big.iso", "c:\\dest")
+//!     .perform_with(&mut Bar).unwrap();
+//! ```
+//! 
+//! - Trash: list, restore and empty the Recycle Bin (Windows) or the FreeDesktop Trash (Linux)
+//! ```rust
+//! for item in ifop::list_trash().unwrap() {
+//!     println!("{} deleted from {}", item.id, item.original_path.display());
+//! }
+//! 
+//! // By original path or by id, under a free name if the path is taken again
+//! ifop::restore_trash_item("c:\\src\\file.txt", Some(ifop::OperationFlags::FOF_RENAMEONCOLLISION)).unwrap();
+//! 
+//! // Items deleted more than 30 days ago
+//! ifop::empty_trash(Some(std::time::Duration::from_secs(30 * 24 * 60 * 60))).unwrap();
+//! ```
+//! 
+//! - Undo, redo and history: with the journal on, every operation is recorded and can be undone or redone later, unless its items changed since. Undoing a copy merged into an existing folder only removes what it added; one that replaced files is refused
+//! ```rust
+//! ifop::enable_journal(true);
+//! ifop::move_file("c:\\src\\file.txt", "c:\\dest", None).unwrap();
+//! 
+//! // Moves the file back to c:\src
+//! ifop::undo_last().unwrap();
+//! 
+//! // Moves it to c:\dest again
+//! ifop::redo_last().unwrap();
+//! 
+//! for entry in ifop::history().unwrap() {
+//!     println!("#{} {} {} items {:?}", entry.id, entry.command, entry.actions.len(), entry.status);
+//! }
+//! ```
+//! 
+//! - Dry run: the file-level actions an operation would take, with conflicts and totals, as text or JSON (`serde`)
+//! ```rust
+//! let plan = ifop::FileOperation::new(None)
+//!     .move_to("c:\\src\\folder", "c:\\dest")
+//!     .plan().unwrap();
+//! 
+//! // One action per line: create dir, copy, overwrite, delete... then the totals
+//! println!("{}", plan);
+//! println!("{}", serde_json::to_string(&plan).unwrap());
+//! ```
+//! 
+//! - Conflict policy: overwrite, skip, keep both (`name (2).ext` or `name_2026-10-18.ext`), overwrite if newer or different, or fail, the same on Windows and Linux
+//! ```rust
+//! ifop::FileOperation::new(None)
+//!     .on_conflict(ifop::ConflictPolicy::OverwriteIfDifferent(ifop::Difference::Content))
+//!     .copy("c:\\src\\folder", "c:\\dest")
+//!     .perform().unwrap();
+//! ```
+//! 
+//! - Wildcards: `*`, `?`, `**` and `[a-z]` expanded to the matching items, an error when a pattern matches nothing
+//! ```rust
+//! // `true` to ignore case
+//! let logs = ifop::expand_patterns(&["c:\\logs\\*.log", "c:\\logs\\**\\*.tmp"], true).unwrap();
+//! ifop::delete_files(logs.iter().map(String::as_str).collect(), None).unwrap();
+//! ```
+//! 
+//! - Filters: what copied, moved or deleted folders contribute, by gitignore patterns, size, date, hidden and read-only attributes and `.ifopignore` files
+//! ```rust
+//! let filter = ifop::Filter::new()
+//!     .exclude("target/")
+//!     .exclude("node_modules/")
+//!     .exclude(".git/")
+//!     .ignore_files(true);
+//! ifop::FileOperation::new(None)
+//!     .filter(filter)
+//!     .copy("c:\\src\\project", "c:\\backup")
+//!     .perform()
+//!     .unwrap();
+//! ```
+//! 
+//! - One-way sync: copy new and changed files to a folder, optionally delete what the source does not have
+//! ```rust
+//! let options = ifop::SyncOptions::new().delete_extraneous(true);
+//! let report = ifop::sync("c:\\build", "\\\\server\\share\\build", &options, Some(ifop::OperationFlags::FOF_ALLOWUNDO)).unwrap();
+//! for item in &report.items {
+//!     println!("{} {}", item.op, item.path.display());
+//! }
+//! ```
+//! 
+//! - Two-way sync: changes on either side since the last run go to the other one, conflicts decided by a `ConflictPolicy`
+//! ```rust
+//! let options = ifop::SyncOptions::new().on_conflict(ifop::ConflictPolicy::KeepBoth(ifop::KeepBothNaming::Dated));
+//! ifop::sync_bidirectional("c:\\work", "\\\\server\\share\\work", &options, None).unwrap();
+//! ```
+//! 
+//! - Verified copies: every copied file checked against its source with BLAKE3 or SHA-256, copied again on a mismatch, moves to another volume deleting their source only once checked
+//! ```rust
+//! let report = ifop::FileOperation::new(None)
+//!     .verify(ifop::Checksum::Blake3, 2)
+//!     .move_to("c:\\photos", "e:\\backup")
+//!     .perform()
+//!     .unwrap();
+//! for item in report.failures() {
+//!     // ifop::Error::Mismatch when the copy still differs after 2 retries
+//!     println!("{:?}", item.result);
+//! }
+//! ```
+//! 
+//! - Manifests: `SHA256SUMS` / `B3SUMS` files, or JSON with sizes, written for what a copy or move produced and checked again later
+//! ```rust
+//! ifop::FileOperation::new(None)
+//!     .manifest("d:\\release\\SHA256SUMS", ifop::Checksum::Sha256)
+//!     .copy("c:\\build\\bundle", "d:\\release")
+//!     .perform()
+//!     .unwrap();
+//! 
+//! let report = ifop::Manifest::read("d:\\release\\SHA256SUMS").unwrap().verify("d:\\release").unwrap();
+//! println!("missing {:?}, extra {:?}, corrupted {:?}", report.missing, report.extra, report.corrupted);
+//! ```
+//! 
+//! - Resumable copies of huge files and trees: a staging file and a checkpoint next to the target, so that running it again after an interruption goes on where it stopped
+//! ```rust
+//! // Finished files are skipped, the half-copied one is checked and completed.
+//! ifop::copy_resume("c:\\vm\\disk.vhdx", "\\\\server\\share\\vm", None).unwrap();
+//! ```
+//! 
+//! - Parallel copies of trees with many small files, with the `std::fs` backend: folders first, then the files through a bounded queue to a pool of workers, one per CPU for `copy_file`, `copy_files` and `FileOperation`
+//! ```rust
+//! // Other sizes with `CopyOptions`.
+//! let options = ifop::CopyOptions::new().threads(8).queue(4096);
+//! ifop::copy_files_with(vec!["c:\\src\\node_modules"], "d:\\cache", &options, None).unwrap();
+//! ```
+//! `cargo bench --bench parallel_copy` compares one thread with several on a generated tree.
+//! 
+//! - Linux copies clone files on Btrfs, XFS and the like (`FICLONE`), or copy them in the kernel with `copy_file_range`, and keep the holes of sparse files
+//! ```rust
+//! // Build caches as reflink clones, or fail where the file system cannot clone.
+//! let options = ifop::CopyOptions::new().reflink(ifop::Reflink::Always);
+//! ifop::copy_file_with("/var/cache/build", "/mnt/btrfs/cache", &options, None).unwrap();
+//! ```
+//! 
+//! - Moves to another file system, with the `std::fs` backend: each item is copied next to its target, checked against its source, put in place, and only then is the source deleted; a copy that fails or cannot be put in place leaves both sides as they were. The report says how each item was moved
+//! ```rust
+//! let report = ifop::FileOperation::new(None).move_to("/home/me/videos", "/mnt/usb").perform().unwrap();
+//! for item in &report.items {
+//!     // Some(MoveStrategy::CopyDelete), or Some(MoveStrategy::Rename) on the same file system
+//!     println!("{}: {:?}", item.path.display(), item.strategy);
+//! }
+//! ```
+//! 
+//! - Rename without replacing, checked and done in one step: a taken name is an `Error::Conflict`, never overwritten (Linux, Windows)
+//! ```rust
+//! ifop::rename_no_replace("/srv/app/config.next", "config").unwrap();
+//! ```
+//! 
+//! - Swap two items in one step, such as a blue/green switch of config folders (Linux)
+//! ```rust
+//! // Each path now holds what the other one held
+//! ifop::swap("/srv/app/config", "/srv/app/config.next").unwrap();
+//! ```
+//! 
 //! ## Command Usage
 //! 
 //! Compile examples
@@ -163,6 +388,16 @@
 //! ifop copy --src <filename|folder>,<filename|folder>... --dest <folder>
 //! ```
 //! 
+//! `Copy` single file under a new name
+//! ```console
+//! ifop copy --src <filename|folder> --dest <folder> --name <new_name>
+//! ```
+//! 
+//! `Copy` pairs listed in a TSV/CSV file, one `src<TAB>dest_folder[<TAB>new_name]` per line
+//! ```console
+//! ifop copy --pairs <pairs.tsv>
+//! ```
+//! 
 //! `Move` single file
 //! ```console
 //! ifop move --src <filename|folder> --dest <folder>
@@ -173,6 +408,16 @@
 //! ifop move --src <filename|folder>,<filename|folder>... --dest <folder>
 //! ```
 //! 
+//! `Move` single file under a new name
+//! ```console
+//! ifop move --src <filename|folder> --dest <folder> --name <new_name>
+//! ```
+//! 
+//! `Move` pairs listed in a TSV/CSV file
+//! ```console
+//! ifop move --pairs <pairs.csv>
+//! ```
+//! 
 //! `Rename` single file
 //! ```console
 //! ifop rename --src <filename|folder> --dest <folder>
@@ -203,28 +448,181 @@
 //! ifop new-folder --root <root_path> --name <name>
 //! ```
 //! 
+//! List the Recycle Bin / Trash: deletion date, size, original path, id
+//! ```console
+//! ifop trash list
+//! ```
+//! 
+//! Restore by id or original path, `--flags FOF_RENAMEONCOLLISION` if the path is taken
+//! ```console
+//! ifop trash restore <id|original_path>
+//! ```
+//! 
+//! Empty the trash, everything or what was deleted more than 30 days ago
+//! ```console
+//! ifop trash empty [--older-than 30d]
+//! ```
+//! 
+//! Undo the last operation, or the last N, from the journal kept by the command
+//! ```console
+//! ifop undo [N]
+//! ```
+//! 
+//! Redo the last undone operation, or the last N
+//! ```console
+//! ifop redo [N]
+//! ```
+//! 
+//! Recorded operations: id, time, command, item count, status
+//! ```console
+//! ifop history
+//! ```
+//! 
+//! Print what a command would do without doing it, `--json` for JSON; exits with 1 on conflicts
+//! ```console
+//! ifop <command> [options] --dry-run [--json]
+//! ```
+//! 
+//! Decide conflicts of copy, move and rename: overwrite, skip, keep-both, keep-both-dated,
+//! overwrite-if-newer, overwrite-if-different, overwrite-if-different-content or fail
+//! ```console
+//! ifop copy --src <filename|folder> --dest <folder> --on-conflict keep-both
+//! ```
+//! 
+//! Wildcards in --src and --target, `--ignore-case` to match names without regard to case
+//! ```console
+//! ifop delete --target "C:\logs\*.log" [--ignore-case]
+//! ```
+//! 
+//! Filters for the content of folders: gitignore patterns, sizes (10k, 5M, 1G), dates (YYYY-MM-DD or an age like 7d), attributes, `.ifopignore` files
+//! ```console
+//! ifop copy --src "C:\src\project" --dest "C:\backup" --exclude target/ --exclude .git/ [--include <pattern>] [--min-size <size>] [--max-size <size>] [--modified-after <time>] [--modified-before <time>] [--skip-hidden] [--skip-read-only] [--ifopignore]
+//! ```
+//! 
+//! Sync --dest with --src: `--content` to compare content instead of size and time, `--delete` for what --src does not have
+//! ```console
+//! ifop sync --src "C:\build" --dest "\\server\share\build" [--content] [--delete] [--flags FOF_ALLOWUNDO]
+//! ```
+//! 
+//! Sync both ways, the last synced tree kept next to the journal or in `--state <file>`
+//! ```console
+//! ifop sync --src "C:\work" --dest "\\server\share\work" --bidirectional [--on-conflict keep-both|overwrite-if-newer|...] [--state <file>]
+//! ```
+//! 
+//! Check copies against their source, blake3 by default, `--retries <N>` to copy a mismatching file again
+//! ```console
+//! ifop move --src "C:\photos" --dest "E:\backup" --verify [blake3|sha256] [--retries 2]
+//! ```
+//! 
+//! Write a manifest of the copied files (SHA256SUMS, B3SUMS or <name>.json), then check a folder against it
+//! ```console
+//! ifop copy --src "C:\build\bundle" --dest "D:\release" --write-manifest "D:\release\SHA256SUMS" [--checksum blake3|sha256]
+//! ifop verify --manifest "D:\release\SHA256SUMS" [--root <folder>]
+//! ```
+//! 
+//! Copy with a checkpoint: after an interruption, the same command goes on where it stopped
+//! ```console
+//! ifop copy --src "C:\vm\disk.vhdx" --dest "\\server\share\vm" --resume
+//! ```
+//! 
+//! Copy the files of folders with 8 threads instead of one per CPU, `--threads 1` for one file after the other (std::fs backend)
+//! ```console
+//! ifop copy --src "/src/node_modules" --dest "/cache" --threads 8
+//! ```
+//! 
+//! Clone on Btrfs/XFS when possible (auto, the default), only by cloning, or never (Linux)
+//! ```console
+//! ifop copy --src "/var/cache/build" --dest "/mnt/btrfs/cache" --reflink auto|always-reflink|never-reflink
+//! ```
+//! 
+//! Rename, failing instead of replacing when the new name is taken (Linux, Windows)
+//! ```console
+//! ifop rename --src "/srv/app/config.next" --dest "config" --no-replace
+//! ```
+//! 
+//! Swap two items in one step (Linux)
+//! ```console
+//! ifop swap --src "/srv/app/config" --dest "/srv/app/config.next"
+//! ```
+//! 
 //! With `--flags`
 //! ```console
 //! ifop <command> [options] --flags FOF_ALLOWUNDO|FOF_CONFIRMMOUSE|....
 //! ```
+//!
+//! The command exits with 1 when an item failed and with 2 when the user aborted all or part of the operation, even if other items got done.
+//! 
+//! Unknown flag names are rejected. The same syntax parses into [`OperationFlags`]:
+//! ```rust
+//! let flags: ifop::OperationFlags = "FOF_ALLOWUNDO|FOF_NOCONFIRMATION".parse().unwrap();
+//! assert_eq!(flags.to_string(), "FOF_ALLOWUNDO|FOF_NOCONFIRMATION");
+//! ```
 
-use windows::Win32::UI::Shell::*;
-use windows::Win32::System::Com::{ CoCreateInstance, CLSCTX_ALL};
-use windows::Win32::Storage::FileSystem::*;
-use windows::core::*;
-use windows_core::Result;
+#[cfg(windows)]
+mod shell;
+#[cfg(windows)]
+use shell as imp;
 
-unsafe fn get_item(target: &str) -> Result<IShellItem>{
-    SHCreateItemFromParsingName( &HSTRING::from(target), None)
-}
+#[cfg(not(windows))]
+mod stdfs;
+#[cfg(not(windows))]
+use stdfs as imp;
 
-unsafe fn get_operation(op: Option<FILEOPERATION_FLAGS>) -> Result<IFileOperation> {
-    let result:IFileOperation = CoCreateInstance(&FileOperation, None, CLSCTX_ALL)?;
-    if let Some(flags) = op {
-        result.SetOperationFlags(flags)?
-    }
-    Ok(result)
-}
+mod operation;
+pub use operation::{FileOperation, OperationKind};
+
+mod flags;
+pub use flags::{OperationFlags, ParseFlagsError};
+
+mod error;
+pub use error::{Error, Result};
+
+mod report;
+pub use report::{ItemOutcome, MoveStrategy, OperationReport, OperationStatus};
+
+mod progress;
+pub use progress::{Progress, ProgressSink};
+
+#[cfg(any(unix, windows))]
+mod trash;
+#[cfg(any(unix, windows))]
+pub use trash::{empty_trash, list_trash, restore_trash_item, TrashItem};
+
+mod plan;
+pub use plan::{Plan, PlannedAction};
+
+mod patterns;
+pub use patterns::expand_patterns;
+
+mod filter;
+pub use filter::{Filter, IGNORE_FILE};
+
+mod copy_options;
+pub use copy_options::{CopyOptions, ParseReflinkError, Reflink};
+
+#[cfg(target_os = "linux")]
+mod fastcopy;
+
+mod verify;
+pub use verify::{Checksum, ParseChecksumError};
+
+mod manifest;
+pub use manifest::{Manifest, ManifestEntry, ManifestReport};
+
+mod resume;
+pub use resume::copy_resume;
+
+mod atomic;
+pub use atomic::{rename_no_replace, swap};
+
+mod sync;
+pub use sync::{sync, sync_bidirectional, sync_bidirectional_operation, sync_operation, SyncOptions};
+
+mod conflict;
+pub use conflict::{ConflictPolicy, Difference, KeepBothNaming, ParseConflictPolicyError};
+
+mod journal;
+pub use journal::{enable_journal, history, redo, redo_last, undo, undo_last, Fingerprint, JournalAction, JournalEntry, Merged};
 
 /// ### Copy multiple `files`
 /// ```
@@ -259,15 +657,31 @@ unsafe fn get_operation(op: Option<FILEOPERATION_FLAGS>) -> Result<IFileOperatio
 ///     }
 /// }
 /// ```
-pub fn copy_files(src: Vec<&str>, dest: &str, flags: Option<FILEOPERATION_FLAGS>) -> Result<()> {
-    unsafe {
-        let operation = get_operation(flags)?;
+/// The files of folders are copied with one worker per CPU, see [`CopyOptions`].
+pub fn copy_files(src: Vec<&str>, dest: &str, flags: Option<OperationFlags>) -> Result<()> {
+    copy_files_with(src, dest, &CopyOptions::default(), flags)
+}
 
-        for dir in src {
-            operation.CopyItem(&get_item(dir)?, &get_item(dest)?, None, None)?;
-        }
-        operation.PerformOperations()
+/// ### Copy multiple `folders` with other [`CopyOptions`]
+/// ```rust
+/// let options = ifop::CopyOptions::new().threads(8).queue(4096);
+///
+/// match ifop::copy_files_with(vec!["c:\\src\\folder1", "c:\\src\\folder2"], "c:\\dest", &options, None) {
+///     Ok(_) => {
+///         println!("Success");
+///     }
+///     Err(e) => {
+///         println!("{}", e);
+///     }
+/// }
+/// ```
+pub fn copy_files_with(src: Vec<&str>, dest: &str, options: &CopyOptions, flags: Option<OperationFlags>) -> Result<()> {
+    let mut operation = FileOperation::new(flags);
+    operation.copy_options(options.clone());
+    for file in src {
+        operation.copy(file, dest);
     }
+    operation.perform()?.into_result()
 }
 
 /// ### Copy one `file`
@@ -293,13 +707,74 @@ pub fn copy_files(src: Vec<&str>, dest: &str, flags: Option<FILEOPERATION_FLAGS>
 ///     }
 /// }
 /// ```
-pub fn copy_file(src: &str, dest: &str, flags: Option<FILEOPERATION_FLAGS>) -> Result<()> {
-    unsafe {
-        let operation = get_operation(flags)?;
+/// The files of a folder are copied with one worker per CPU, see [`CopyOptions`].
+pub fn copy_file(src: &str, dest: &str, flags: Option<OperationFlags>) -> Result<()> {
+    copy_file_with(src, dest, &CopyOptions::default(), flags)
+}
+
+/// ### Copy one `folder` with other [`CopyOptions`]
+/// ```rust
+/// let options = ifop::CopyOptions::new().threads(2);
+///
+/// match ifop::copy_file_with("c:\\src\\node_modules", "c:\\dest", &options, None) {
+///     Ok(_) => {
+///         println!("Success");
+///     }
+///     Err(e) => {
+///         println!("{}", e);
+///     }
+/// }
+/// ```
+pub fn copy_file_with(src: &str, dest: &str, options: &CopyOptions, flags: Option<OperationFlags>) -> Result<()> {
+    FileOperation::new(flags).copy_options(options.clone()).copy(src, dest).perform()?.into_result()
+}
 
-        operation.CopyItem(&get_item(src)?,&get_item(dest)?, None, None)?;
-        operation.PerformOperations()
+/// ### Copy one `file` under a new name
+/// ```rust
+/// match ifop::copy_file_as("c:\\src\\report.xlsx", "\\\\share\\archive", "report-2026-10.xlsx", None) {
+///     Ok(_) => {
+///         println!("Success");
+///     }
+///     Err(e) => {
+///         println!("{}", e);
+///     }
+/// }
+/// ```
+pub fn copy_file_as(src: &str, dest: &str, new_name: &str, flags: Option<OperationFlags>) -> Result<()> {
+    FileOperation::new(flags).copy_as(src, dest, new_name).perform()?.into_result()
+}
+
+/// ### Copy each `src` into its own destination folder
+/// Every tuple is `(src, dest_folder, new_name)`; all pairs run as one operation,
+/// like `FOF_MULTIDESTFILES` with `SHFileOperation`.
+/// ```rust
+/// let pairs = vec![
+///     ("c:\\build\\app.exe", "\\\\server1\\deploy", None),
+///     ("c:\\build\\app.pdb", "\\\\server1\\symbols", None),
+///     ("c:\\build\\app.cfg", "\\\\server2\\deploy", Some("app.prod.cfg")),
+/// ];
+///
+/// match ifop::copy_pairs(pairs, None) {
+///     Ok(_) => {
+///         println!("Success");
+///     }
+///     Err(e) => {
+///         println!("{}", e);
+///     }
+/// }
+/// ```
+pub fn copy_pairs<'a, I>(pairs: I, flags: Option<OperationFlags>) -> Result<()>
+where
+    I: IntoIterator<Item = (&'a str, &'a str, Option<&'a str>)>,
+{
+    let mut operation = FileOperation::new(flags);
+    for (src, dest, name) in pairs {
+        match name {
+            Some(name) => operation.copy_as(src, dest, name),
+            None => operation.copy(src, dest),
+        };
     }
+    operation.perform()?.into_result()
 }
 
 
@@ -327,13 +802,8 @@ pub fn copy_file(src: &str, dest: &str, flags: Option<FILEOPERATION_FLAGS>) -> R
 ///     }
 /// }
 /// ```
-pub fn delete_file(target: &str, flags: Option<FILEOPERATION_FLAGS>) -> Result<()> {
-    unsafe {
-        let operation = get_operation(flags)?;
-
-        operation.DeleteItem(&get_item(target)?, None)?;
-        operation.PerformOperations()
-    }
+pub fn delete_file(target: &str, flags: Option<OperationFlags>) -> Result<()> {
+    FileOperation::new(flags).delete(target).perform()?.into_result()
 }
 
 /// ### Delete multiple `files`
@@ -370,15 +840,12 @@ pub fn delete_file(target: &str, flags: Option<FILEOPERATION_FLAGS>) -> Result<(
 ///     }
 /// }
 /// ```
-pub fn delete_files(targets: Vec<&str>, flags: Option<FILEOPERATION_FLAGS>) -> Result<()> {
-    unsafe {
-        let operation = get_operation(flags)?;
-
-        for target in targets {
-            operation.DeleteItem(&get_item(target)?, None)?;
-        }
-        operation.PerformOperations()
+pub fn delete_files(targets: Vec<&str>, flags: Option<OperationFlags>) -> Result<()> {
+    let mut operation = FileOperation::new(flags);
+    for target in targets {
+        operation.delete(target);
     }
+    operation.perform()?.into_result()
 }
 
 /// ### Rename one `file`
@@ -405,13 +872,8 @@ pub fn delete_files(targets: Vec<&str>, flags: Option<FILEOPERATION_FLAGS>) -> R
 /// }
 /// 
 /// ```
-pub fn rename_file(src: &str, dest: &str, flags: Option<FILEOPERATION_FLAGS>) -> Result<()> {
-    unsafe {
-        let operation = get_operation(flags)?;
-
-        operation.RenameItem(&get_item(src)?, &HSTRING::from(dest), None)?;
-        operation.PerformOperations()
-    }
+pub fn rename_file(src: &str, dest: &str, flags: Option<OperationFlags>) -> Result<()> {
+    FileOperation::new(flags).rename(src, dest).perform()?.into_result()
 }
 
 /// ### Rename multiple `files`
@@ -449,15 +911,12 @@ pub fn rename_file(src: &str, dest: &str, flags: Option<FILEOPERATION_FLAGS>) ->
 /// }
 /// 
 /// ```
-pub fn rename_files(targets: Vec<&str>, new_name: &str, flags: Option<FILEOPERATION_FLAGS>) -> Result<()> {
-    unsafe {
-        let operation = get_operation(flags)?;
-
-        for target in targets {
-            operation.RenameItem(&get_item(target)?, &HSTRING::from(new_name), None)?;
-        }
-        operation.PerformOperations()
+pub fn rename_files(targets: Vec<&str>, new_name: &str, flags: Option<OperationFlags>) -> Result<()> {
+    let mut operation = FileOperation::new(flags);
+    for target in targets {
+        operation.rename(target, new_name);
     }
+    operation.perform()?.into_result()
 }
 
 /// ### Move one `file`
@@ -484,13 +943,54 @@ pub fn rename_files(targets: Vec<&str>, new_name: &str, flags: Option<FILEOPERAT
 /// }
 /// 
 /// ```
-pub fn move_file(src: &str, dest: &str, flags: Option<FILEOPERATION_FLAGS>) -> Result<()> {
-    unsafe {
-        let operation = get_operation(flags)?;
+pub fn move_file(src: &str, dest: &str, flags: Option<OperationFlags>) -> Result<()> {
+    FileOperation::new(flags).move_to(src, dest).perform()?.into_result()
+}
 
-        operation.MoveItem(&get_item(src)?, &get_item(dest)?, None, None)?;
-        operation.PerformOperations()
+/// ### Move one `file` under a new name
+/// ```rust
+/// match ifop::move_file_as("c:\\src\\report.xlsx", "\\\\share\\archive", "report-2026-10.xlsx", None) {
+///     Ok(_) => {
+///         println!("Success");
+///     }
+///     Err(e) => {
+///         println!("{}", e);
+///     }
+/// }
+/// ```
+pub fn move_file_as(src: &str, dest: &str, new_name: &str, flags: Option<OperationFlags>) -> Result<()> {
+    FileOperation::new(flags).move_as(src, dest, new_name).perform()?.into_result()
+}
+
+/// ### Move each `src` into its own destination folder
+/// Every tuple is `(src, dest_folder, new_name)`; all pairs run as one operation.
+/// ```rust
+/// let pairs = vec![
+///     ("c:\\inbox\\a.pdf", "c:\\invoices", None),
+///     ("c:\\inbox\\b.pdf", "c:\\receipts", Some("2026-10-18.pdf")),
+/// ];
+///
+/// match ifop::move_pairs(pairs, None) {
+///     Ok(_) => {
+///         println!("Success");
+///     }
+///     Err(e) => {
+///         println!("{}", e);
+///     }
+/// }
+/// ```
+pub fn move_pairs<'a, I>(pairs: I, flags: Option<OperationFlags>) -> Result<()>
+where
+    I: IntoIterator<Item = (&'a str, &'a str, Option<&'a str>)>,
+{
+    let mut operation = FileOperation::new(flags);
+    for (src, dest, name) in pairs {
+        match name {
+            Some(name) => operation.move_as(src, dest, name),
+            None => operation.move_to(src, dest),
+        };
     }
+    operation.perform()?.into_result()
 }
 
 
@@ -528,15 +1028,12 @@ pub fn move_file(src: &str, dest: &str, flags: Option<FILEOPERATION_FLAGS>) -> R
 /// }
 /// 
 /// ```
-pub fn move_files(src: Vec<&str>, dest: &str, flags: Option<FILEOPERATION_FLAGS>) -> Result<()> {
-    unsafe {
-        let operation = get_operation(flags)?;
-
-        for dir in src {
-            operation.MoveItem(&get_item(dir)?, &get_item(dest)?, None, None)?;
-        }
-        operation.PerformOperations()
+pub fn move_files(src: Vec<&str>, dest: &str, flags: Option<OperationFlags>) -> Result<()> {
+    let mut operation = FileOperation::new(flags);
+    for file in src {
+        operation.move_to(file, dest);
     }
+    operation.perform()?.into_result()
 }
 
 /// ### Create `folder`
@@ -551,17 +1048,8 @@ pub fn move_files(src: Vec<&str>, dest: &str, flags: Option<FILEOPERATION_FLAGS>
 /// }
 /// 
 /// ```
-pub fn create_folder(target: &str, name: &str, flags: Option<FILEOPERATION_FLAGS>) -> Result<()> {
-    unsafe {
-        let operation = get_operation(flags)?;
-
-        operation.NewItem(
-            &get_item(target)?, 
-            FILE_ATTRIBUTE_DIRECTORY.0, 
-            &HSTRING::from(name), 
-            None, None)?;
-        operation.PerformOperations()
-    }
+pub fn create_folder(target: &str, name: &str, flags: Option<OperationFlags>) -> Result<()> {
+    FileOperation::new(flags).new_folder(target, name).perform()?.into_result()
 }
 
 /// ### Create `folder`
@@ -576,96 +1064,206 @@ pub fn create_folder(target: &str, name: &str, flags: Option<FILEOPERATION_FLAGS
 /// }
 /// 
 /// ```
-pub fn create_file(target: &str, name: &str, flags: Option<FILEOPERATION_FLAGS>) -> Result<()> {
-    unsafe {
-        let operation = get_operation(flags)?;
-
-        operation.NewItem(
-            &get_item(target)?, 
-            FILE_ATTRIBUTE_NORMAL.0, 
-            &HSTRING::from(name), 
-            None, None)?;
-        operation.PerformOperations()
-    }
+pub fn create_file(target: &str, name: &str, flags: Option<OperationFlags>) -> Result<()> {
+    FileOperation::new(flags).new_file(target, name).perform()?.into_result()
 }
 
 #[cfg(test)]
 mod tests {
     use crate::*;
+    #[cfg(windows)]
     use windows::Win32::System::Com::{COINIT_MULTITHREADED, CoInitializeEx};
 
+    fn init() {
+        #[cfg(windows)]
+        let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+    }
+
+    fn join(root: &str, name: &str) -> String {
+        std::path::Path::new(root).join(name).to_str().unwrap().to_owned()
+    }
+
     #[test]
     fn test_new_folder() {
-        let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+        init();
 
         let curr_dir = std::env::current_dir().unwrap();
-        let root_dir = &format!("{}\\test", curr_dir.to_str().unwrap());
-        assert_eq!(create_folder(root_dir, "new_folder", None),  Ok(()));
-        assert_eq!(delete_file(&format!("{}\\new_folder", root_dir), None),  Ok(()));
+        let root_dir = &join(curr_dir.to_str().unwrap(), "test");
+        create_folder(root_dir, "new_folder", None).unwrap();
+        delete_file(&join(root_dir, "new_folder"), None).unwrap();
     }
 
     #[test]
     fn test_new_file() {
-        let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+        init();
 
         let curr_dir = std::env::current_dir().unwrap();
-        let root_dir = &format!("{}\\test", curr_dir.to_str().unwrap());
-        assert_eq!(create_file(root_dir, "new_file", None),  Ok(()));
-        assert_eq!(delete_file(&format!("{}\\new_file", root_dir), None),  Ok(()));
+        let root_dir = &join(curr_dir.to_str().unwrap(), "test");
+        create_file(root_dir, "new_file", None).unwrap();
+        delete_file(&join(root_dir, "new_file"), None).unwrap();
     }
 
     #[test]
     fn test_copy() {
-        let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+        init();
 
         let curr_dir = std::env::current_dir().unwrap();
-        let root_dir = &format!("{}\\test", curr_dir.to_str().unwrap());
-        let folder = &format!("{}\\test_copy", root_dir);
-        let src = &format!("{}\\src", folder);
-        let dest = &format!("{}\\dest", folder);
-        let src_folder = &format!("{}\\folder", src);
-        assert_eq!(create_folder(root_dir, "test_copy", None),  Ok(()));
-        assert_eq!(create_folder(folder, "src", None),  Ok(()));
-        assert_eq!(create_folder(folder, "dest", None),  Ok(()));
-        assert_eq!(create_folder(src, "folder", None),  Ok(()));
-        assert_eq!(create_file(src_folder, "file1", None),  Ok(()));
-        assert_eq!(create_file(src_folder, "file2", None),  Ok(()));
-        assert_eq!(copy_file(src_folder, dest, None),  Ok(()));
-        assert_eq!(delete_file(folder, None),  Ok(()));
+        let root_dir = &join(curr_dir.to_str().unwrap(), "test");
+        let folder = &join(root_dir, "test_copy");
+        let src = &join(folder, "src");
+        let dest = &join(folder, "dest");
+        let src_folder = &join(src, "folder");
+        create_folder(root_dir, "test_copy", None).unwrap();
+        create_folder(folder, "src", None).unwrap();
+        create_folder(folder, "dest", None).unwrap();
+        create_folder(src, "folder", None).unwrap();
+        create_file(src_folder, "file1", None).unwrap();
+        create_file(src_folder, "file2", None).unwrap();
+        copy_file(src_folder, dest, None).unwrap();
+        delete_file(folder, None).unwrap();
     }
 
     #[test]
     fn test_move() {
-        let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+        init();
 
         let curr_dir = std::env::current_dir().unwrap();
-        let root_dir = &format!("{}\\test", curr_dir.to_str().unwrap());
-        let folder = &format!("{}\\test_move", root_dir);
-        let src = &format!("{}\\src", folder);
-        let dest = &format!("{}\\dest", folder);
-        let src_folder = &format!("{}\\folder", src);
-        assert_eq!(create_folder(root_dir, "test_move", None),  Ok(()));
-        assert_eq!(create_folder(folder, "src", None),  Ok(()));
-        assert_eq!(create_folder(folder, "dest", None),  Ok(()));
-        assert_eq!(create_folder(src, "folder", None),  Ok(()));
-        assert_eq!(create_file(src_folder, "file1", None),  Ok(()));
-        assert_eq!(create_file(src_folder, "file2", None),  Ok(()));
-        assert_eq!(move_file(src_folder, dest, None),  Ok(()));
-        assert_eq!(delete_file(folder, None),  Ok(()));
+        let root_dir = &join(curr_dir.to_str().unwrap(), "test");
+        let folder = &join(root_dir, "test_move");
+        let src = &join(folder, "src");
+        let dest = &join(folder, "dest");
+        let src_folder = &join(src, "folder");
+        create_folder(root_dir, "test_move", None).unwrap();
+        create_folder(folder, "src", None).unwrap();
+        create_folder(folder, "dest", None).unwrap();
+        create_folder(src, "folder", None).unwrap();
+        create_file(src_folder, "file1", None).unwrap();
+        create_file(src_folder, "file2", None).unwrap();
+        move_file(src_folder, dest, None).unwrap();
+        delete_file(folder, None).unwrap();
     }
 
 
     #[test]
     fn test_rename() {
-        let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+        init();
 
         let curr_dir = std::env::current_dir().unwrap();
-        let root_dir = &format!("{}\\test", curr_dir.to_str().unwrap());
-        let folder = &format!("{}\\test_rename", root_dir);
-        let src = &format!("{}\\src", folder);
-        assert_eq!(create_folder(root_dir, "test_rename", None),  Ok(()));
-        assert_eq!(create_folder(folder, "src", None),  Ok(()));
-        assert_eq!(rename_file(src, "dest", None),  Ok(()));
-        assert_eq!(delete_file(folder, None),  Ok(()));
+        let root_dir = &join(curr_dir.to_str().unwrap(), "test");
+        let folder = &join(root_dir, "test_rename");
+        let src = &join(folder, "src");
+        create_folder(root_dir, "test_rename", None).unwrap();
+        create_folder(folder, "src", None).unwrap();
+        // A name, not a path out of the folder.
+        for name in ["../dest", "sub/dest", ".."] {
+            assert_eq!(rename_file(src, name, None), Err(Error::InvalidPath { path: src.into(), op: OperationKind::Rename }));
+        }
+        rename_file(src, "dest", None).unwrap();
+        delete_file(folder, None).unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_batch() {
+        init();
+
+        let curr_dir = std::env::current_dir().unwrap();
+        let root_dir = &join(curr_dir.to_str().unwrap(), "test");
+        let folder = &join(root_dir, "test_batch");
+        let old = &join(folder, "old");
+        let archive = &join(folder, "archive");
+        create_folder(root_dir, "test_batch", None).unwrap();
+        create_folder(folder, "old", None).unwrap();
+        create_file(old, "file1", None).unwrap();
+        create_file(old, "file2", None).unwrap();
+        FileOperation::new(None)
+            .new_folder(folder, "archive")
+            .copy(&join(old, "file1"), archive)
+            .copy(&join(old, "file2"), archive)
+            .delete(old)
+            .perform()
+            .unwrap();
+        assert!(std::path::Path::new(&join(archive, "file1")).exists());
+        assert!(std::path::Path::new(&join(archive, "file2")).exists());
+        assert!(!std::path::Path::new(old).exists());
+        delete_file(folder, None).unwrap();
+    }
+
+    #[test]
+    fn test_copy_as() {
+        init();
+
+        let curr_dir = std::env::current_dir().unwrap();
+        let root_dir = &join(curr_dir.to_str().unwrap(), "test");
+        let folder = &join(root_dir, "test_copy_as");
+        let src = &join(folder, "src");
+        let dest = &join(folder, "dest");
+        create_folder(root_dir, "test_copy_as", None).unwrap();
+        create_folder(folder, "src", None).unwrap();
+        create_folder(folder, "dest", None).unwrap();
+        create_file(src, "report.xlsx", None).unwrap();
+        copy_file_as(&join(src, "report.xlsx"), dest, "report-2026-10.xlsx", None).unwrap();
+        move_file_as(&join(src, "report.xlsx"), dest, "report-moved.xlsx", None).unwrap();
+        assert!(std::path::Path::new(&join(dest, "report-2026-10.xlsx")).exists());
+        assert!(std::path::Path::new(&join(dest, "report-moved.xlsx")).exists());
+        assert!(!std::path::Path::new(&join(src, "report.xlsx")).exists());
+        delete_file(folder, None).unwrap();
+    }
+
+    #[test]
+    fn test_copy_pairs() {
+        init();
+
+        let curr_dir = std::env::current_dir().unwrap();
+        let root_dir = &join(curr_dir.to_str().unwrap(), "test");
+        let folder = &join(root_dir, "test_copy_pairs");
+        let src = &join(folder, "src");
+        let dest1 = &join(folder, "dest1");
+        let dest2 = &join(folder, "dest2");
+        create_folder(root_dir, "test_copy_pairs", None).unwrap();
+        create_folder(folder, "src", None).unwrap();
+        create_folder(folder, "dest1", None).unwrap();
+        create_folder(folder, "dest2", None).unwrap();
+        create_file(src, "file1", None).unwrap();
+        create_file(src, "file2", None).unwrap();
+        let file1 = &join(src, "file1");
+        let file2 = &join(src, "file2");
+        copy_pairs(vec![
+            (file1.as_str(), dest1.as_str(), None),
+            (file2.as_str(), dest2.as_str(), Some("renamed")),
+        ], None).unwrap();
+        move_pairs(vec![(file1.as_str(), dest2.as_str(), None)], None).unwrap();
+        assert!(std::path::Path::new(&join(dest1, "file1")).exists());
+        assert!(std::path::Path::new(&join(dest2, "renamed")).exists());
+        assert!(std::path::Path::new(&join(dest2, "file1")).exists());
+        assert!(!std::path::Path::new(file1).exists());
+        delete_file(folder, None).unwrap();
+    }
+
+    #[test]
+    fn test_report() {
+        init();
+
+        let curr_dir = std::env::current_dir().unwrap();
+        let root_dir = &join(curr_dir.to_str().unwrap(), "test");
+        let folder = &join(root_dir, "test_report");
+        let missing = &join(folder, "missing");
+        create_folder(root_dir, "test_report", None).unwrap();
+        create_file(folder, "file1", None).unwrap();
+        create_file(folder, "file2", None).unwrap();
+        let report = FileOperation::new(None)
+            .delete(&join(folder, "file1"))
+            .delete(missing)
+            .delete(&join(folder, "file2"))
+            .perform()
+            .unwrap();
+        assert_eq!(report.items.len(), 3);
+        assert_eq!(report.status(), OperationStatus::Partial);
+        assert!(report.items[0].result.is_ok());
+        assert!(report.items[2].result.is_ok());
+        let error = Error::NotFound { path: missing.into(), op: OperationKind::Delete };
+        assert_eq!(report.failures().next().unwrap().result, Err(error.clone()));
+        assert_eq!(delete_file(missing, None), Err(error));
+        assert!(!std::path::Path::new(&join(folder, "file2")).exists());
+        delete_file(folder, None).unwrap();
+    }
+}
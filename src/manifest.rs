@@ -0,0 +1,248 @@
+//! Checksum manifests: one line per file in the `SHA256SUMS` format that
+//! `sha256sum -c` and `b3sum -c` read, or JSON with sizes when the file name
+//! ends in `.json`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::filter::entries;
+use crate::verify::hash_file;
+use crate::{Checksum, Error, Filter, OperationKind, OperationReport, Result};
+
+/// One file of a [`Manifest`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Relative to the manifest's folder when the file is inside it, `/`
+    /// separated.
+    pub path: String,
+    /// Not in the `SHA256SUMS` format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Lowercase hex.
+    pub hash: String,
+}
+
+/// ### Files with their checksum
+/// ```rust
+/// let manifest = ifop::Manifest::create("c:\\release", ifop::Checksum::Sha256).unwrap();
+/// manifest.write("c:\\release\\SHA256SUMS").unwrap();
+///
+/// let report = ifop::Manifest::read("c:\\release\\SHA256SUMS").unwrap().verify("c:\\release").unwrap();
+/// for path in &report.corrupted {
+///     println!("corrupted: {}", path.display());
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub checksum: Checksum,
+    #[serde(rename = "files")]
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// What [`Manifest::verify`] found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ManifestReport {
+    /// Files that match the manifest.
+    pub verified: usize,
+    /// In the manifest, not on disk.
+    pub missing: Vec<PathBuf>,
+    /// On disk under the checked folder, not in the manifest.
+    pub extra: Vec<PathBuf>,
+    /// Another size or checksum than in the manifest.
+    pub corrupted: Vec<PathBuf>,
+}
+
+impl ManifestReport {
+    /// `true` when nothing is missing, extra or corrupted.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.corrupted.is_empty()
+    }
+}
+
+fn manifest_error(e: io::Error, path: &Path) -> Error {
+    Error::Platform { path: Some(path.to_owned()), op: None, code: e.raw_os_error().unwrap_or(0), message: e.to_string() }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+fn key(relative: &Path) -> String {
+    relative.iter().map(|part| part.to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+/// Every file of `path`, itself when it is one.
+fn files(path: &Path) -> Vec<PathBuf> {
+    match path.is_dir() {
+        true => entries(&Filter::default(), path).into_iter().filter(|(_, dir)| !dir).map(|(relative, _)| path.join(relative)).collect(),
+        false => vec![path.to_owned()],
+    }
+}
+
+impl Manifest {
+    /// The checksum a text manifest named like `path` holds: `B3SUMS` and
+    /// the like are BLAKE3, the other names SHA-256.
+    pub fn checksum_for(path: &str) -> Checksum {
+        let name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+        if name.contains("b3") || name.contains("blake3") { Checksum::Blake3 } else { Checksum::Sha256 }
+    }
+
+    /// The checksums of `files`, with their paths relative to `base` when
+    /// they are inside it.
+    fn of(files: &[PathBuf], base: &Path, checksum: Checksum) -> Result<Manifest> {
+        let mut entries = Vec::new();
+        for file in files {
+            let meta = fs::metadata(file).map_err(|e| Error::from_io(e, file, OperationKind::Copy))?;
+            let hash = hash_file(file, checksum).map_err(|e| Error::from_io(e, file, OperationKind::Copy))?;
+            let path = match file.strip_prefix(base) {
+                Ok(relative) => key(relative),
+                Err(_) => file.to_string_lossy().into_owned(),
+            };
+            entries.push(ManifestEntry { path, size: Some(meta.len()), hash });
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Manifest { checksum, entries })
+    }
+
+    /// Every file below `root`, relative to it.
+    pub fn create(root: &str, checksum: Checksum) -> Result<Manifest> {
+        let root = Path::new(root);
+        if !root.is_dir() {
+            return Err(Error::NotFound { path: root.to_owned(), op: OperationKind::Copy });
+        }
+        Manifest::of(&files(root), root, checksum)
+    }
+
+    /// A `SHA256SUMS` style file, or JSON when the name ends in `.json`. The
+    /// checksum of the text format comes from the name: `B3SUMS` is BLAKE3.
+    pub fn read(path: &str) -> Result<Manifest> {
+        let path = Path::new(path);
+        let content = fs::read_to_string(path).map_err(|e| manifest_error(e, path))?;
+        if is_json(path) {
+            return serde_json::from_str(&content).map_err(|e| manifest_error(e.into(), path));
+        }
+        let mut entries = Vec::new();
+        for line in content.lines().map(str::trim_end).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            // `<hash>  <path>`, or `<hash> *<path>` for binary mode.
+            let parsed = line.split_once(' ').map(|(hash, rest)| (hash, rest.strip_prefix([' ', '*']).unwrap_or(rest)));
+            let Some((hash, file)) = parsed.filter(|(hash, _)| hash.chars().all(|c| c.is_ascii_hexdigit())) else {
+                let message = format!("not a checksum line: {}", line);
+                return Err(manifest_error(io::Error::new(io::ErrorKind::InvalidData, message), path));
+            };
+            entries.push(ManifestEntry { path: file.to_owned(), size: None, hash: hash.to_ascii_lowercase() });
+        }
+        Ok(Manifest { checksum: Manifest::checksum_for(&path.to_string_lossy()), entries })
+    }
+
+    /// Write the manifest to `path`, in the format its name asks for.
+    pub fn write(&self, path: &str) -> Result<()> {
+        let path = Path::new(path);
+        let content = match is_json(path) {
+            true => serde_json::to_string_pretty(self).map_err(|e| manifest_error(e.into(), path))? + "\n",
+            false => self.entries.iter().map(|entry| format!("{}  {}\n", entry.hash, entry.path)).collect(),
+        };
+        fs::write(path, content).map_err(|e| manifest_error(e, path))
+    }
+
+    /// Check the files below `root` against the manifest. Files of `root`
+    /// that are not in it count as extra, except manifests: files named like
+    /// `SHA256SUMS`, `B3SUMS` or ending in `.json` at the top of `root`.
+    pub fn verify(&self, root: &str) -> Result<ManifestReport> {
+        let root = Path::new(root);
+        let mut report = ManifestReport::default();
+        let at = |path: &str| match Path::new(path).is_absolute() {
+            true => PathBuf::from(path),
+            false => path.split('/').fold(root.to_owned(), |path, part| path.join(part)),
+        };
+        let expected: BTreeMap<PathBuf, &ManifestEntry> = self.entries.iter().map(|entry| (at(&entry.path), entry)).collect();
+        for (path, entry) in &expected {
+            let Ok(meta) = fs::metadata(path) else {
+                report.missing.push(path.clone());
+                continue;
+            };
+            if entry.size.is_some_and(|size| size != meta.len()) {
+                report.corrupted.push(path.clone());
+                continue;
+            }
+            match hash_file(path, self.checksum) {
+                Ok(hash) if hash.eq_ignore_ascii_case(&entry.hash) => report.verified += 1,
+                Ok(_) => report.corrupted.push(path.clone()),
+                Err(e) => return Err(Error::from_io(e, path, OperationKind::Copy)),
+            }
+        }
+        let known: BTreeSet<&PathBuf> = expected.keys().collect();
+        for file in files(root) {
+            let manifest = file.parent() == Some(root) && (is_json(&file) || file.file_name().is_some_and(|name| {
+                name.to_string_lossy().to_ascii_uppercase().ends_with("SUMS")
+            }));
+            if !manifest && !known.contains(&file) {
+                report.extra.push(file);
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// The manifest of what the operation copied or moved, written to `path`.
+pub(crate) fn emit(report: &OperationReport, path: &str, checksum: Checksum) -> Result<()> {
+    let written: Vec<PathBuf> = report.items.iter()
+        .filter(|item| matches!(item.op, OperationKind::Copy | OperationKind::Move) && item.result.is_ok())
+        .filter_map(|item| item.result_path.as_deref())
+        .flat_map(files)
+        .collect();
+    let path_buf = PathBuf::from(path);
+    let base = path_buf.parent().unwrap_or(Path::new(""));
+    Manifest::of(&written, base, checksum)?.write(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileOperation;
+
+    #[test]
+    fn test_manifest() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_manifest");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("src").join("sub")).unwrap();
+        fs::create_dir_all(folder.join("release")).unwrap();
+        fs::write(folder.join("src").join("a"), "content").unwrap();
+        fs::write(folder.join("src").join("sub").join("b"), "other").unwrap();
+
+        let release = folder.join("release");
+        let sums = release.join("SHA256SUMS");
+        FileOperation::new(None)
+            .manifest(sums.to_str().unwrap(), Checksum::Sha256)
+            .copy(folder.join("src").join("a").to_str().unwrap(), release.to_str().unwrap())
+            .copy(folder.join("src").join("sub").to_str().unwrap(), release.to_str().unwrap())
+            .perform()
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&sums).unwrap(),
+            "ed7002b439e9ac845f22357d822bac1444730fbdb6016d3ec9432297b9ec9f73  a\n\
+             d9298a10d1b0735837dc4bd85dac641b0f3cef27a47e5d53a54f2f3f5b2fcffa  sub/b\n"
+        );
+
+        let manifest = Manifest::read(sums.to_str().unwrap()).unwrap();
+        assert!(manifest.verify(release.to_str().unwrap()).unwrap().is_ok());
+
+        fs::write(release.join("a"), "changed").unwrap();
+        fs::remove_file(release.join("sub").join("b")).unwrap();
+        fs::write(release.join("new"), "").unwrap();
+        let report = manifest.verify(release.to_str().unwrap()).unwrap();
+        assert_eq!(report.corrupted, vec![release.join("a")]);
+        assert_eq!(report.missing, vec![release.join("sub").join("b")]);
+        assert_eq!(report.extra, vec![release.join("new")]);
+
+        // JSON keeps sizes, a different size is corrupted without hashing.
+        let json = release.join("manifest.json");
+        let created = Manifest::create(release.to_str().unwrap(), Checksum::Blake3).unwrap();
+        created.write(json.to_str().unwrap()).unwrap();
+        assert_eq!(Manifest::read(json.to_str().unwrap()).unwrap(), created);
+        assert_eq!(created.entries.iter().map(|entry| entry.path.as_str()).collect::<Vec<_>>(), vec!["SHA256SUMS", "a", "new"]);
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
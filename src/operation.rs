@@ -0,0 +1,323 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::conflict::{self, Resolved};
+use crate::{imp, Checksum, ConflictPolicy, CopyOptions, Filter, OperationFlags, OperationReport, Plan, ProgressSink, Result};
+
+/// The kind of a queued step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    Copy,
+    Move,
+    Delete,
+    Rename,
+    NewFolder,
+    NewFile,
+}
+
+impl fmt::Display for OperationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OperationKind::Copy => "copy",
+            OperationKind::Move => "move",
+            OperationKind::Delete => "delete",
+            OperationKind::Rename => "rename",
+            OperationKind::NewFolder => "new folder",
+            OperationKind::NewFile => "new file",
+        })
+    }
+}
+
+/// One queued step of a [`FileOperation`].
+#[derive(Clone, Debug)]
+pub(crate) enum Step {
+    Copy { src: String, dest: String, name: Option<String> },
+    Move { src: String, dest: String, name: Option<String> },
+    Delete { target: String },
+    Rename { src: String, new_name: String },
+    NewFolder { root: String, name: String },
+    NewFile { root: String, name: String },
+}
+
+impl Step {
+    pub(crate) fn kind(&self) -> OperationKind {
+        match self {
+            Step::Copy { .. } => OperationKind::Copy,
+            Step::Move { .. } => OperationKind::Move,
+            Step::Delete { .. } => OperationKind::Delete,
+            Step::Rename { .. } => OperationKind::Rename,
+            Step::NewFolder { .. } => OperationKind::NewFolder,
+            Step::NewFile { .. } => OperationKind::NewFile,
+        }
+    }
+
+    /// The item the step is about: the source, or the item to create.
+    pub(crate) fn path(&self) -> PathBuf {
+        match self {
+            Step::Copy { src, .. } | Step::Move { src, .. } | Step::Rename { src, .. } => PathBuf::from(src),
+            Step::Delete { target } => PathBuf::from(target),
+            Step::NewFolder { root, name } | Step::NewFile { root, name } => Path::new(root).join(name),
+        }
+    }
+
+    /// Where the step puts its item, `None` for a delete.
+    pub(crate) fn target(&self) -> Option<PathBuf> {
+        match self {
+            Step::Copy { src, dest, name } | Step::Move { src, dest, name } => {
+                let name = name.as_deref().map(Path::new).or_else(|| Path::new(src).file_name().map(Path::new))?;
+                Some(Path::new(dest).join(name))
+            }
+            Step::Rename { src, new_name } => Some(Path::new(src).with_file_name(new_name)),
+            Step::Delete { .. } => None,
+            Step::NewFolder { .. } | Step::NewFile { .. } => Some(self.path()),
+        }
+    }
+}
+
+/// `name` is one file name that a rename can give: no separator, not `.` or
+/// `..`.
+pub(crate) fn is_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) && !name.ends_with(['/', std::path::MAIN_SEPARATOR])
+}
+
+/// ### Batch of mixed steps performed as one unit
+///
+/// Every step is queued first and then executed in order by [`FileOperation::perform`].
+/// On Windows all steps go into a single `IFileOperation`, so there is one progress
+/// window and one undo record for the whole batch.
+///
+/// A failing step does not stop the others (unless `FOFX_EARLYFAILURE` is set);
+/// the returned [`OperationReport`] holds the outcome of every item.
+/// ```rust
+/// let mut operation = ifop::FileOperation::new(None);
+/// operation
+///     .new_folder("c:\\dest", "archive")
+///     .copy("c:\\src\\file1.txt", "c:\\dest\\archive")
+///     .copy("c:\\src\\file2.txt", "c:\\dest\\archive")
+///     .delete("c:\\old");
+///
+/// match operation.perform() {
+///     Ok(report) => {
+///         for item in report.failures() {
+///             println!("{:?}", item.result);
+///         }
+///     }
+///     Err(e) => {
+///         println!("{}", e);
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct FileOperation {
+    flags: Option<OperationFlags>,
+    steps: Vec<Step>,
+    on_conflict: Option<ConflictPolicy>,
+    filter: Option<Filter>,
+    verify: Option<(Checksum, u32)>,
+    manifest: Option<(String, Checksum)>,
+    copy_options: CopyOptions,
+}
+
+impl FileOperation {
+    pub fn new(flags: Option<OperationFlags>) -> Self {
+        FileOperation {
+            flags,
+            steps: Vec::new(),
+            on_conflict: None,
+            filter: None,
+            verify: None,
+            manifest: None,
+            copy_options: CopyOptions::default(),
+        }
+    }
+
+    /// Copy `src` into the `dest` folder.
+    pub fn copy(&mut self, src: &str, dest: &str) -> &mut Self {
+        self.steps.push(Step::Copy { src: src.to_owned(), dest: dest.to_owned(), name: None });
+        self
+    }
+
+    /// Copy `src` into the `dest` folder under `new_name`.
+    pub fn copy_as(&mut self, src: &str, dest: &str, new_name: &str) -> &mut Self {
+        self.steps.push(Step::Copy { src: src.to_owned(), dest: dest.to_owned(), name: Some(new_name.to_owned()) });
+        self
+    }
+
+    /// Move `src` into the `dest` folder.
+    pub fn move_to(&mut self, src: &str, dest: &str) -> &mut Self {
+        self.steps.push(Step::Move { src: src.to_owned(), dest: dest.to_owned(), name: None });
+        self
+    }
+
+    /// Move `src` into the `dest` folder under `new_name`.
+    pub fn move_as(&mut self, src: &str, dest: &str, new_name: &str) -> &mut Self {
+        self.steps.push(Step::Move { src: src.to_owned(), dest: dest.to_owned(), name: Some(new_name.to_owned()) });
+        self
+    }
+
+    /// Delete `target`.
+    pub fn delete(&mut self, target: &str) -> &mut Self {
+        self.steps.push(Step::Delete { target: target.to_owned() });
+        self
+    }
+
+    /// Rename `src` to `new_name` inside its parent folder.
+    pub fn rename(&mut self, src: &str, new_name: &str) -> &mut Self {
+        self.steps.push(Step::Rename { src: src.to_owned(), new_name: new_name.to_owned() });
+        self
+    }
+
+    /// Create folder `name` inside `root`.
+    pub fn new_folder(&mut self, root: &str, name: &str) -> &mut Self {
+        self.steps.push(Step::NewFolder { root: root.to_owned(), name: name.to_owned() });
+        self
+    }
+
+    /// Create empty file `name` inside `root`.
+    pub fn new_file(&mut self, root: &str, name: &str) -> &mut Self {
+        self.steps.push(Step::NewFile { root: root.to_owned(), name: name.to_owned() });
+        self
+    }
+
+    /// Decide conflicts of copies, moves and renames with `policy` instead of
+    /// the shell's prompt and the collision flags. See [`ConflictPolicy`].
+    pub fn on_conflict(&mut self, policy: ConflictPolicy) -> &mut Self {
+        self.on_conflict = Some(policy);
+        self
+    }
+
+    /// Only touch what passes `filter` inside copied, moved and deleted
+    /// folders. See [`Filter`].
+    pub fn filter(&mut self, filter: Filter) -> &mut Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Check every copied file against its source with `checksum`, copying it
+    /// again up to `retries` times when they differ; what still differs fails
+    /// with [`crate::Error::Mismatch`]. A move to another volume becomes a
+    /// checked copy, and its source is deleted only once the copy checks out.
+    pub fn verify(&mut self, checksum: Checksum, retries: u32) -> &mut Self {
+        self.verify = Some((checksum, retries));
+        self
+    }
+
+    /// Once performed, write a manifest of the copied and moved files to
+    /// `path`, with their paths relative to its folder. See [`crate::Manifest`].
+    pub fn manifest(&mut self, path: &str, checksum: Checksum) -> &mut Self {
+        self.manifest = Some((path.to_owned(), checksum));
+        self
+    }
+
+    /// Copy folders as `options` say, e.g. with several threads. See
+    /// [`CopyOptions`].
+    pub fn copy_options(&mut self, options: CopyOptions) -> &mut Self {
+        self.copy_options = options;
+        self
+    }
+
+    /// Number of queued steps.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Run every queued step in order. `Err` means the operation as a whole
+    /// failed; failures of single items are in the report.
+    pub fn perform(&self) -> Result<OperationReport> {
+        self.perform_with(&mut ())
+    }
+
+    /// [`FileOperation::perform`], reporting progress to `sink` as it goes.
+    pub fn perform_with(&self, sink: &mut dyn ProgressSink) -> Result<OperationReport> {
+        let before = crate::journal::before(&self.steps());
+        let report = match self.on_conflict {
+            None => self.run(&self.steps(), self.flags, sink)?,
+            Some(policy) => {
+                let resolved = conflict::resolve(&self.steps(), policy, self.flags);
+                let steps: Vec<Step> = resolved.iter()
+                    .filter_map(|resolved| match resolved {
+                        Resolved::Run(step) => Some(step.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                let performed = self.run(&steps, conflict::flags(self.flags), sink)?;
+                merge(&resolved, performed)
+            }
+        };
+        crate::journal::record(&report, &before);
+        if let Some((path, checksum)) = &self.manifest {
+            crate::manifest::emit(&report, path, *checksum)?;
+        }
+        Ok(report)
+    }
+
+    /// ### List what [`FileOperation::perform`] would do, without doing it
+    /// Folders are expanded into file-level actions, with the conflicts the
+    /// flags do not resolve. Fails like the operation would on a missing
+    /// source or destination folder.
+    /// ```rust
+    /// let plan = ifop::FileOperation::new(None)
+    ///     .move_to("c:\\src\\folder", "c:\\dest")
+    ///     .plan()
+    ///     .unwrap();
+    /// println!("{}", plan);
+    /// ```
+    pub fn plan(&self) -> Result<Plan> {
+        match self.on_conflict {
+            None => crate::plan::plan(&self.steps(), self.flags),
+            Some(policy) => crate::plan::plan_resolved(&conflict::resolve(&self.steps(), policy, self.flags), conflict::flags(self.flags)),
+        }
+    }
+}
+
+impl FileOperation {
+    fn run(&self, steps: &[Step], flags: Option<OperationFlags>, sink: &mut dyn ProgressSink) -> Result<OperationReport> {
+        match self.verify {
+            None => imp::perform(steps, flags, &self.copy_options, sink),
+            Some((checksum, retries)) => crate::verify::perform(steps, flags, checksum, retries, &self.copy_options, sink),
+        }
+    }
+
+    /// The queued steps, with filtered folders expanded.
+    fn steps(&self) -> Cow<'_, [Step]> {
+        match &self.filter {
+            None => Cow::Borrowed(&self.steps),
+            Some(filter) => Cow::Owned(crate::filter::expand(&self.steps, filter)),
+        }
+    }
+}
+
+/// The backend's report of the steps that ran, with the items the conflict
+/// policy skipped or failed put back in their place. Merged source folders
+/// are removed here, once what was moved out of them ran; a folder an item
+/// failed to leave stays.
+pub(crate) fn merge(resolved: &[Resolved], performed: OperationReport) -> OperationReport {
+    let mut report = OperationReport { items: Vec::new(), aborted: performed.aborted };
+    let mut items = performed.items.into_iter();
+    for resolved in resolved {
+        match resolved {
+            Resolved::Run(_) => match items.next() {
+                Some(item) => report.items.push(item),
+                // Not reached after an early failure or an abort.
+                None => break,
+            },
+            Resolved::Skipped { op, src, .. } => report.push(*op, src.clone(), None, Ok(())),
+            Resolved::Failed { op, src, target } => {
+                report.push(*op, src.clone(), None, Err(crate::Error::Conflict { path: target.clone(), op: *op }))
+            }
+            Resolved::Emptied { src } => match std::fs::remove_dir(src) {
+                Ok(()) => report.push(OperationKind::Delete, src.clone(), None, Ok(())),
+                Err(e) if e.kind() == std::io::ErrorKind::DirectoryNotEmpty => {}
+                Err(e) => report.push(OperationKind::Delete, src.clone(), None, Err(crate::Error::from_io(e, src, OperationKind::Delete))),
+            },
+        }
+    }
+    report
+}
@@ -0,0 +1,84 @@
+//! Glob patterns for sources: `*`, `?`, `**` and `[...]` classes, expanded
+//! to the items they match before they are queued.
+
+use std::path::PathBuf;
+use glob::MatchOptions;
+use crate::{Error, Result};
+
+/// Anything that makes a path a pattern.
+fn is_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// ### Expand glob patterns into the items they match
+/// `*` and `?` match within a name, `**` any number of folders, `[abc]`,
+/// `[a-z]` and `[!abc]` one character. Paths without any of these go through
+/// as they are. Every item appears once, in the order of the patterns and
+/// then alphabetically. A pattern that matches nothing fails with
+/// [`Error::Pattern`].
+/// ```rust
+/// let logs = ifop::expand_patterns(&["c:\\logs\\*.log", "c:\\logs\\**\\*.tmp"], true).unwrap();
+/// ifop::delete_files(logs.iter().map(String::as_str).collect(), None).unwrap();
+/// ```
+pub fn expand_patterns(patterns: &[&str], ignore_case: bool) -> Result<Vec<String>> {
+    let options = MatchOptions { case_sensitive: !ignore_case, ..MatchOptions::new() };
+    let mut items: Vec<String> = Vec::new();
+    for pattern in patterns {
+        if !is_pattern(pattern) {
+            if !items.iter().any(|item| item == pattern) {
+                items.push((*pattern).to_owned());
+            }
+            continue;
+        }
+        let error = |message: String| Error::Pattern { pattern: PathBuf::from(pattern), message };
+        let mut matches: Vec<String> = glob::glob_with(pattern, options)
+            .map_err(|e| error(e.to_string()))?
+            .flatten()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        if matches.is_empty() {
+            return Err(error("matches nothing".to_owned()));
+        }
+        matches.sort();
+        for item in matches {
+            if !items.contains(&item) {
+                items.push(item);
+            }
+        }
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_expand_patterns() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_expand_patterns");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("sub").join("deep")).unwrap();
+        for name in ["a.log", "B.LOG", "c.txt", "sub/d.log", "sub/deep/e.log"] {
+            fs::write(folder.join(name), "").unwrap();
+        }
+        let pattern = |rel: &str| folder.join(rel).to_string_lossy().into_owned();
+        let names = |items: Vec<String>| -> Vec<String> {
+            items.iter().map(|item| item[folder.to_string_lossy().len() + 1..].replace('\\', "/")).collect()
+        };
+
+        assert_eq!(names(expand_patterns(&[&pattern("*.log")], false).unwrap()), vec!["a.log"]);
+        assert_eq!(names(expand_patterns(&[&pattern("*.log")], true).unwrap()), vec!["B.LOG", "a.log"]);
+        assert_eq!(
+            names(expand_patterns(&[&pattern("**/*.log"), &pattern("a.log"), &pattern("[ab].*")], false).unwrap()),
+            vec!["a.log", "sub/d.log", "sub/deep/e.log"]
+        );
+        assert_eq!(names(expand_patterns(&[&pattern("?.txt"), &pattern("missing")], false).unwrap()), vec!["c.txt", "missing"]);
+        assert_eq!(
+            expand_patterns(&[&pattern("*.bak")], false),
+            Err(Error::Pattern { pattern: PathBuf::from(pattern("*.bak")), message: "matches nothing".to_owned() })
+        );
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
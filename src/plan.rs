@@ -0,0 +1,513 @@
+//! Dry run of a [`crate::FileOperation`]: what it would do, file by file,
+//! read from the disk without changing anything.
+//!
+//! The steps are expanded the way the `std::fs` backend runs them, with the
+//! same collision flags. Earlier steps are taken into account by later ones:
+//! a folder created by one step is there for the next.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::conflict::Resolved;
+use crate::operation::{is_file_name, Step};
+use crate::{Error, OperationFlags, OperationKind, Result};
+
+/// One file-level action of a [`Plan`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PlannedAction {
+    CreateDir { path: PathBuf },
+    CreateFile { path: PathBuf },
+    CopyFile { src: PathBuf, dest: PathBuf, bytes: u64 },
+    /// Copy or move onto an existing file, which gets replaced.
+    Overwrite { src: PathBuf, dest: PathBuf, bytes: u64 },
+    /// Whole item renamed into place, folders included.
+    Move { src: PathBuf, dest: PathBuf, bytes: u64 },
+    Rename { src: PathBuf, dest: PathBuf },
+    DeleteFile { path: PathBuf, bytes: u64 },
+    /// Removed once empty.
+    DeleteDir { path: PathBuf },
+    /// Into the Recycle Bin / Trash.
+    Trash { path: PathBuf, bytes: u64 },
+    /// The existing file stays: `FOFX_KEEPNEWERFILE` or the conflict policy.
+    Skip { src: PathBuf, dest: PathBuf },
+    /// The target exists and the flags do not allow replacing it: the step
+    /// would fail, or the shell would ask.
+    Conflict { path: PathBuf, op: OperationKind },
+}
+
+impl PlannedAction {
+    /// Bytes of file content written, moved or deleted.
+    pub fn bytes(&self) -> u64 {
+        match self {
+            PlannedAction::CopyFile { bytes, .. }
+            | PlannedAction::Overwrite { bytes, .. }
+            | PlannedAction::Move { bytes, .. }
+            | PlannedAction::DeleteFile { bytes, .. }
+            | PlannedAction::Trash { bytes, .. } => *bytes,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for PlannedAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlannedAction::CreateDir { path } => write!(f, "create dir  {}", path.display()),
+            PlannedAction::CreateFile { path } => write!(f, "create file {}", path.display()),
+            PlannedAction::CopyFile { src, dest, bytes } => write!(f, "copy        {} -> {} ({} bytes)", src.display(), dest.display(), bytes),
+            PlannedAction::Overwrite { src, dest, bytes } => write!(f, "overwrite   {} -> {} ({} bytes)", src.display(), dest.display(), bytes),
+            PlannedAction::Move { src, dest, bytes } => write!(f, "move        {} -> {} ({} bytes)", src.display(), dest.display(), bytes),
+            PlannedAction::Rename { src, dest } => write!(f, "rename      {} -> {}", src.display(), dest.display()),
+            PlannedAction::DeleteFile { path, bytes } => write!(f, "delete      {} ({} bytes)", path.display(), bytes),
+            PlannedAction::DeleteDir { path } => write!(f, "delete dir  {}", path.display()),
+            PlannedAction::Trash { path, bytes } => write!(f, "trash       {} ({} bytes)", path.display(), bytes),
+            PlannedAction::Skip { src, dest } => write!(f, "skip        {} -> {} (target kept)", src.display(), dest.display()),
+            PlannedAction::Conflict { path, op } => write!(f, "CONFLICT    {} {}: target already exists", op, path.display()),
+        }
+    }
+}
+
+/// What [`crate::FileOperation::plan`] found, in the order it would happen.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Plan {
+    pub actions: Vec<PlannedAction>,
+    /// Number of actions, skipped items and conflicts left out.
+    pub items: u64,
+    /// Bytes of file content written, moved or deleted.
+    pub bytes: u64,
+    /// Items left alone, their target kept.
+    pub skipped: u64,
+    pub conflicts: u64,
+}
+
+impl Plan {
+    fn push(&mut self, action: PlannedAction) {
+        match action {
+            PlannedAction::Conflict { .. } => self.conflicts += 1,
+            PlannedAction::Skip { .. } => self.skipped += 1,
+            _ => self.items += 1,
+        }
+        self.bytes += action.bytes();
+        self.actions.push(action);
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.conflicts > 0
+    }
+}
+
+/// One action per line, then the totals.
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for action in &self.actions {
+            writeln!(f, "{}", action)?;
+        }
+        write!(f, "{} items, {} bytes, {} skipped, {} conflicts", self.items, self.bytes, self.skipped, self.conflicts)
+    }
+}
+
+/// The disk as the earlier steps would leave it.
+#[derive(Default)]
+pub(crate) struct View {
+    created: HashSet<PathBuf>,
+    removed: HashSet<PathBuf>,
+}
+
+impl View {
+    pub(crate) fn exists(&self, path: &Path) -> bool {
+        if self.created.contains(path) {
+            return true;
+        }
+        !path.ancestors().any(|ancestor| self.removed.contains(ancestor)) && fs::symlink_metadata(path).is_ok()
+    }
+
+    pub(crate) fn is_dir(&self, path: &Path) -> bool {
+        self.exists(path) && (self.created.contains(path) || path.is_dir())
+    }
+
+    pub(crate) fn create(&mut self, path: &Path) {
+        self.removed.remove(path);
+        self.created.insert(path.to_owned());
+    }
+
+    pub(crate) fn remove(&mut self, path: &Path) {
+        self.created.retain(|created| !created.starts_with(path));
+        self.removed.insert(path.to_owned());
+    }
+
+    /// `file.txt` -> `file (2).txt`... the name the backend would pick.
+    pub(crate) fn unique_name(&self, target: &Path) -> PathBuf {
+        let stem = target.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let ext = target.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+        (2..)
+            .map(|n| target.with_file_name(format!("{} ({}){}", stem, n, ext)))
+            .find(|candidate| !self.exists(candidate))
+            .unwrap_or_else(|| target.to_owned())
+    }
+}
+
+/// Where an item goes.
+enum Target {
+    Free(PathBuf),
+    Overwrite(PathBuf),
+}
+
+/// `None` on a conflict, which is part of the plan.
+fn resolve(view: &View, plan: &mut Plan, target: PathBuf, op: OperationKind, flags: OperationFlags) -> Option<Target> {
+    if !view.exists(&target) {
+        return Some(Target::Free(target));
+    }
+    if flags.contains(OperationFlags::FOF_RENAMEONCOLLISION) {
+        return Some(Target::Free(view.unique_name(&target)));
+    }
+    if flags.contains(OperationFlags::FOF_NOCONFIRMATION) {
+        return Some(Target::Overwrite(target));
+    }
+    plan.push(PlannedAction::Conflict { path: target, op });
+    None
+}
+
+/// Same test as the backend: an existing target modified after the source.
+fn newer(src: &Path, target: &Path) -> bool {
+    match (fs::metadata(src).and_then(|m| m.modified()), fs::metadata(target).and_then(|m| m.modified())) {
+        (Ok(src), Ok(target)) => target > src,
+        _ => false,
+    }
+}
+
+pub(crate) fn children(view: &View, path: &Path, op: OperationKind) -> Result<Vec<PathBuf>> {
+    if view.created.contains(path) && !path.is_dir() {
+        // Created by an earlier step, still empty.
+        return Ok(Vec::new());
+    }
+    let mut children: Vec<PathBuf> = fs::read_dir(path)
+        .map_err(|e| Error::from_io(e, path, op))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|child| view.exists(child))
+        .collect();
+    children.sort();
+    Ok(children)
+}
+
+/// Bytes of file content in `path`, folders included. Unreadable parts count as 0.
+pub(crate) fn size_of(path: &Path) -> u64 {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::read_dir(path)
+            .map(|entries| entries.flatten().map(|entry| size_of(&entry.path())).sum())
+            .unwrap_or(0),
+        Ok(meta) if meta.is_file() => meta.len(),
+        _ => 0,
+    }
+}
+
+fn copy_item(view: &mut View, plan: &mut Plan, src: &Path, target: &Path, flags: OperationFlags, depth: usize, op: OperationKind) -> Result<()> {
+    let meta = fs::symlink_metadata(src).map_err(|e| Error::from_io(e, src, op))?;
+    if meta.is_dir() {
+        if !view.is_dir(target) {
+            plan.push(PlannedAction::CreateDir { path: target.to_owned() });
+            view.create(target);
+        }
+        for child in children(view, src, op)? {
+            if depth == 0 && flags.contains(OperationFlags::FOF_NORECURSION) && child.is_dir() {
+                continue;
+            }
+            let name = child.file_name().unwrap_or_default();
+            copy_item(view, plan, &child, &target.join(name), flags, depth + 1, op)?;
+        }
+        return Ok(());
+    }
+    let (src, dest, bytes) = (src.to_owned(), target.to_owned(), meta.len());
+    if view.exists(target) {
+        if flags.contains(OperationFlags::FOFX_KEEPNEWERFILE) && newer(&src, target) {
+            plan.push(PlannedAction::Skip { src, dest });
+        } else {
+            plan.push(PlannedAction::Overwrite { src, dest, bytes });
+        }
+    } else {
+        plan.push(PlannedAction::CopyFile { src, dest, bytes });
+    }
+    view.create(target);
+    Ok(())
+}
+
+fn delete_item(view: &mut View, plan: &mut Plan, target: &Path) -> Result<()> {
+    if view.is_dir(target) && !fs::symlink_metadata(target).is_ok_and(|meta| meta.file_type().is_symlink()) {
+        for child in children(view, target, OperationKind::Delete)? {
+            delete_item(view, plan, &child)?;
+        }
+        plan.push(PlannedAction::DeleteDir { path: target.to_owned() });
+    } else {
+        plan.push(PlannedAction::DeleteFile { path: target.to_owned(), bytes: size_of(target) });
+    }
+    view.remove(target);
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn same_volume(src: &Path, folder: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::symlink_metadata(src), fs::metadata(folder)) {
+        (Ok(src), Ok(folder)) => src.dev() == folder.dev(),
+        _ => true,
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn same_volume(src: &Path, folder: &Path) -> bool {
+    let volume = |path: &Path| path.components().next().map(|c| c.as_os_str().to_ascii_lowercase());
+    volume(src) == volume(folder)
+}
+
+fn move_item(view: &mut View, plan: &mut Plan, src: &Path, target: &Path, overwrite: bool, flags: OperationFlags) -> Result<()> {
+    let op = OperationKind::Move;
+    if overwrite && view.is_dir(src) && view.is_dir(target) {
+        // Merged into the existing folder, item by item.
+        for child in children(view, src, op)? {
+            let child_target = target.join(child.file_name().unwrap_or_default());
+            let overwrite = view.exists(&child_target);
+            move_item(view, plan, &child, &child_target, overwrite, flags)?;
+        }
+        // Items kept by `FOFX_KEEPNEWERFILE` stay, and the folder with them.
+        if children(view, src, op)?.is_empty() {
+            plan.push(PlannedAction::DeleteDir { path: src.to_owned() });
+            view.remove(src);
+        }
+        return Ok(());
+    }
+    if overwrite && flags.contains(OperationFlags::FOFX_KEEPNEWERFILE) && !view.is_dir(target) && newer(src, target) {
+        plan.push(PlannedAction::Skip { src: src.to_owned(), dest: target.to_owned() });
+        return Ok(());
+    }
+    if !same_volume(src, target.parent().unwrap_or(target)) {
+        // Copied over, then deleted.
+        copy_item(view, plan, src, target, flags - OperationFlags::FOF_NORECURSION, 0, op)?;
+        return delete_item(view, plan, src);
+    }
+    let bytes = size_of(src);
+    if overwrite && !view.is_dir(target) && !view.is_dir(src) {
+        plan.push(PlannedAction::Overwrite { src: src.to_owned(), dest: target.to_owned(), bytes });
+    } else {
+        if overwrite {
+            plan.push(PlannedAction::DeleteFile { path: target.to_owned(), bytes: size_of(target) });
+        }
+        plan.push(PlannedAction::Move { src: src.to_owned(), dest: target.to_owned(), bytes });
+    }
+    view.remove(src);
+    view.create(target);
+    Ok(())
+}
+
+/// Destination folder of a copy or move, which has to exist by then.
+fn folder(view: &View, dest: &str, op: OperationKind) -> Result<PathBuf> {
+    let dest = PathBuf::from(dest);
+    if !view.is_dir(&dest) {
+        return Err(Error::NotFound { path: dest, op });
+    }
+    Ok(dest)
+}
+
+fn source(view: &View, src: &str, op: OperationKind) -> Result<PathBuf> {
+    let src = PathBuf::from(src);
+    if !view.exists(&src) {
+        return Err(Error::NotFound { path: src, op });
+    }
+    Ok(src)
+}
+
+fn target_in(src: &Path, dest: &Path, name: Option<&str>, op: OperationKind) -> Result<PathBuf> {
+    match name.map(Path::new).or_else(|| src.file_name().map(Path::new)) {
+        Some(name) => Ok(dest.join(name)),
+        None => Err(Error::InvalidPath { path: src.to_owned(), op }),
+    }
+}
+
+fn plan_step(view: &mut View, plan: &mut Plan, step: &Step, flags: OperationFlags) -> Result<()> {
+    let op = step.kind();
+    match step {
+        Step::Copy { src, dest, name } => {
+            let src = source(view, src, op)?;
+            let target = target_in(&src, &folder(view, dest, op)?, name.as_deref(), op)?;
+            let target = if target == src {
+                Target::Free(view.unique_name(&target))
+            } else {
+                match resolve(view, plan, target, op, flags) {
+                    Some(target) => target,
+                    None => return Ok(()),
+                }
+            };
+            let (Target::Free(target) | Target::Overwrite(target)) = target;
+            copy_item(view, plan, &src, &target, flags, 0, op)
+        }
+        Step::Move { src, dest, name } => {
+            let src = source(view, src, op)?;
+            let target = target_in(&src, &folder(view, dest, op)?, name.as_deref(), op)?;
+            if target == src {
+                return Ok(());
+            }
+            match resolve(view, plan, target, op, flags) {
+                Some(Target::Free(target)) => move_item(view, plan, &src, &target, false, flags),
+                Some(Target::Overwrite(target)) => move_item(view, plan, &src, &target, true, flags),
+                None => Ok(()),
+            }
+        }
+        Step::Delete { target } => {
+            let target = source(view, target, op)?;
+            if flags.intersects(OperationFlags::FOF_ALLOWUNDO | OperationFlags::FOFX_RECYCLEONDELETE) {
+                plan.push(PlannedAction::Trash { bytes: size_of(&target), path: target.clone() });
+                view.remove(&target);
+                return Ok(());
+            }
+            delete_item(view, plan, &target)
+        }
+        Step::Rename { src, new_name } => {
+            let src = source(view, src, op)?;
+            if !is_file_name(new_name) {
+                return Err(Error::InvalidPath { path: src, op });
+            }
+            let target = src.with_file_name(new_name);
+            if target == src {
+                return Ok(());
+            }
+            match resolve(view, plan, target, op, flags) {
+                Some(Target::Free(target)) => {
+                    plan.push(PlannedAction::Rename { src: src.clone(), dest: target.clone() });
+                    view.remove(&src);
+                    view.create(&target);
+                    Ok(())
+                }
+                Some(Target::Overwrite(target)) => move_item(view, plan, &src, &target, true, flags),
+                None => Ok(()),
+            }
+        }
+        Step::NewFolder { root, name } | Step::NewFile { root, name } => {
+            let mut path = folder(view, root, op)?.join(name);
+            if view.exists(&path) {
+                if !flags.contains(OperationFlags::FOF_RENAMEONCOLLISION) {
+                    plan.push(PlannedAction::Conflict { path, op });
+                    return Ok(());
+                }
+                path = view.unique_name(&path);
+            }
+            plan.push(match op {
+                OperationKind::NewFolder => PlannedAction::CreateDir { path: path.clone() },
+                _ => PlannedAction::CreateFile { path: path.clone() },
+            });
+            view.create(&path);
+            Ok(())
+        }
+    }
+}
+
+pub(crate) fn plan(steps: &[Step], flags: Option<OperationFlags>) -> Result<Plan> {
+    let flags = flags.unwrap_or_default();
+    let (mut view, mut plan) = (View::default(), Plan::default());
+    for step in steps {
+        plan_step(&mut view, &mut plan, step, flags)?;
+    }
+    Ok(plan)
+}
+
+/// [`plan`] of steps whose conflicts a [`crate::ConflictPolicy`] decided.
+pub(crate) fn plan_resolved(resolved: &[Resolved], flags: Option<OperationFlags>) -> Result<Plan> {
+    let flags = flags.unwrap_or_default();
+    let (mut view, mut plan) = (View::default(), Plan::default());
+    for resolved in resolved {
+        match resolved {
+            Resolved::Run(step) => plan_step(&mut view, &mut plan, step, flags)?,
+            Resolved::Skipped { src, target, .. } => plan.push(PlannedAction::Skip { src: src.clone(), dest: target.clone() }),
+            Resolved::Failed { op, target, .. } => plan.push(PlannedAction::Conflict { path: target.clone(), op: *op }),
+            Resolved::Emptied { src } => {
+                view.remove(src);
+                plan.push(PlannedAction::DeleteDir { path: src.clone() });
+            }
+        }
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileOperation;
+
+    #[test]
+    fn test_plan() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_plan");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("src").join("sub")).unwrap();
+        fs::create_dir_all(folder.join("dest").join("src")).unwrap();
+        fs::write(folder.join("src").join("a"), "aaa").unwrap();
+        fs::write(folder.join("src").join("sub").join("b"), "bb").unwrap();
+        fs::write(folder.join("dest").join("src").join("a"), "old").unwrap();
+        let path = |rel: &str| rel.split('/').fold(folder.clone(), |path, part| path.join(part));
+        let text = |rel: &str| path(rel).to_string_lossy().into_owned();
+
+        // Without flags the existing folder is a conflict, and the new
+        // folder of the first step is there for the second one.
+        let plan = FileOperation::new(None)
+            .new_folder(&text(""), "archive")
+            .copy(&text("src"), &text("archive"))
+            .copy(&text("src"), &text("dest"))
+            .plan()
+            .unwrap();
+        assert_eq!(plan.actions, vec![
+            PlannedAction::CreateDir { path: path("archive") },
+            PlannedAction::CreateDir { path: path("archive/src") },
+            PlannedAction::CopyFile { src: path("src/a"), dest: path("archive/src/a"), bytes: 3 },
+            PlannedAction::CreateDir { path: path("archive/src/sub") },
+            PlannedAction::CopyFile { src: path("src/sub/b"), dest: path("archive/src/sub/b"), bytes: 2 },
+            PlannedAction::Conflict { path: path("dest/src"), op: OperationKind::Copy },
+        ]);
+        assert_eq!((plan.items, plan.bytes, plan.conflicts), (5, 5, 1));
+        assert!(!path("archive").exists());
+
+        let plan = FileOperation::new(Some(OperationFlags::FOF_NOCONFIRMATION))
+            .copy(&text("src"), &text("dest"))
+            .delete(&text("src"))
+            .plan()
+            .unwrap();
+        assert_eq!(plan.actions, vec![
+            PlannedAction::Overwrite { src: path("src/a"), dest: path("dest/src/a"), bytes: 3 },
+            PlannedAction::CreateDir { path: path("dest/src/sub") },
+            PlannedAction::CopyFile { src: path("src/sub/b"), dest: path("dest/src/sub/b"), bytes: 2 },
+            PlannedAction::DeleteFile { path: path("src/a"), bytes: 3 },
+            PlannedAction::DeleteFile { path: path("src/sub/b"), bytes: 2 },
+            PlannedAction::DeleteDir { path: path("src/sub") },
+            PlannedAction::DeleteDir { path: path("src") },
+        ]);
+        assert!(!plan.has_conflicts());
+        let json = serde_json::to_value(&plan).unwrap();
+        assert_eq!(json["actions"][0]["action"], "overwrite");
+        assert_eq!(json["bytes"], 10);
+        assert_eq!(fs::read_to_string(path("dest/src/a")).unwrap(), "old");
+
+        // Skipped items are not counted as items.
+        let plan = FileOperation::new(None)
+            .on_conflict(crate::ConflictPolicy::Skip)
+            .copy(&text("src"), &text("dest"))
+            .plan()
+            .unwrap();
+        assert_eq!(plan.actions[0], PlannedAction::Skip { src: path("src/a"), dest: path("dest/src/a") });
+        assert_eq!((plan.items, plan.bytes, plan.skipped, plan.conflicts), (2, 2, 1, 0));
+        assert!(plan.to_string().ends_with("2 items, 2 bytes, 1 skipped, 0 conflicts"));
+
+        // The merged folder stays with the older file kept out of it.
+        let earlier = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        fs::File::options().write(true).open(path("src/a")).unwrap().set_modified(earlier).unwrap();
+        let plan = FileOperation::new(Some(OperationFlags::FOF_NOCONFIRMATION | OperationFlags::FOFX_KEEPNEWERFILE))
+            .move_to(&text("src"), &text("dest"))
+            .plan()
+            .unwrap();
+        assert_eq!(plan.actions, vec![
+            PlannedAction::Skip { src: path("src/a"), dest: path("dest/src/a") },
+            PlannedAction::Move { src: path("src/sub"), dest: path("dest/src/sub"), bytes: 2 },
+        ]);
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+use std::path::Path;
+use crate::{OperationKind, OperationReport, Result};
+
+/// Counters passed to [`ProgressSink::progress`].
+///
+/// Items count the steps of the operation. Bytes are the file content copied
+/// or moved so far; on Windows the shell only reports its share of the work
+/// done, which the bytes follow.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Progress {
+    pub items_done: u64,
+    pub items_total: u64,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+}
+
+/// ### Progress callbacks of a [`crate::FileOperation`]
+///
+/// Every method has an empty default, implement only the ones you need.
+/// Item hooks are called for the queued steps, not for the files inside a
+/// copied folder.
+/// ```rust
+/// struct Log;
+///
+/// impl ifop::ProgressSink for Log {
+///     fn post_item(&mut self, op: ifop::OperationKind, path: &std::path::Path, result_path: Option<&std::path::Path>, status: &ifop::Result<()>) {
+///         println!("{} {} -> {:?}: {:?}", op, path.display(), result_path, status);
+///     }
+///
+///     fn progress(&mut self, progress: &ifop::Progress) {
+///         println!("{}/{} bytes", progress.bytes_done, progress.bytes_total);
+///     }
+/// }
+///
+/// ifop::FileOperation::new(None)
+///     .copy("c:\\src\\big.iso", "c:\\dest")
+///     .perform_with(&mut Log)
+///     .unwrap();
+/// ```
+pub trait ProgressSink {
+    /// The operation starts running.
+    fn start(&mut self) {}
+
+    /// The operation is over, `report` is what [`crate::FileOperation::perform`] returns.
+    fn finish(&mut self, _report: &OperationReport) {}
+
+    /// `path` is about to be processed.
+    fn pre_item(&mut self, _op: OperationKind, _path: &Path) {}
+
+    /// `path` was processed. `result_path` is where the item ended up: the
+    /// copy, the moved or renamed item, the new item, or the recycled item
+    /// when a delete goes to the recycle bin.
+    fn post_item(&mut self, _op: OperationKind, _path: &Path, _result_path: Option<&Path>, _status: &Result<()>) {}
+
+    /// Items or bytes moved forward.
+    fn progress(&mut self, _progress: &Progress) {}
+}
+
+/// No progress wanted, used by [`crate::FileOperation::perform`].
+impl ProgressSink for () {}
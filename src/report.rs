@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::{Error, OperationKind, Result};
+
+/// Outcome of one queued step.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ItemOutcome {
+    pub op: OperationKind,
+    /// The item the step was queued with: the source, or the new item's path.
+    pub path: PathBuf,
+    /// Where the item ended up, when the backend knows: the copy, the moved
+    /// or renamed item, the new item, or the trashed item.
+    pub result_path: Option<PathBuf>,
+    pub result: Result<()>,
+    /// How a move got done, when the backend knows.
+    pub strategy: Option<MoveStrategy>,
+}
+
+/// How an item was moved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MoveStrategy {
+    /// Renamed, on the same file system.
+    Rename,
+    /// To another file system: copied, then the source deleted. The
+    /// `std::fs` backend checks the copy against the source in between.
+    CopyDelete,
+}
+
+/// How much of an operation got done.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    /// Every item succeeded.
+    Done,
+    /// Some items succeeded, others failed or were skipped.
+    Partial,
+    /// The user cancelled or declined before any item got done.
+    Aborted,
+    /// No item succeeded, without the user aborting anything.
+    Failed,
+}
+
+/// Per-item outcome of a [`crate::FileOperation`], in the order the steps were queued.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OperationReport {
+    pub items: Vec<ItemOutcome>,
+    /// `GetAnyOperationsAborted` on Windows: the user cancelled, or chose "Skip"
+    /// in a conflict dialog. The `std::fs` backend never prompts, so a conflict
+    /// the shell would have asked about counts as a declined prompt.
+    pub aborted: bool,
+}
+
+impl OperationReport {
+    pub(crate) fn push(&mut self, op: OperationKind, path: PathBuf, result_path: Option<PathBuf>, result: Result<()>) {
+        self.items.push(ItemOutcome { op, path, result_path, result, strategy: None });
+    }
+
+    /// `true` when every item succeeded.
+    pub fn is_ok(&self) -> bool {
+        self.items.iter().all(|item| item.result.is_ok())
+    }
+
+    pub fn status(&self) -> OperationStatus {
+        let done = self.items.iter().filter(|item| item.result.is_ok()).count();
+        if done == self.items.len() && !self.aborted {
+            OperationStatus::Done
+        } else if done > 0 {
+            OperationStatus::Partial
+        } else if self.aborted {
+            OperationStatus::Aborted
+        } else {
+            OperationStatus::Failed
+        }
+    }
+
+    /// Items that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &ItemOutcome> {
+        self.items.iter().filter(|item| item.result.is_err())
+    }
+
+    /// Exit code of a command that ran the operation: 0 when done, 2 when the
+    /// user aborted all or part of it, 1 when items failed otherwise.
+    pub fn exit_code(&self) -> i32 {
+        match self.status() {
+            OperationStatus::Done => 0,
+            OperationStatus::Aborted => 2,
+            OperationStatus::Partial if self.aborted => 2,
+            OperationStatus::Partial | OperationStatus::Failed => 1,
+        }
+    }
+
+    /// `Ok` when every item succeeded and nothing was aborted. When the
+    /// user cancelled or declined something, even inside a folder whose item
+    /// succeeded, [`Error::Aborted`] about the first failed item if any;
+    /// otherwise the first item's error.
+    pub fn into_result(self) -> Result<()> {
+        let aborted = self.aborted;
+        let first = self.items.into_iter().map(|item| item.result).find(|result| result.is_err()).unwrap_or(Ok(()));
+        match first {
+            Err(e) if aborted && !matches!(e, Error::Aborted { .. }) => {
+                Err(Error::Aborted { path: e.path().map(|path| path.to_owned()), op: e.operation() })
+            }
+            Ok(()) if aborted => Err(Error::Aborted { path: None, op: None }),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_status() {
+        let path = PathBuf::from("file");
+        let failed = Err(Error::Conflict { path: path.clone(), op: OperationKind::Copy });
+        let report = |results: Vec<Result<()>>, aborted| OperationReport {
+            items: results.into_iter().map(|result| ItemOutcome { op: OperationKind::Copy, path: path.clone(), result_path: None, result, strategy: None }).collect(),
+            aborted,
+        };
+
+        let done = report(vec![Ok(())], false);
+        assert_eq!((done.status(), done.exit_code()), (OperationStatus::Done, 0));
+        assert_eq!(done.into_result(), Ok(()));
+
+        // A "Skip" inside a copied folder: the item is fine, the operation is not.
+        let skipped = report(vec![Ok(())], true);
+        assert_eq!((skipped.status(), skipped.exit_code()), (OperationStatus::Partial, 2));
+        assert_eq!(skipped.into_result(), Err(Error::Aborted { path: None, op: None }));
+
+        // A declined conflict next to an item that got done.
+        let declined = report(vec![Ok(()), failed.clone()], true);
+        assert_eq!((declined.status(), declined.exit_code()), (OperationStatus::Partial, 2));
+        assert_eq!(declined.into_result(), Err(Error::Aborted { path: Some(path.clone()), op: Some(OperationKind::Copy) }));
+        let cancelled = report(vec![failed.clone()], true);
+        assert_eq!((cancelled.status(), cancelled.exit_code()), (OperationStatus::Aborted, 2));
+
+        let partial = report(vec![Ok(()), failed.clone()], false);
+        assert_eq!((partial.status(), partial.exit_code()), (OperationStatus::Partial, 1));
+        assert_eq!(partial.into_result(), failed);
+        let none = report(vec![failed.clone()], false);
+        assert_eq!((none.status(), none.exit_code()), (OperationStatus::Failed, 1));
+    }
+}
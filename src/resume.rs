@@ -0,0 +1,302 @@
+//! Resumable copies, done with `std::fs` on every platform. Each file is
+//! written to `<name>.ifop-part` and renamed into place once complete; a
+//! checkpoint, `.<name>.ifop-resume` next to the target, keeps the finished
+//! files and how far the current one got, so a copy that died can go on from
+//! there.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::journal::{self, Before};
+use crate::operation::Step;
+use crate::{Error, OperationFlags, OperationKind, OperationReport, Result};
+
+const CHUNK: usize = 1024 * 1024;
+/// Checkpoints are written at most this often, and after this many bytes.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(2);
+const CHECKPOINT_BYTES: u64 = 64 * 1024 * 1024;
+
+/// A file as it was when copying it started.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Source {
+    size: u64,
+    /// Nanoseconds since the epoch.
+    modified: u64,
+}
+
+/// How far the file being copied got.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Partial {
+    path: String,
+    source: Source,
+    /// Bytes in the staging file that were synced to disk.
+    offset: u64,
+    /// BLAKE3 of those bytes.
+    hash: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Checkpoint {
+    src: PathBuf,
+    /// Finished files by path relative to the source, `/` separated.
+    done: BTreeMap<String, Source>,
+    partial: Option<Partial>,
+    /// The target was there before the first run: the journal only takes
+    /// what a resumed run added as the copy's.
+    #[serde(default)]
+    existed: bool,
+}
+
+fn io_error(op: OperationKind, path: &Path) -> impl FnOnce(io::Error) -> Error + '_ {
+    move |e| Error::from_io(e, path, op)
+}
+
+fn with_suffix(path: &Path, prefix: &str, suffix: &str) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(format!("{}{}{}", prefix, name, suffix))
+}
+
+fn checkpoint_path(target: &Path) -> PathBuf {
+    with_suffix(target, ".", ".ifop-resume")
+}
+
+fn staging_path(target: &Path) -> PathBuf {
+    with_suffix(target, "", ".ifop-part")
+}
+
+fn source(meta: &fs::Metadata) -> Source {
+    let modified = meta.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map_or(0, |time| time.as_nanos() as u64);
+    Source { size: meta.len(), modified }
+}
+
+/// BLAKE3 of the first `len` bytes of `path`, `None` when it is shorter.
+fn prefix_hash(path: &Path, len: u64) -> Option<String> {
+    let file = fs::File::open(path).ok()?;
+    let mut hasher = blake3::Hasher::new();
+    let read = io::copy(&mut file.take(len), &mut hasher).ok()?;
+    (read == len).then(|| hasher.finalize().to_hex().to_string())
+}
+
+struct Resumer {
+    checkpoint: Checkpoint,
+    path: PathBuf,
+    saved: Instant,
+    unsaved_bytes: u64,
+}
+
+impl Resumer {
+    fn save(&mut self) -> Result<()> {
+        let content = serde_json::to_vec(&self.checkpoint).map_err(|e| Error::from_io(e.into(), &self.path, OperationKind::Copy))?;
+        let temp = with_suffix(&self.path, "", ".tmp");
+        fs::write(&temp, content)
+            .and_then(|()| fs::rename(&temp, &self.path))
+            .map_err(io_error(OperationKind::Copy, &self.path))?;
+        self.saved = Instant::now();
+        self.unsaved_bytes = 0;
+        Ok(())
+    }
+
+    fn due(&self) -> bool {
+        self.unsaved_bytes >= CHECKPOINT_BYTES || self.saved.elapsed() >= CHECKPOINT_INTERVAL
+    }
+
+    fn item(&mut self, src: &Path, target: &Path, key: &str) -> Result<()> {
+        let meta = fs::metadata(src).map_err(io_error(OperationKind::Copy, src))?;
+        if !meta.is_dir() {
+            return self.file(src, target, key, &meta);
+        }
+        fs::create_dir_all(target).map_err(io_error(OperationKind::Copy, target))?;
+        let mut children: Vec<_> = fs::read_dir(src)
+            .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+            .map_err(io_error(OperationKind::Copy, src))?;
+        children.sort_by_key(|entry| entry.file_name());
+        for child in children {
+            let name = child.file_name().to_string_lossy().into_owned();
+            let key = if key.is_empty() { name } else { format!("{}/{}", key, name) };
+            self.item(&child.path(), &target.join(child.file_name()), &key)?;
+        }
+        Ok(())
+    }
+
+    /// Where to go on with the staging file from: the checkpoint's offset when
+    /// the source is unchanged and the bytes up to it are still the same.
+    fn resume_offset(&self, key: &str, staging: &Path, source: Source) -> (u64, blake3::Hasher) {
+        let mut hasher = blake3::Hasher::new();
+        let Some(partial) = self.checkpoint.partial.as_ref().filter(|partial| partial.path == key && partial.source == source) else {
+            return (0, hasher);
+        };
+        if prefix_hash(staging, partial.offset).as_deref() != Some(partial.hash.as_str()) {
+            return (0, hasher);
+        }
+        // The hasher goes on from the bytes already there.
+        let prefix = fs::File::open(staging).map(|file| file.take(partial.offset));
+        match prefix.and_then(|mut prefix| io::copy(&mut prefix, &mut hasher)) {
+            Ok(_) => (partial.offset, hasher),
+            Err(_) => (0, blake3::Hasher::new()),
+        }
+    }
+
+    fn file(&mut self, src: &Path, target: &Path, key: &str, meta: &fs::Metadata) -> Result<()> {
+        let source = source(meta);
+        let finished = fs::metadata(target).is_ok_and(|target| target.len() == source.size);
+        if self.checkpoint.done.get(key) == Some(&source) && finished {
+            return Ok(());
+        }
+        let staging = staging_path(target);
+        let (mut offset, mut hasher) = self.resume_offset(key, &staging, source);
+
+        let mut reader = fs::File::open(src).map_err(io_error(OperationKind::Copy, src))?;
+        let mut writer = fs::OpenOptions::new().write(true).create(true).truncate(false).open(&staging)
+            .map_err(io_error(OperationKind::Copy, &staging))?;
+        // Whatever came after the last checkpoint is written again.
+        writer.set_len(offset)
+            .and_then(|()| writer.seek(SeekFrom::Start(offset)))
+            .and_then(|_| reader.seek(SeekFrom::Start(offset)))
+            .map_err(io_error(OperationKind::Copy, &staging))?;
+        let mut buf = vec![0; CHUNK];
+        loop {
+            let read = reader.read(&mut buf).map_err(io_error(OperationKind::Copy, src))?;
+            if read == 0 {
+                break;
+            }
+            writer.write_all(&buf[..read]).map_err(io_error(OperationKind::Copy, &staging))?;
+            hasher.update(&buf[..read]);
+            offset += read as u64;
+            self.unsaved_bytes += read as u64;
+            if self.due() {
+                writer.sync_data().map_err(io_error(OperationKind::Copy, &staging))?;
+                let hash = hasher.finalize().to_hex().to_string();
+                self.checkpoint.partial = Some(Partial { path: key.to_owned(), source, offset, hash });
+                self.save()?;
+            }
+        }
+        writer.sync_all().map_err(io_error(OperationKind::Copy, &staging))?;
+        if let Ok(modified) = meta.modified() {
+            let _ = writer.set_modified(modified);
+        }
+        drop(writer);
+        fs::set_permissions(&staging, meta.permissions())
+            .and_then(|()| fs::rename(&staging, target))
+            .map_err(io_error(OperationKind::Copy, target))?;
+
+        self.checkpoint.done.insert(key.to_owned(), source);
+        self.checkpoint.partial = None;
+        if self.due() {
+            self.save()?;
+        }
+        Ok(())
+    }
+}
+
+/// ### Copy `src` into the `dest` folder so that an interrupted copy can go on
+/// Files are copied to a staging file and renamed into place when complete,
+/// with a checkpoint of the finished files and of the bytes of the current
+/// one, next to the target. Called again after an interruption, it skips
+/// the finished files that did not change, checks the staging file against
+/// its checkpoint and appends the rest. The checkpoint is removed once the
+/// copy is complete.
+///
+/// Without a checkpoint, an existing target is a [`Error::Conflict`] unless
+/// the flags have `FOF_NOCONFIRMATION`. The copy is done with `std::fs`, on
+/// Windows too: no progress dialog and no shell undo, but the finished copy
+/// is recorded in the journal when it is on.
+/// ```rust
+/// // Run it again after a failure to go on where it stopped.
+/// ifop::copy_resume("c:\\vm\\disk.vhdx", "\\\\server\\share\\vm", None).unwrap();
+/// ```
+pub fn copy_resume(src: &str, dest: &str, flags: Option<OperationFlags>) -> Result<()> {
+    let (src, dest) = (Path::new(src), Path::new(dest));
+    let op = OperationKind::Copy;
+    let Some(name) = src.file_name() else {
+        return Err(Error::InvalidPath { path: src.to_owned(), op });
+    };
+    if !dest.is_dir() {
+        return Err(Error::NotFound { path: dest.to_owned(), op });
+    }
+    let target = dest.join(name);
+    let path = checkpoint_path(&target);
+    let checkpoint = fs::read_to_string(&path).ok()
+        .and_then(|content| serde_json::from_str::<Checkpoint>(&content).ok())
+        .filter(|checkpoint| checkpoint.src == src);
+    let step = Step::Copy { src: src.to_string_lossy().into_owned(), dest: dest.to_string_lossy().into_owned(), name: None };
+    let (checkpoint, before) = match checkpoint {
+        Some(checkpoint) if !checkpoint.existed => (checkpoint, Before::default()),
+        Some(checkpoint) => (checkpoint, journal::before(&[step])),
+        None if target.exists() && !flags.unwrap_or_default().contains(OperationFlags::FOF_NOCONFIRMATION) => {
+            return Err(Error::Conflict { path: target, op });
+        }
+        None => (Checkpoint { src: src.to_owned(), existed: target.exists(), ..Checkpoint::default() }, journal::before(&[step])),
+    };
+    let mut resumer = Resumer { checkpoint, path, saved: Instant::now(), unsaved_bytes: 0 };
+    resumer.save()?;
+    match resumer.item(src, &target, "") {
+        Ok(()) => {
+            fs::remove_file(&resumer.path).map_err(io_error(op, &resumer.path))?;
+            let mut report = OperationReport::default();
+            report.push(op, src.to_owned(), Some(target), Ok(()));
+            journal::record(&report, &before);
+            Ok(())
+        }
+        Err(e) => {
+            // Best effort: the last checkpoint is still there if this fails.
+            let _ = resumer.save();
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_resume() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_copy_resume");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("src").join("sub")).unwrap();
+        fs::create_dir_all(folder.join("dest")).unwrap();
+        let big: Vec<u8> = (0..3 * CHUNK as u32).map(|i| (i % 251) as u8).collect();
+        fs::write(folder.join("src").join("a"), "done").unwrap();
+        fs::write(folder.join("src").join("sub").join("big"), &big).unwrap();
+        let (src, dest) = (folder.join("src"), folder.join("dest"));
+        let target = dest.join("src");
+
+        // As an interrupted copy leaves it: `a` finished, `big` half copied.
+        fs::create_dir_all(target.join("sub")).unwrap();
+        fs::write(target.join("a"), "done").unwrap();
+        let half = CHUNK as u64 + 17;
+        let mut staging = big[..half as usize].to_vec();
+        staging.extend_from_slice(b"garbage after the checkpoint");
+        fs::write(staging_path(&target.join("sub").join("big")), staging).unwrap();
+        let meta = |name: &str| source(&fs::metadata(src.join(name)).unwrap());
+        let checkpoint = Checkpoint {
+            src: src.clone(),
+            done: BTreeMap::from([("a".to_owned(), meta("a"))]),
+            partial: Some(Partial {
+                path: "sub/big".to_owned(),
+                source: meta("sub/big"),
+                offset: half,
+                hash: blake3::hash(&big[..half as usize]).to_hex().to_string(),
+            }),
+            existed: false,
+        };
+        fs::write(checkpoint_path(&target), serde_json::to_vec(&checkpoint).unwrap()).unwrap();
+
+        copy_resume(src.to_str().unwrap(), dest.to_str().unwrap(), None).unwrap();
+        assert_eq!(fs::read(target.join("sub").join("big")).unwrap(), big);
+        assert!(!checkpoint_path(&target).exists());
+        assert!(!staging_path(&target.join("sub").join("big")).exists());
+
+        // Done: the target is there and there is no checkpoint.
+        assert_eq!(
+            copy_resume(src.to_str().unwrap(), dest.to_str().unwrap(), None),
+            Err(Error::Conflict { path: target.clone(), op: OperationKind::Copy })
+        );
+        copy_resume(src.to_str().unwrap(), dest.to_str().unwrap(), Some(OperationFlags::FOF_NOCONFIRMATION)).unwrap();
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+//! `IFileOperation` backend used on Windows.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use windows::Win32::UI::Shell::*;
+use windows::Win32::System::Com::{ CoCreateInstance, CoTaskMemFree, CLSCTX_ALL};
+use windows::Win32::Storage::FileSystem::*;
+use windows::Win32::Foundation::E_INVALIDARG;
+use windows::core::*;
+use windows_core::Result;
+use crate::operation::{is_file_name, Step};
+use crate::plan::{same_volume, size_of};
+use crate::{CopyOptions, MoveStrategy, OperationFlags, OperationKind, OperationReport, Progress, ProgressSink};
+
+unsafe fn get_item(target: &str) -> Result<IShellItem>{
+    SHCreateItemFromParsingName( &HSTRING::from(target), None)
+}
+
+/// Folders created by an earlier step of the same batch do not exist yet when
+/// the later steps are queued, so they cannot be parsed. A simple ID list is
+/// enough for the shell to use them as a destination once they exist.
+unsafe fn get_dest_item(target: &str, pending: &[PathBuf]) -> Result<IShellItem> {
+    match get_item(target) {
+        Ok(item) => Ok(item),
+        Err(e) => {
+            if !pending.contains(&normalize(Path::new(target))) {
+                return Err(e);
+            }
+            let pidl = SHSimpleIDListFromPath(&HSTRING::from(target));
+            if pidl.is_null() {
+                return Err(E_INVALIDARG.into());
+            }
+            let item = SHCreateItemFromIDList(pidl);
+            ILFree(Some(pidl));
+            item
+        }
+    }
+}
+
+/// Paths are compared case-insensitively, like the file system does.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    PathBuf::from(path.to_string_lossy().to_lowercase())
+}
+
+fn created_in(dest: &str, src: &str, name: &Option<String>) -> PathBuf {
+    match name {
+        Some(name) => normalize(&Path::new(dest).join(name)),
+        None => normalize(&Path::new(dest).join(Path::new(src).file_name().unwrap_or_default())),
+    }
+}
+
+/// `pszCopyName`/`pszNewName` argument: null keeps the source name.
+fn new_name(name: &Option<HSTRING>) -> PCWSTR {
+    name.as_ref().map_or(PCWSTR::null(), |name| PCWSTR(name.as_ptr()))
+}
+
+pub(crate) unsafe fn get_operation(op: Option<OperationFlags>) -> Result<IFileOperation> {
+    let result:IFileOperation = CoCreateInstance(&FileOperation, None, CLSCTX_ALL)?;
+    if let Some(flags) = op {
+        result.SetOperationFlags(flags.into())?
+    }
+    Ok(result)
+}
+
+fn error_at(path: &str, kind: OperationKind) -> impl FnOnce(Error) -> crate::Error + '_ {
+    move |e| crate::Error::from_windows(&e, Some(Path::new(path)), Some(kind))
+}
+
+/// Queue one step. Failures name the path that could not be used, which for a
+/// destination folder is not the step's own item.
+unsafe fn queue(operation: &IFileOperation, step: &Step, pending: &mut Vec<PathBuf>) -> crate::Result<()> {
+    let kind = step.kind();
+    let at = |path| error_at(path, kind);
+
+    match step {
+        Step::Copy { src, dest, name } => {
+            let copy_name = name.as_deref().map(HSTRING::from);
+            let item = get_item(src).map_err(at(src))?;
+            let folder = get_dest_item(dest, pending).map_err(at(dest))?;
+            operation.CopyItem(&item, &folder, new_name(&copy_name), None).map_err(at(src))?;
+            pending.push(created_in(dest, src, name));
+        }
+        Step::Move { src, dest, name } => {
+            let move_name = name.as_deref().map(HSTRING::from);
+            let item = get_item(src).map_err(at(src))?;
+            let folder = get_dest_item(dest, pending).map_err(at(dest))?;
+            operation.MoveItem(&item, &folder, new_name(&move_name), None).map_err(at(src))?;
+            pending.push(created_in(dest, src, name));
+        }
+        Step::Delete { target } => {
+            operation.DeleteItem(&get_item(target).map_err(at(target))?, None).map_err(at(target))?;
+        }
+        Step::Rename { src, new_name } => {
+            if !is_file_name(new_name) {
+                return Err(crate::Error::InvalidPath { path: PathBuf::from(src), op: kind });
+            }
+            let item = get_dest_item(src, pending).map_err(at(src))?;
+            operation.RenameItem(&item, &HSTRING::from(new_name), None).map_err(at(src))?;
+            pending.push(normalize(&Path::new(src).with_file_name(new_name)));
+        }
+        Step::NewFolder { root, name } => {
+            operation.NewItem(
+                &get_dest_item(root, pending).map_err(at(root))?,
+                FILE_ATTRIBUTE_DIRECTORY.0,
+                &HSTRING::from(name),
+                None, None).map_err(at(root))?;
+            pending.push(normalize(&Path::new(root).join(name)));
+        }
+        Step::NewFile { root, name } => {
+            operation.NewItem(
+                &get_dest_item(root, pending).map_err(at(root))?,
+                FILE_ATTRIBUTE_NORMAL.0,
+                &HSTRING::from(name),
+                None, None).map_err(at(root))?;
+        }
+    }
+    Ok(())
+}
+
+/// Per-item results the shell reported, keyed by kind and normalized path.
+pub(crate) type Outcomes = HashMap<(OperationKind, PathBuf), VecDeque<(crate::Result<()>, Option<PathBuf>)>>;
+
+/// `IFileOperationProgressSink` forwarding the queued items to the caller's
+/// [`ProgressSink`] and recording how each one ended.
+#[implement(IFileOperationProgressSink)]
+struct Advised {
+    /// Only valid while the sink is advised, see [`perform_advised`].
+    user: *mut (dyn ProgressSink + 'static),
+    queued: HashSet<(OperationKind, PathBuf)>,
+    progress: Cell<Progress>,
+    outcomes: Rc<RefCell<Outcomes>>,
+}
+
+/// `COPYENGINE_S_USER_IGNORED`: the user chose "Skip" for this item.
+const USER_IGNORED: HRESULT = HRESULT(0x0027_0005);
+
+unsafe fn item_path(item: Option<&IShellItem>) -> Option<PathBuf> {
+    let name = item?.GetDisplayName(SIGDN_FILESYSPATH).ok()?;
+    let path = name.to_string().ok().map(PathBuf::from);
+    CoTaskMemFree(Some(name.0 as *const _));
+    path
+}
+
+impl Advised {
+    fn pre(&self, op: OperationKind, path: Option<PathBuf>) -> Result<()> {
+        if let Some(path) = path.filter(|path| self.queued.contains(&(op, normalize(path)))) {
+            unsafe { (*self.user).pre_item(op, &path) };
+        }
+        Ok(())
+    }
+
+    fn post(&self, op: OperationKind, path: Option<PathBuf>, hr: HRESULT, result: Option<&IShellItem>) -> Result<()> {
+        let Some(path) = path.filter(|path| self.queued.contains(&(op, normalize(path)))) else {
+            return Ok(());
+        };
+        let status = if hr == USER_IGNORED {
+            Err(crate::Error::Aborted { path: Some(path.clone()), op: Some(op) })
+        } else if hr.is_err() {
+            Err(crate::Error::from_windows(&hr.into(), Some(&path), Some(op)))
+        } else {
+            Ok(())
+        };
+        let mut progress = self.progress.get();
+        progress.items_done += 1;
+        self.progress.set(progress);
+        let result_path = unsafe { item_path(result) };
+        unsafe {
+            (*self.user).post_item(op, &path, result_path.as_deref(), &status);
+            (*self.user).progress(&progress);
+        }
+        self.outcomes.borrow_mut().entry((op, normalize(&path))).or_default().push_back((status, result_path));
+        Ok(())
+    }
+}
+
+fn folder_child(folder: Option<&IShellItem>, name: &PCWSTR) -> Option<PathBuf> {
+    let name = unsafe { name.to_string() }.ok()?;
+    unsafe { item_path(folder) }.map(|folder| folder.join(name))
+}
+
+#[allow(non_snake_case)]
+impl IFileOperationProgressSink_Impl for Advised {
+    fn StartOperations(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn FinishOperations(&self, _hrresult: HRESULT) -> Result<()> {
+        Ok(())
+    }
+
+    fn PreRenameItem(&self, _dwflags: u32, psiitem: Option<&IShellItem>, _psznewname: &PCWSTR) -> Result<()> {
+        self.pre(OperationKind::Rename, unsafe { item_path(psiitem) })
+    }
+
+    fn PostRenameItem(&self, _dwflags: u32, psiitem: Option<&IShellItem>, _psznewname: &PCWSTR, hrrename: HRESULT, psinewlycreated: Option<&IShellItem>) -> Result<()> {
+        self.post(OperationKind::Rename, unsafe { item_path(psiitem) }, hrrename, psinewlycreated)
+    }
+
+    fn PreMoveItem(&self, _dwflags: u32, psiitem: Option<&IShellItem>, _psidestinationfolder: Option<&IShellItem>, _psznewname: &PCWSTR) -> Result<()> {
+        self.pre(OperationKind::Move, unsafe { item_path(psiitem) })
+    }
+
+    fn PostMoveItem(&self, _dwflags: u32, psiitem: Option<&IShellItem>, _psidestinationfolder: Option<&IShellItem>, _psznewname: &PCWSTR, hrmove: HRESULT, psinewlycreated: Option<&IShellItem>) -> Result<()> {
+        self.post(OperationKind::Move, unsafe { item_path(psiitem) }, hrmove, psinewlycreated)
+    }
+
+    fn PreCopyItem(&self, _dwflags: u32, psiitem: Option<&IShellItem>, _psidestinationfolder: Option<&IShellItem>, _psznewname: &PCWSTR) -> Result<()> {
+        self.pre(OperationKind::Copy, unsafe { item_path(psiitem) })
+    }
+
+    fn PostCopyItem(&self, _dwflags: u32, psiitem: Option<&IShellItem>, _psidestinationfolder: Option<&IShellItem>, _psznewname: &PCWSTR, hrcopy: HRESULT, psinewlycreated: Option<&IShellItem>) -> Result<()> {
+        self.post(OperationKind::Copy, unsafe { item_path(psiitem) }, hrcopy, psinewlycreated)
+    }
+
+    fn PreDeleteItem(&self, _dwflags: u32, psiitem: Option<&IShellItem>) -> Result<()> {
+        self.pre(OperationKind::Delete, unsafe { item_path(psiitem) })
+    }
+
+    fn PostDeleteItem(&self, _dwflags: u32, psiitem: Option<&IShellItem>, hrdelete: HRESULT, psinewlycreated: Option<&IShellItem>) -> Result<()> {
+        self.post(OperationKind::Delete, unsafe { item_path(psiitem) }, hrdelete, psinewlycreated)
+    }
+
+    fn PreNewItem(&self, _dwflags: u32, psidestinationfolder: Option<&IShellItem>, psznewname: &PCWSTR) -> Result<()> {
+        // The flags are transfer flags, not attributes: the queued step tells
+        // a new folder from a new file.
+        let path = folder_child(psidestinationfolder, psznewname);
+        let folder = path.as_ref().is_some_and(|path| self.queued.contains(&(OperationKind::NewFolder, normalize(path))));
+        self.pre(if folder { OperationKind::NewFolder } else { OperationKind::NewFile }, path)
+    }
+
+    fn PostNewItem(&self, _dwflags: u32, psidestinationfolder: Option<&IShellItem>, psznewname: &PCWSTR, _psztemplatename: &PCWSTR, dwfileattributes: u32, hrnew: HRESULT, psinewitem: Option<&IShellItem>) -> Result<()> {
+        let kind = if dwfileattributes & FILE_ATTRIBUTE_DIRECTORY.0 != 0 { OperationKind::NewFolder } else { OperationKind::NewFile };
+        self.post(kind, folder_child(psidestinationfolder, psznewname), hrnew, psinewitem)
+    }
+
+    fn UpdateProgress(&self, iworktotal: u32, iworksofar: u32) -> Result<()> {
+        // Work units, not bytes: their share of the total applies to the bytes.
+        let mut progress = self.progress.get();
+        if iworktotal > 0 {
+            let done = u128::from(progress.bytes_total) * u128::from(iworksofar.min(iworktotal)) / u128::from(iworktotal);
+            progress.bytes_done = done as u64;
+        }
+        self.progress.set(progress);
+        unsafe { (*self.user).progress(&progress) };
+        Ok(())
+    }
+
+    fn ResetTimer(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn PauseTimer(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn ResumeTimer(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// `PerformOperations` with an advised sink, returning what the shell said
+/// about each queued item.
+pub(crate) unsafe fn perform_advised(operation: &IFileOperation, report: &OperationReport, sink: &mut dyn ProgressSink) -> Result<Outcomes> {
+    let queued: HashSet<_> = report.items.iter()
+        .filter(|item| item.result.is_ok())
+        .map(|item| (item.op, normalize(&item.path)))
+        .collect();
+    let bytes_total = report.items.iter()
+        .filter(|item| item.result.is_ok() && matches!(item.op, OperationKind::Copy | OperationKind::Move))
+        .map(|item| size_of(&item.path))
+        .sum();
+    let outcomes = Rc::new(RefCell::new(Outcomes::new()));
+    let advised: IFileOperationProgressSink = Advised {
+        // SAFETY: the shell only calls the sink between Advise and Unadvise,
+        // both within this function, so the borrow outlives every call.
+        user: std::mem::transmute::<&mut dyn ProgressSink, &'static mut dyn ProgressSink>(sink),
+        progress: Cell::new(Progress { items_total: queued.len() as u64, bytes_total, ..Progress::default() }),
+        queued,
+        outcomes: outcomes.clone(),
+    }.into();
+
+    let cookie = operation.Advise(&advised)?;
+    let result = operation.PerformOperations();
+    // Best effort: what the operation did matters more than a failed Unadvise.
+    let _ = operation.Unadvise(cookie);
+    result?;
+    // The shell may still hold the sink: take the outcomes out of it.
+    let outcomes = std::mem::take(&mut *outcomes.borrow_mut());
+    Ok(outcomes)
+}
+
+/// Queue every step, then run them as one `IFileOperation`. Steps that cannot
+/// be queued are reported and left out; with `FOFX_EARLYFAILURE` the first
+/// such step cancels the whole batch before anything runs. The shell copies
+/// its own way: the copy options do not apply.
+pub fn perform(steps: &[Step], flags: Option<OperationFlags>, _options: &CopyOptions, sink: &mut dyn ProgressSink) -> crate::Result<OperationReport> {
+    let early_failure = flags.is_some_and(|flags| flags.contains(OperationFlags::FOFX_EARLYFAILURE));
+    let mut report = OperationReport::default();
+    let mut outcomes = Outcomes::new();
+    unsafe {
+        let operation = get_operation(flags).map_err(|e| crate::Error::from_windows(&e, None, None))?;
+        let mut pending: Vec<PathBuf> = Vec::new();
+
+        sink.start();
+        for step in steps {
+            let result = queue(&operation, step, &mut pending);
+            let failed = result.is_err();
+            report.push(step.kind(), step.path(), None, result);
+            if failed && early_failure {
+                sink.finish(&report);
+                return Ok(report);
+            }
+        }
+        if report.items.iter().any(|item| item.result.is_ok()) {
+            match perform_advised(&operation, &report, sink) {
+                Ok(advised) => outcomes = advised,
+                Err(e) => match crate::Error::from_windows(&e, None, None) {
+                    crate::Error::Aborted { .. } => report.aborted = true,
+                    error => return Err(error),
+                },
+            }
+            report.aborted |= operation.GetAnyOperationsAborted().is_ok_and(|aborted| aborted.as_bool());
+        }
+    }
+    for item in report.items.iter_mut().filter(|item| item.result.is_ok()) {
+        match outcomes.get_mut(&(item.op, normalize(&item.path))).and_then(VecDeque::pop_front) {
+            Some((result, result_path)) => {
+                item.result = result;
+                item.result_path = result_path;
+            }
+            // Never reached by the shell: cancelled before its turn.
+            None if report.aborted => item.result = Err(crate::Error::Aborted { path: Some(item.path.clone()), op: Some(item.op) }),
+            None => {}
+        }
+    }
+    // The shell copies and deletes what goes to another volume.
+    for (item, step) in report.items.iter_mut().zip(steps) {
+        if let (Step::Move { src, dest, .. }, Ok(())) = (step, &item.result) {
+            let same = same_volume(Path::new(src), Path::new(dest));
+            item.strategy = Some(if same { MoveStrategy::Rename } else { MoveStrategy::CopyDelete });
+        }
+    }
+    sink.finish(&report);
+    Ok(report)
+}
@@ -0,0 +1,970 @@
+//! Pure `std::fs` backend used on every target that has no `IFileOperation`.
+//!
+//! The functions mirror what Explorer does for the same request: items are
+//! copied or moved *into* the destination folder, folders are handled
+//! recursively and name collisions are resolved with the same flags the
+//! shell understands. See [`crate::OperationFlags`] for the flags that apply.
+
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use crate::operation::{is_file_name, Step};
+use crate::plan::{same_volume, size_of};
+use crate::verify::verify_item;
+use crate::{Checksum, CopyOptions, ItemOutcome, MoveStrategy, OperationFlags, OperationKind, OperationReport, Progress, ProgressSink, Reflink};
+
+/// Payload of an `io::Error` about another path than the step's own item,
+/// e.g. the destination folder or the existing target.
+#[derive(Debug)]
+struct AtPath {
+    path: PathBuf,
+    message: String,
+}
+
+impl fmt::Display for AtPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for AtPath {}
+
+fn at(path: &Path) -> impl FnOnce(Error) -> Error + '_ {
+    move |e| Error::new(e.kind(), AtPath { path: path.to_owned(), message: e.to_string() })
+}
+
+/// Forwards byte and item counts to the caller's sink.
+struct Tracker<'a> {
+    sink: &'a mut dyn ProgressSink,
+    progress: Progress,
+}
+
+impl Tracker<'_> {
+    fn set_bytes(&mut self, bytes_done: u64) {
+        self.progress.bytes_done = bytes_done;
+        self.sink.progress(&self.progress);
+    }
+
+    fn add_bytes(&mut self, bytes: u64) {
+        self.set_bytes(self.progress.bytes_done + bytes);
+    }
+}
+
+/// The path an error is about, falling back to the step's item.
+fn error_path(error: &Error, step: &Step) -> PathBuf {
+    match error.get_ref().and_then(|e| e.downcast_ref::<AtPath>()) {
+        Some(at) => at.path.clone(),
+        None => step.path(),
+    }
+}
+
+fn file_name(src: &Path) -> Result<&std::ffi::OsStr> {
+    src.file_name().ok_or_else(|| Error::new(
+        ErrorKind::InvalidInput,
+        format!("{}: path has no file name", src.display())
+    ))
+}
+
+fn dest_folder(dest: &str) -> Result<&Path> {
+    let dest = Path::new(dest);
+    if !fs::metadata(dest).map_err(at(dest))?.is_dir() {
+        return Err(at(dest)(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{}: destination is not a folder", dest.display())
+        )));
+    }
+    Ok(dest)
+}
+
+/// `file.txt` -> `file (2).txt`, `file (3).txt`... like Explorer does.
+pub(crate) fn unique_name(target: &Path) -> PathBuf {
+    let stem = target.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = target.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    let mut n = 2;
+    loop {
+        let candidate = target.with_file_name(format!("{} ({}){}", stem, n, ext));
+        if fs::symlink_metadata(&candidate).is_err() {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+/// How to treat an item that already exists at the target path.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Collision {
+    /// Nothing there, just go ahead.
+    Free,
+    /// Replace files and merge folders.
+    Overwrite,
+}
+
+pub(crate) fn resolve_target(target: PathBuf, flags: OperationFlags) -> Result<(PathBuf, Collision)> {
+    if fs::symlink_metadata(&target).is_err() {
+        return Ok((target, Collision::Free));
+    }
+    if flags.contains(OperationFlags::FOF_RENAMEONCOLLISION) {
+        return Ok((unique_name(&target), Collision::Free));
+    }
+    if flags.contains(OperationFlags::FOF_NOCONFIRMATION) {
+        return Ok((target, Collision::Overwrite));
+    }
+    Err(at(&target)(Error::new(
+        ErrorKind::AlreadyExists,
+        format!("{}: target already exists", target.display())
+    )))
+}
+
+pub(crate) fn remove_item(target: &Path) -> Result<()> {
+    if fs::symlink_metadata(target)?.is_dir() {
+        fs::remove_dir_all(target)
+    } else {
+        fs::remove_file(target)
+    }
+}
+
+#[cfg(unix)]
+fn copy_link(src: &Path, target: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(src)?, target)
+}
+
+#[cfg(not(unix))]
+fn copy_link(src: &Path, target: &Path) -> Result<()> {
+    fs::copy(src, target).map(|_| ())
+}
+
+/// `FOFX_KEEPNEWERFILE`: an existing target modified after the source wins.
+fn keep_newer(src: &fs::Metadata, target: &Path) -> bool {
+    match (src.modified(), fs::metadata(target).and_then(|m| m.modified())) {
+        (Ok(src), Ok(target)) => target > src,
+        _ => false,
+    }
+}
+
+/// Copy the content of one file, returning the number of bytes.
+fn copy_contents(src: &Path, target: &Path, flags: OperationFlags, reflink: Reflink) -> Result<u64> {
+    if flags.contains(OperationFlags::FOFX_PREFERHARDLINK) && fs::hard_link(src, target).is_ok() {
+        return fs::metadata(target).map(|meta| meta.len());
+    }
+    if flags.contains(OperationFlags::FOF_NOCOPYSECURITYATTRIBS) {
+        // A freshly created file gets the default permissions.
+        let mut reader = fs::File::open(src)?;
+        let mut writer = fs::File::create(target)?;
+        return std::io::copy(&mut reader, &mut writer);
+    }
+    #[cfg(target_os = "linux")]
+    let bytes = crate::fastcopy::copy(src, target, reflink)?;
+    #[cfg(not(target_os = "linux"))]
+    let bytes = {
+        let _ = reflink;
+        fs::copy(src, target)?
+    };
+    // Like the shell, the copy keeps the modification time; a read handle is
+    // enough for it, so read-only copies keep it too.
+    if let Ok(modified) = fs::metadata(src).and_then(|meta| meta.modified()) {
+        let _ = fs::File::open(target).and_then(|file| file.set_modified(modified));
+    }
+    Ok(bytes)
+}
+
+/// Make `target` the folder `src` is copied to, merging into an existing
+/// folder and replacing a file.
+fn create_folder(src: &Path, target: &Path) -> Result<()> {
+    if target.starts_with(src) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{}: cannot copy a folder into itself", src.display())
+        ));
+    }
+    match fs::symlink_metadata(target) {
+        Ok(existing) if existing.is_dir() => Ok(()),
+        Ok(_) => {
+            fs::remove_file(target)?;
+            fs::create_dir(target)
+        }
+        Err(_) => fs::create_dir(target),
+    }
+}
+
+/// Copy one file or link to `target`, returning the number of bytes done.
+fn copy_leaf(src: &Path, meta: &fs::Metadata, target: &Path, flags: OperationFlags, reflink: Reflink) -> Result<u64> {
+    if let Ok(existing) = fs::symlink_metadata(target) {
+        if flags.contains(OperationFlags::FOFX_KEEPNEWERFILE) && existing.is_file() && keep_newer(meta, target) {
+            return Ok(meta.len());
+        }
+        if existing.is_dir() || existing.file_type().is_symlink() {
+            remove_item(target)?;
+        } else if flags.contains(OperationFlags::FOFX_PREFERHARDLINK) {
+            fs::remove_file(target)?;
+        }
+    }
+    if meta.file_type().is_symlink() {
+        copy_link(src, target).map(|()| 0)
+    } else {
+        copy_contents(src, target, flags, reflink)
+    }
+}
+
+/// Copy `src` to exactly `target`, recursing into folders. Existing files are
+/// replaced and existing folders merged; callers decide beforehand whether
+/// that is allowed.
+fn copy_item(src: &Path, target: &Path, flags: OperationFlags, reflink: Reflink, depth: usize, tracker: &mut Tracker) -> Result<()> {
+    let meta = fs::symlink_metadata(src)?;
+    if !meta.is_dir() {
+        tracker.add_bytes(copy_leaf(src, &meta, target, flags, reflink)?);
+        return Ok(());
+    }
+    create_folder(src, target)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        if depth == 0 && flags.contains(OperationFlags::FOF_NORECURSION) && entry.file_type()?.is_dir() {
+            continue;
+        }
+        copy_item(&entry.path(), &target.join(entry.file_name()), flags, reflink, depth + 1, tracker)?;
+    }
+    if flags.contains(OperationFlags::FOF_NOCOPYSECURITYATTRIBS) {
+        return Ok(());
+    }
+    fs::set_permissions(target, meta.permissions())
+}
+
+/// A file of a folder copied by [`copy_tree`].
+struct Job {
+    src: PathBuf,
+    meta: fs::Metadata,
+    target: PathBuf,
+}
+
+/// The walk of a folder copied by [`copy_tree`]: creates the folders and
+/// queues the files, taking in what the workers report as it goes.
+struct Walk<'a, 't, 's> {
+    results: &'a mpsc::Receiver<Result<u64>>,
+    failed: &'a AtomicBool,
+    flags: OperationFlags,
+    /// Folders with the permissions they get once their files are copied.
+    folders: Vec<(PathBuf, fs::Permissions)>,
+    error: Option<Error>,
+    tracker: &'t mut Tracker<'s>,
+}
+
+impl Walk<'_, '_, '_> {
+    fn settle(&mut self, result: Result<u64>) {
+        match result {
+            Ok(bytes) => self.tracker.add_bytes(bytes),
+            Err(e) => {
+                self.failed.store(true, Ordering::Relaxed);
+                self.error.get_or_insert(e);
+            }
+        }
+    }
+
+    fn walk(&mut self, jobs: &mpsc::SyncSender<Job>, src: &Path, target: &Path, depth: usize) -> Result<()> {
+        let meta = fs::symlink_metadata(src)?;
+        if !meta.is_dir() {
+            // Waits while the queue is full.
+            let job = Job { src: src.to_owned(), meta, target: target.to_owned() };
+            jobs.send(job).map_err(|_| Error::other("the copy workers stopped"))?;
+            while let Ok(result) = self.results.try_recv() {
+                self.settle(result);
+            }
+            return Ok(());
+        }
+        create_folder(src, target)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            if depth == 0 && self.flags.contains(OperationFlags::FOF_NORECURSION) && entry.file_type()?.is_dir() {
+                continue;
+            }
+            if self.failed.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            self.walk(jobs, &entry.path(), &target.join(entry.file_name()), depth + 1)?;
+        }
+        if !self.flags.contains(OperationFlags::FOF_NOCOPYSECURITYATTRIBS) {
+            self.folders.push((target.to_owned(), meta.permissions()));
+        }
+        Ok(())
+    }
+}
+
+/// [`copy_item`] for a folder, with its files copied by a pool of workers.
+/// Every folder is created before the files in it are queued, and gets its
+/// permissions once all files are copied. The first failure stops the copy.
+fn copy_tree(src: &Path, target: &Path, flags: OperationFlags, options: &CopyOptions, tracker: &mut Tracker) -> Result<()> {
+    let (jobs, queue) = mpsc::sync_channel::<Job>(options.queue);
+    let (copied, results) = mpsc::channel();
+    let queue = Mutex::new(queue);
+    let failed = AtomicBool::new(false);
+    let reflink = options.reflink;
+
+    let (folders, error) = thread::scope(|scope| {
+        for _ in 0..options.workers() {
+            let (queue, failed, copied) = (&queue, &failed, copied.clone());
+            scope.spawn(move || loop {
+                let Ok(Ok(job)) = queue.lock().map(|queue| queue.recv()) else {
+                    break;
+                };
+                // What is left after a failure is only taken off the queue.
+                if failed.load(Ordering::Relaxed) {
+                    continue;
+                }
+                let result = copy_leaf(&job.src, &job.meta, &job.target, flags, reflink).map_err(at(&job.src));
+                if result.is_err() {
+                    failed.store(true, Ordering::Relaxed);
+                }
+                if copied.send(result).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(copied);
+        let mut walk = Walk { results: &results, failed: &failed, flags, folders: Vec::new(), error: None, tracker };
+        if let Err(e) = walk.walk(&jobs, src, target, 0) {
+            walk.settle(Err(e));
+        }
+        // The workers stop once the queue is empty and closed.
+        drop(jobs);
+        for result in results.iter() {
+            walk.settle(result);
+        }
+        (walk.folders, walk.error)
+    });
+    if let Some(e) = error {
+        return Err(e);
+    }
+    for (folder, permissions) in folders {
+        fs::set_permissions(folder, permissions)?;
+    }
+    Ok(())
+}
+
+/// A hidden free name next to `target`, `.name.suffix`.
+fn hidden(target: &Path, suffix: &str) -> Result<PathBuf> {
+    let name = file_name(target)?.to_string_lossy();
+    let hidden = target.with_file_name(format!(".{}.{}", name, suffix));
+    match fs::symlink_metadata(&hidden) {
+        Ok(_) => Ok(unique_name(&hidden)),
+        Err(_) => Ok(hidden),
+    }
+}
+
+/// Rename `staging` to `target`. What is at `target` is renamed aside first
+/// and put back if that fails; returns where it went.
+pub(crate) fn place(staging: &Path, target: &Path) -> Result<Option<PathBuf>> {
+    let aside = match fs::symlink_metadata(target) {
+        Ok(_) => {
+            let aside = hidden(target, "ifop-replaced")?;
+            fs::rename(target, &aside)?;
+            Some(aside)
+        }
+        Err(_) => None,
+    };
+    if let Err(e) = fs::rename(staging, target) {
+        if let Some(aside) = &aside {
+            let _ = fs::rename(aside, target);
+        }
+        return Err(e);
+    }
+    Ok(aside)
+}
+
+/// Undo [`place`]: the copy back to `staging`, what it replaced back to `target`.
+fn unplace(staging: &Path, target: &Path, aside: Option<&Path>) -> Result<()> {
+    fs::rename(target, staging)?;
+    match aside {
+        Some(aside) => fs::rename(aside, target),
+        None => Ok(()),
+    }
+}
+
+/// A move to another file system, done in three steps: every item is copied
+/// next to its target under a temporary name and checked against its
+/// source, then put in place, then its source deleted. When a copy fails, or
+/// a copy cannot be put in place, what was done so far is rolled back and
+/// nothing has moved.
+struct Across {
+    flags: OperationFlags,
+    reflink: Reflink,
+    /// `(src, temporary copy, target)`
+    staged: Vec<(PathBuf, PathBuf, PathBuf)>,
+    /// Source folders merged into existing ones, emptied by the move; the
+    /// deepest first.
+    merged: Vec<PathBuf>,
+}
+
+impl Across {
+    fn stage(&mut self, src: &Path, target: &Path, collision: Collision, tracker: &mut Tracker) -> Result<()> {
+        let src_meta = fs::symlink_metadata(src)?;
+        if collision == Collision::Overwrite {
+            let target_is_dir = fs::symlink_metadata(target)?.is_dir();
+            if src_meta.is_dir() && target_is_dir {
+                for entry in fs::read_dir(src)? {
+                    let entry = entry?;
+                    let child = target.join(entry.file_name());
+                    let collision = if fs::symlink_metadata(&child).is_ok() { Collision::Overwrite } else { Collision::Free };
+                    self.stage(&entry.path(), &child, collision, tracker)?;
+                }
+                self.merged.push(src.to_owned());
+                return Ok(());
+            }
+            if self.flags.contains(OperationFlags::FOFX_KEEPNEWERFILE) && !target_is_dir && keep_newer(&src_meta, target) {
+                return Ok(());
+            }
+        }
+        let staging = hidden(target, "ifop-move")?;
+        self.staged.push((src.to_owned(), staging.clone(), target.to_owned()));
+
+        let flags = self.flags - OperationFlags::FOF_NORECURSION;
+        copy_item(src, &staging, flags, self.reflink, 0, tracker)?;
+        verify_item(src, &staging, Checksum::Blake3, flags - OperationFlags::FOFX_KEEPNEWERFILE, OperationKind::Move, 0)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    fn perform(mut self, src: &Path, target: &Path, collision: Collision, tracker: &mut Tracker) -> Result<()> {
+        if let Err(e) = self.stage(src, target, collision, tracker) {
+            for (_, staging, _) in &self.staged {
+                let _ = remove_item(staging);
+            }
+            return Err(e);
+        }
+        self.finish(src)
+    }
+
+    /// Put the staged copies in place, then delete their sources.
+    fn finish(&self, src: &Path) -> Result<()> {
+        let mut placed = Vec::new();
+        for (_, staging, target) in &self.staged {
+            match place(staging, target) {
+                Ok(aside) => placed.push((staging, target, aside)),
+                Err(e) => {
+                    for (staging, target, aside) in placed.into_iter().rev() {
+                        if let Err(undo) = unplace(staging, target, aside.as_deref()) {
+                            return Err(Error::other(format!(
+                                "{}: partly moved, {} could not be put back: {}",
+                                src.display(), target.display(), undo
+                            )));
+                        }
+                    }
+                    for (_, staging, _) in &self.staged {
+                        let _ = remove_item(staging);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        // Everything is in place: what it replaced can go.
+        for aside in placed.into_iter().filter_map(|(_, _, aside)| aside) {
+            let _ = remove_item(&aside);
+        }
+        for (src, _, _) in &self.staged {
+            remove_item(src)?;
+        }
+        for folder in &self.merged {
+            remove_merged(folder, self.flags)?;
+        }
+        Ok(())
+    }
+}
+
+/// Remove a source folder a merge emptied. Files `FOFX_KEEPNEWERFILE` left
+/// behind keep it, and the folders above it, where they are.
+fn remove_merged(folder: &Path, flags: OperationFlags) -> Result<()> {
+    match fs::remove_dir(folder) {
+        Err(e) if e.kind() == ErrorKind::DirectoryNotEmpty && flags.contains(OperationFlags::FOFX_KEEPNEWERFILE) => Ok(()),
+        result => result,
+    }
+}
+
+/// Move `src` to exactly `target`, with a plain rename on the same file
+/// system and [`Across`] to another one. An existing folder is merged into
+/// item by item.
+fn move_item(src: &Path, target: &Path, collision: Collision, flags: OperationFlags, reflink: Reflink, tracker: &mut Tracker) -> Result<MoveStrategy> {
+    let across = Across { flags, reflink, staged: Vec::new(), merged: Vec::new() };
+    if !same_volume(src, target.parent().unwrap_or(target)) {
+        across.perform(src, target, collision, tracker)?;
+        return Ok(MoveStrategy::CopyDelete);
+    }
+    if collision == Collision::Overwrite {
+        let src_meta = fs::symlink_metadata(src)?;
+        let target_is_dir = fs::symlink_metadata(target)?.is_dir();
+        if src_meta.is_dir() && target_is_dir {
+            let mut strategy = MoveStrategy::Rename;
+            for entry in fs::read_dir(src)? {
+                let entry = entry?;
+                let child = target.join(entry.file_name());
+                let collision = if fs::symlink_metadata(&child).is_ok() { Collision::Overwrite } else { Collision::Free };
+                strategy = strategy.max(move_item(&entry.path(), &child, collision, flags, reflink, tracker)?);
+            }
+            remove_merged(src, flags)?;
+            return Ok(strategy);
+        }
+        if flags.contains(OperationFlags::FOFX_KEEPNEWERFILE) && !target_is_dir && keep_newer(&src_meta, target) {
+            // The older source stays where it is.
+            return Ok(MoveStrategy::Rename);
+        }
+        if src_meta.is_dir() != target_is_dir {
+            remove_item(target)?;
+        }
+    }
+
+    match fs::rename(src, target) {
+        Ok(()) => Ok(MoveStrategy::Rename),
+        // Two mounts of the same device.
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            let collision = if fs::symlink_metadata(target).is_ok() { Collision::Overwrite } else { Collision::Free };
+            across.perform(src, target, collision, tracker)?;
+            Ok(MoveStrategy::CopyDelete)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Where `src` ends up inside `dest`: its own name unless a new one is given.
+fn target_in(src: &Path, dest: &str, name: Option<&str>) -> Result<PathBuf> {
+    let dest = dest_folder(dest)?;
+    match name {
+        Some(name) => Ok(dest.join(name)),
+        None => Ok(dest.join(file_name(src)?)),
+    }
+}
+
+fn copy(src: &str, dest: &str, name: Option<&str>, flags: OperationFlags, options: &CopyOptions, tracker: &mut Tracker) -> Result<PathBuf> {
+    let src = Path::new(src);
+    let target = target_in(src, dest, name)?;
+    let meta = fs::symlink_metadata(src)?;
+
+    // Copying an item onto itself always produces a renamed copy.
+    let (target, _) = if target == src {
+        (unique_name(&target), Collision::Free)
+    } else {
+        resolve_target(target, flags)?
+    };
+    if meta.is_dir() && options.workers() > 1 {
+        copy_tree(src, &target, flags, options, tracker)?;
+    } else {
+        copy_item(src, &target, flags, options.reflink, 0, tracker)?;
+    }
+    Ok(target)
+}
+
+fn move_to(src: &str, dest: &str, name: Option<&str>, flags: OperationFlags, reflink: Reflink, tracker: &mut Tracker) -> Result<(PathBuf, MoveStrategy)> {
+    let src = Path::new(src);
+    let target = target_in(src, dest, name)?;
+    fs::symlink_metadata(src)?;
+    if target == src {
+        return Ok((target, MoveStrategy::Rename));
+    }
+    if target.starts_with(src) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{}: cannot move a folder into itself", src.display())
+        ));
+    }
+    let (target, collision) = resolve_target(target, flags)?;
+    // A rename moves every byte at once, a fallback copy counts as it goes.
+    let (bytes_done, size) = (tracker.progress.bytes_done, size_of(src));
+    let strategy = move_item(src, &target, collision, flags, reflink, tracker)?;
+    tracker.set_bytes(bytes_done + size);
+    Ok((target, strategy))
+}
+
+/// Delete `target`, into the trash when the flags allow undo.
+fn delete(target: &str, flags: OperationFlags) -> Result<Option<PathBuf>> {
+    if flags.intersects(OperationFlags::FOF_ALLOWUNDO | OperationFlags::FOFX_RECYCLEONDELETE) {
+        return recycle(Path::new(target)).map(Some);
+    }
+    remove_item(Path::new(target)).map(|_| None)
+}
+
+#[cfg(unix)]
+fn recycle(target: &Path) -> Result<PathBuf> {
+    crate::trash::trash(target)
+}
+
+#[cfg(not(unix))]
+fn recycle(target: &Path) -> Result<PathBuf> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        format!("{}: no trash on this platform", target.display())
+    ))
+}
+
+fn rename(src: &str, new_name: &str, flags: OperationFlags, reflink: Reflink, tracker: &mut Tracker) -> Result<PathBuf> {
+    let src = Path::new(src);
+    fs::symlink_metadata(src)?;
+    if !is_file_name(new_name) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("{}: not a file name", new_name)));
+    }
+    let target = src.with_file_name(new_name);
+    if target == src {
+        return Ok(target);
+    }
+    let (target, collision) = resolve_target(target, flags)?;
+    let bytes_done = tracker.progress.bytes_done;
+    move_item(src, &target, collision, flags, reflink, tracker)?;
+    // Renaming moves no content, even when the folder had to be merged.
+    tracker.progress.bytes_done = bytes_done;
+    Ok(target)
+}
+
+fn new_item(root: &str, name: &str, folder: bool, flags: OperationFlags) -> Result<PathBuf> {
+    let path = dest_folder(root)?.join(name);
+    let path = if fs::symlink_metadata(&path).is_ok() && flags.contains(OperationFlags::FOF_RENAMEONCOLLISION) {
+        unique_name(&path)
+    } else {
+        path
+    };
+    if folder {
+        fs::create_dir(&path).map_err(at(&path))?;
+    } else {
+        fs::OpenOptions::new().write(true).create_new(true).open(&path).map_err(at(&path))?;
+    }
+    Ok(path)
+}
+
+/// Run one step, returning where its item ended up and, for a move, how.
+fn run(step: &Step, flags: OperationFlags, options: &CopyOptions, tracker: &mut Tracker) -> Result<(Option<PathBuf>, Option<MoveStrategy>)> {
+    let result_path = match step {
+        Step::Copy { src, dest, name } => copy(src, dest, name.as_deref(), flags, options, tracker).map(Some),
+        Step::Move { src, dest, name } => {
+            let (target, strategy) = move_to(src, dest, name.as_deref(), flags, options.reflink, tracker)?;
+            return Ok((Some(target), Some(strategy)));
+        }
+        Step::Delete { target } => delete(target, flags),
+        Step::Rename { src, new_name } => rename(src, new_name, flags, options.reflink, tracker).map(Some),
+        Step::NewFolder { root, name } => new_item(root, name, true, flags).map(Some),
+        Step::NewFile { root, name } => new_item(root, name, false, flags).map(Some),
+    };
+    result_path.map(|result_path| (result_path, None))
+}
+
+/// Run the steps one after the other. A failed step is recorded and the next
+/// one runs anyway, unless `FOFX_EARLYFAILURE` is set; steps after an early
+/// failure are left out of the report. A conflict marks the report aborted,
+/// as declining the shell's conflict prompt would. Folders are copied as
+/// `options` say.
+pub fn perform(steps: &[Step], flags: Option<OperationFlags>, options: &CopyOptions, sink: &mut dyn ProgressSink) -> crate::Result<OperationReport> {
+    let flags = flags.unwrap_or_default();
+    let bytes_total = steps.iter()
+        .map(|step| match step {
+            Step::Copy { src, .. } | Step::Move { src, .. } => size_of(Path::new(src)),
+            _ => 0,
+        })
+        .sum();
+    let mut tracker = Tracker {
+        sink,
+        progress: Progress { items_total: steps.len() as u64, bytes_total, ..Progress::default() },
+    };
+    let mut report = OperationReport::default();
+
+    tracker.sink.start();
+    for step in steps {
+        let (kind, path) = (step.kind(), step.path());
+        tracker.sink.pre_item(kind, &path);
+        let result = run(step, flags, options, &mut tracker).map_err(|e| {
+            // A checked copy fails with the crate's own error.
+            if let Some(e) = e.get_ref().and_then(|e| e.downcast_ref::<crate::Error>()) {
+                return e.clone();
+            }
+            let error_path = error_path(&e, step);
+            crate::Error::from_io(e, &error_path, kind)
+        });
+        let (result_path, strategy, result) = match result {
+            Ok((result_path, strategy)) => (result_path, strategy, Ok(())),
+            Err(e) => (None, None, Err(e)),
+        };
+        tracker.sink.post_item(kind, &path, result_path.as_deref(), &result);
+        tracker.progress.items_done += 1;
+        tracker.sink.progress(&tracker.progress);
+
+        let failed = result.is_err();
+        if let Err(crate::Error::Conflict { .. } | crate::Error::Aborted { .. }) = result {
+            report.aborted = true;
+        }
+        report.items.push(ItemOutcome { op: kind, path, result_path, result, strategy });
+        if failed && flags.contains(OperationFlags::FOFX_EARLYFAILURE) {
+            break;
+        }
+    }
+    tracker.sink.finish(&report);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder(name: &str) -> PathBuf {
+        let folder = std::env::current_dir().unwrap().join("test").join(name);
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("src").join("sub")).unwrap();
+        fs::create_dir_all(folder.join("dest")).unwrap();
+        fs::write(folder.join("src").join("file1"), "new").unwrap();
+        fs::write(folder.join("src").join("sub").join("file2"), "new").unwrap();
+        folder
+    }
+
+    #[test]
+    fn test_collision_flags() {
+        let folder = folder("test_collision_flags");
+        let src = folder.join("src").join("file1");
+        let dest = folder.join("dest");
+        let (src, dest) = (src.to_str().unwrap(), dest.to_str().unwrap());
+        let mut sink = ();
+        let tracker = &mut Tracker { sink: &mut sink, progress: Progress::default() };
+        let options = &CopyOptions::default();
+
+        copy(src, dest, None, OperationFlags::empty(), options, tracker).unwrap();
+        assert_eq!(copy(src, dest, None, OperationFlags::empty(), options, tracker).unwrap_err().kind(), ErrorKind::AlreadyExists);
+        let step = Step::Copy { src: src.to_owned(), dest: dest.to_owned(), name: None };
+        let report = perform(&[step], None, &CopyOptions::default(), &mut ()).unwrap();
+        assert_eq!(report.status(), crate::OperationStatus::Aborted);
+        assert_eq!(
+            report.items[0].result,
+            Err(crate::Error::Conflict { path: folder.join("dest").join("file1"), op: crate::OperationKind::Copy })
+        );
+        assert_eq!(
+            report.into_result(),
+            Err(crate::Error::Aborted { path: Some(folder.join("dest").join("file1")), op: Some(crate::OperationKind::Copy) })
+        );
+        copy(src, dest, None, OperationFlags::FOF_RENAMEONCOLLISION, options, tracker).unwrap();
+        assert!(folder.join("dest").join("file1 (2)").exists());
+
+        fs::write(folder.join("dest").join("file1"), "kept").unwrap();
+        let newer = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        fs::File::options().write(true).open(folder.join("dest").join("file1")).unwrap().set_modified(newer).unwrap();
+        copy(src, dest, None, OperationFlags::FOF_NOCONFIRMATION | OperationFlags::FOFX_KEEPNEWERFILE, options, tracker).unwrap();
+        assert_eq!(fs::read_to_string(folder.join("dest").join("file1")).unwrap(), "kept");
+        copy(src, dest, None, OperationFlags::FOF_NOCONFIRMATION, options, tracker).unwrap();
+        assert_eq!(fs::read_to_string(folder.join("dest").join("file1")).unwrap(), "new");
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn test_keep_newer_move() {
+        let folder = folder("test_keep_newer_move");
+        fs::create_dir_all(folder.join("dest").join("src")).unwrap();
+        let kept = folder.join("dest").join("src").join("file1");
+        fs::write(&kept, "kept").unwrap();
+        let newer = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        fs::File::options().write(true).open(&kept).unwrap().set_modified(newer).unwrap();
+
+        // The older file stays in its folder, the rest is merged.
+        let step = Step::Move { src: folder.join("src").to_str().unwrap().to_owned(), dest: folder.join("dest").to_str().unwrap().to_owned(), name: None };
+        let flags = OperationFlags::FOF_NOCONFIRMATION | OperationFlags::FOFX_KEEPNEWERFILE;
+        let report = perform(&[step], Some(flags), &CopyOptions::default(), &mut ()).unwrap();
+        assert!(report.is_ok() && !report.aborted);
+        assert_eq!(report.items[0].result_path.as_deref(), Some(folder.join("dest").join("src").as_path()));
+        assert_eq!(fs::read_to_string(&kept).unwrap(), "kept");
+        assert_eq!(fs::read_to_string(folder.join("dest").join("src").join("sub").join("file2")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(folder.join("src").join("file1")).unwrap(), "new");
+        assert!(!folder.join("src").join("sub").exists());
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn test_norecursion_flag() {
+        let folder = folder("test_norecursion_flag");
+        let src = folder.join("src");
+        let dest = folder.join("dest");
+        let mut sink = ();
+        let tracker = &mut Tracker { sink: &mut sink, progress: Progress::default() };
+        let options = &CopyOptions::default();
+
+        copy(src.to_str().unwrap(), dest.to_str().unwrap(), None, OperationFlags::FOF_NORECURSION, options, tracker).unwrap();
+        assert!(dest.join("src").join("file1").exists());
+        assert!(!dest.join("src").join("sub").exists());
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn test_parallel_copy() {
+        let folder = folder("test_parallel_copy");
+        for i in 0..20 {
+            let sub = folder.join("src").join(format!("dir{}", i % 4)).join("deeper");
+            fs::create_dir_all(&sub).unwrap();
+            fs::write(sub.join(format!("file{}", i)), i.to_string()).unwrap();
+        }
+        let (src, dest) = (folder.join("src"), folder.join("dest"));
+        let (src, dest) = (src.to_str().unwrap(), dest.to_str().unwrap());
+        let mut sink = ();
+        let tracker = &mut Tracker { sink: &mut sink, progress: Progress::default() };
+        let options = &CopyOptions::new().threads(4).queue(2);
+        assert_eq!(CopyOptions::default().workers(), thread::available_parallelism().unwrap().get());
+
+        let target = copy(src, dest, None, OperationFlags::empty(), options, tracker).unwrap();
+        assert_eq!(tracker.progress.bytes_done, size_of(Path::new(src)));
+        for i in 0..20 {
+            let file = target.join(format!("dir{}", i % 4)).join("deeper").join(format!("file{}", i));
+            assert_eq!(fs::read_to_string(file).unwrap(), i.to_string());
+        }
+        assert_eq!(fs::read_to_string(target.join("sub").join("file2")).unwrap(), "new");
+
+        // A file where the copy has a folder is replaced, the rest merged.
+        let in_the_way = target.join("dir1").join("deeper");
+        fs::remove_dir_all(&in_the_way).unwrap();
+        fs::write(&in_the_way, "").unwrap();
+        copy(src, dest, None, OperationFlags::FOF_NOCONFIRMATION, options, tracker).unwrap();
+        assert_eq!(fs::read_to_string(in_the_way.join("file1")).unwrap(), "1");
+
+        let into_itself = copy(src, folder.join("src").join("dir0").to_str().unwrap(), None, OperationFlags::empty(), options, tracker);
+        assert_eq!(into_itself.unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_move_across() {
+        let folder = folder("test_move_across");
+        let (src, dest) = (folder.join("src"), folder.join("dest"));
+        let mut sink = ();
+        let tracker = &mut Tracker { sink: &mut sink, progress: Progress::default() };
+        let across = || Across { flags: OperationFlags::empty(), reflink: Reflink::Auto, staged: Vec::new(), merged: Vec::new() };
+
+        // Merged into an existing folder: replaced, added and kept files.
+        fs::create_dir_all(dest.join("src").join("sub")).unwrap();
+        fs::write(dest.join("src").join("file1"), "old").unwrap();
+        fs::write(dest.join("src").join("kept"), "kept").unwrap();
+        across().perform(&src, &dest.join("src"), Collision::Overwrite, tracker).unwrap();
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dest.join("src").join("file1")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(dest.join("src").join("sub").join("file2")).unwrap(), "new");
+        assert!(dest.join("src").join("kept").exists());
+        let names: Vec<_> = fs::read_dir(&dest).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(names, ["src"]);
+
+        // A file that cannot be copied: nothing moves and no copy is left.
+        let moved = dest.join("src");
+        let _socket = std::os::unix::net::UnixListener::bind(moved.join("sub").join("socket")).unwrap();
+        let result = across().perform(&moved, &folder.join("again"), Collision::Free, tracker);
+        assert!(result.is_err());
+        assert!(moved.join("file1").exists());
+        let names: Vec<_> = fs::read_dir(&folder).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+        assert!(!names.iter().any(|name| name.contains("again")));
+
+        let step = Step::Move { src: moved.join("kept").to_str().unwrap().to_owned(), dest: folder.to_str().unwrap().to_owned(), name: None };
+        let report = perform(&[step], None, &CopyOptions::default(), &mut ()).unwrap();
+        assert_eq!(report.items[0].strategy, Some(MoveStrategy::Rename));
+
+        // A newer target is kept, and so is the older source with its folder.
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("file1"), "older").unwrap();
+        fs::write(src.join("sub").join("file3"), "new").unwrap();
+        let newer = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        fs::File::options().write(true).open(moved.join("file1")).unwrap().set_modified(newer).unwrap();
+        let flags = OperationFlags::FOF_NOCONFIRMATION | OperationFlags::FOFX_KEEPNEWERFILE;
+        Across { flags, ..across() }.perform(&src, &moved, Collision::Overwrite, tracker).unwrap();
+        assert_eq!(fs::read_to_string(moved.join("file1")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(moved.join("sub").join("file3")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(src.join("file1")).unwrap(), "older");
+        assert!(!src.join("sub").exists());
+
+        // The second copy cannot be put in place: the first one is taken
+        // back, and what it replaced restored.
+        let staging = moved.join(".file1.ifop-move");
+        fs::write(&staging, "copy").unwrap();
+        let staged = vec![
+            (src.join("file1"), staging.clone(), moved.join("file1")),
+            (src.join("sub"), moved.join(".sub.ifop-move"), moved.join("sub")),
+        ];
+        assert!(Across { staged, ..across() }.finish(&src).is_err());
+        assert_eq!(fs::read_to_string(moved.join("file1")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(src.join("file1")).unwrap(), "older");
+        assert!(moved.join("sub").join("file3").exists());
+        let names: Vec<_> = fs::read_dir(&moved).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+        assert!(!names.iter().any(|name| name.starts_with('.')));
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+        last: Progress,
+    }
+
+    impl ProgressSink for Recorder {
+        fn start(&mut self) {
+            self.events.push("start".to_owned());
+        }
+
+        fn finish(&mut self, report: &OperationReport) {
+            self.events.push(format!("finish {:?}", report.status()));
+        }
+
+        fn pre_item(&mut self, op: crate::OperationKind, _path: &Path) {
+            self.events.push(format!("pre {}", op));
+        }
+
+        fn post_item(&mut self, op: crate::OperationKind, _path: &Path, result_path: Option<&Path>, status: &crate::Result<()>) {
+            let name = result_path.and_then(Path::file_name).map(|name| name.to_string_lossy().into_owned());
+            self.events.push(format!("post {} {:?} {}", op, name, status.is_ok()));
+        }
+
+        fn progress(&mut self, progress: &Progress) {
+            self.last = *progress;
+        }
+    }
+
+    #[test]
+    fn test_progress_sink() {
+        let folder = folder("test_progress_sink");
+        let src = folder.join("src");
+        let dest = folder.join("dest");
+        let (src, dest) = (src.to_str().unwrap(), dest.to_str().unwrap());
+        let steps = [
+            Step::Copy { src: src.to_owned(), dest: dest.to_owned(), name: Some("copy".to_owned()) },
+            Step::Delete { target: folder.join("missing").to_str().unwrap().to_owned() },
+        ];
+
+        let mut recorder = Recorder::default();
+        perform(&steps, None, &CopyOptions::default(), &mut recorder).unwrap();
+        assert_eq!(recorder.events, [
+            "start",
+            "pre copy",
+            "post copy Some(\"copy\") true",
+            "pre delete",
+            "post delete None false",
+            "finish Partial",
+        ]);
+        assert_eq!(recorder.last, Progress { items_done: 2, items_total: 2, bytes_done: 6, bytes_total: 6 });
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_delete_to_trash() {
+        let folder = folder("test_delete_to_trash");
+        let data_home = folder.join("data");
+        let _env = crate::trash::ENV_LOCK.lock().unwrap();
+        std::env::set_var("XDG_DATA_HOME", &data_home);
+        let trash = data_home.join("Trash");
+
+        for _ in 0..2 {
+            fs::write(folder.join("src").join("file1"), "new").unwrap();
+            delete(folder.join("src").join("file1").to_str().unwrap(), OperationFlags::FOF_ALLOWUNDO).unwrap();
+        }
+        assert!(!folder.join("src").join("file1").exists());
+        assert!(trash.join("files").join("file1").exists());
+        assert!(trash.join("files").join("file1.2").exists());
+        let info = fs::read_to_string(trash.join("info").join("file1.trashinfo")).unwrap();
+        let path = format!("Path={}\n", folder.join("src").join("file1").display());
+        assert!(info.starts_with("[Trash Info]\n"));
+        assert!(info.contains(&path));
+        assert!(info.contains("DeletionDate="));
+
+        assert_eq!(delete(folder.join("src").join("sub").to_str().unwrap(), OperationFlags::empty()).unwrap(), None);
+        assert!(!folder.join("src").join("sub").exists());
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
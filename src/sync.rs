@@ -0,0 +1,696 @@
+//! Folder sync, queued on a [`FileOperation`] as the copies, deletes and
+//! renames it takes. One way, the destination is made to match the source;
+//! both ways, the changes since the last run, kept in a state file next to
+//! the journal, go to the other side.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::conflict::{different, keep_both_name};
+use crate::filter::entries;
+use crate::plan::View;
+use crate::{ConflictPolicy, Difference, Error, FileOperation, Filter, KeepBothNaming, OperationFlags, OperationKind, OperationReport, Result};
+
+/// ### How [`sync`] decides what to copy and delete
+/// ```rust
+/// let options = ifop::SyncOptions::new()
+///     .compare(ifop::Difference::Content)
+///     .delete_extraneous(true)
+///     .filter(ifop::Filter::new().exclude("*.tmp"));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SyncOptions {
+    compare: Difference,
+    delete_extraneous: bool,
+    filter: Option<Filter>,
+    on_conflict: Option<ConflictPolicy>,
+    state_file: Option<PathBuf>,
+}
+
+impl SyncOptions {
+    pub fn new() -> Self {
+        SyncOptions::default()
+    }
+
+    /// How a file present on both sides is found changed: size and
+    /// modification time (the default), or content.
+    pub fn compare(mut self, compare: Difference) -> Self {
+        self.compare = compare;
+        self
+    }
+
+    /// Delete what the destination has and the source does not. Deletes go
+    /// to the trash when the flags allow undo. A two-way sync always passes
+    /// deletions on.
+    pub fn delete_extraneous(mut self, delete: bool) -> Self {
+        self.delete_extraneous = delete;
+        self
+    }
+
+    /// Only sync what passes `filter`, on both sides: what it leaves out of
+    /// the destination is never deleted.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Two-way sync: what happens to an item changed on both sides since the
+    /// last run, `src` being the first folder. `Overwrite` and
+    /// `OverwriteIfDifferent` take the `src` side, `OverwriteIfNewer` the one
+    /// modified last, `KeepBoth` (the default) keeps both with the `dest`
+    /// version renamed on both sides. Except with `Overwrite`, a change wins
+    /// over a deletion. `Skip` leaves both sides as they are, `Fail` reports
+    /// the item as [`Error::Conflict`].
+    pub fn on_conflict(mut self, policy: ConflictPolicy) -> Self {
+        self.on_conflict = Some(policy);
+        self
+    }
+
+    /// Two-way sync: where the last synced tree is kept, instead of a file
+    /// named after both folders under the journal's folder.
+    pub fn state_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.state_file = Some(path.into());
+        self
+    }
+}
+
+fn text(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+fn parent_and_name(path: &Path) -> (String, String) {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    (path.parent().map(text).unwrap_or_default(), name)
+}
+
+/// ### The operation that syncs folder `dest` with folder `src`
+/// New and changed files are copied over what is there, missing folders
+/// created, and, with [`SyncOptions::delete_extraneous`], items only `dest`
+/// has deleted first. Nothing is queued for what is already in sync, so the
+/// operation can be [planned](FileOperation::plan) or performed as it is.
+/// `dest` is created when it does not exist.
+/// ```rust
+/// let operation = ifop::sync_operation("c:\\build", "\\\\server\\share\\build", &ifop::SyncOptions::new(), None).unwrap();
+/// println!("{}", operation.plan().unwrap());
+/// ```
+pub fn sync_operation(src: &str, dest: &str, options: &SyncOptions, flags: Option<OperationFlags>) -> Result<FileOperation> {
+    let (src_root, dest_root) = (Path::new(src), Path::new(dest));
+    if !src_root.is_dir() {
+        let path = src_root.to_owned();
+        return Err(match src_root.exists() {
+            true => Error::InvalidPath { path, op: OperationKind::Copy },
+            false => Error::NotFound { path, op: OperationKind::Copy },
+        });
+    }
+    let mut operation = FileOperation::new(flags);
+    operation.on_conflict(ConflictPolicy::Overwrite);
+    let filter = options.filter.clone().unwrap_or_default();
+    let sources: BTreeMap<PathBuf, bool> = entries(&filter, src_root).into_iter().collect();
+    let targets: BTreeMap<PathBuf, bool> = match dest_root.is_dir() {
+        true => entries(&filter, dest_root).into_iter().collect(),
+        false => {
+            let (parent, name) = parent_and_name(dest_root);
+            operation.new_folder(&parent, &name);
+            BTreeMap::new()
+        }
+    };
+
+    // Parents come before their content, so a deleted folder hides it.
+    let mut deleted: Vec<&Path> = Vec::new();
+    for (relative, &is_dir) in &targets {
+        if deleted.iter().any(|folder| relative.starts_with(folder)) {
+            continue;
+        }
+        let replaced = sources.get(relative).is_some_and(|&src_dir| src_dir != is_dir);
+        if replaced || (options.delete_extraneous && !sources.contains_key(relative)) {
+            operation.delete(&text(&dest_root.join(relative)));
+            deleted.push(relative);
+        }
+    }
+    for (relative, &is_dir) in &sources {
+        let (source, target) = (src_root.join(relative), dest_root.join(relative));
+        let present = targets.get(relative) == Some(&is_dir);
+        let (parent, name) = parent_and_name(&target);
+        if is_dir {
+            if !present {
+                operation.new_folder(&parent, &name);
+            }
+        } else if !present || different(&source, &target, options.compare) {
+            operation.copy(&text(&source), &parent);
+        }
+    }
+    Ok(operation)
+}
+
+/// ### Sync folder `dest` with folder `src`
+/// Performs [`sync_operation`]; the report lists what changed, one item per
+/// copy, new folder and delete.
+/// ```rust
+/// let options = ifop::SyncOptions::new().delete_extraneous(true);
+/// let report = ifop::sync("c:\\build", "\\\\server\\share\\build", &options, Some(ifop::OperationFlags::FOF_ALLOWUNDO)).unwrap();
+/// for item in &report.items {
+///     println!("{} {}", item.op, item.path.display());
+/// }
+/// ```
+pub fn sync(src: &str, dest: &str, options: &SyncOptions, flags: Option<OperationFlags>) -> Result<OperationReport> {
+    sync_operation(src, dest, options, flags)?.perform()
+}
+
+/// An item as both sides had it after the last two-way sync.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Synced {
+    dir: bool,
+    size: u64,
+    /// Modification times in nanoseconds since the epoch, one per side.
+    src_modified: u64,
+    dest_modified: u64,
+}
+
+/// The state file of a two-way sync.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    src: PathBuf,
+    dest: PathBuf,
+    /// By path relative to the synced folders, `/` separated.
+    items: BTreeMap<String, Synced>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Meta {
+    dir: bool,
+    size: u64,
+    modified: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Side {
+    Src,
+    Dest,
+}
+
+impl Side {
+    fn other(self) -> Side {
+        match self {
+            Side::Src => Side::Dest,
+            Side::Dest => Side::Src,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Change {
+    None,
+    Created,
+    Modified,
+    Deleted,
+}
+
+fn nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+}
+
+fn key(relative: &Path) -> String {
+    relative.iter().map(|part| part.to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+fn at(root: &Path, key: &str) -> PathBuf {
+    key.split('/').fold(root.to_owned(), |path, part| path.join(part))
+}
+
+fn under(key: &str, folder: &str) -> bool {
+    key.strip_prefix(folder).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// What passes `filter` below `root`, by key.
+fn scan(filter: &Filter, root: &Path) -> BTreeMap<String, Meta> {
+    if !root.is_dir() {
+        return BTreeMap::new();
+    }
+    entries(filter, root)
+        .into_iter()
+        .filter_map(|(relative, dir)| {
+            let meta = fs::symlink_metadata(root.join(&relative)).ok()?;
+            let modified = meta.modified().map_or(0, nanos);
+            Some((key(&relative), Meta { dir, size: if dir { 0 } else { meta.len() }, modified }))
+        })
+        .collect()
+}
+
+fn change(synced: Option<&Synced>, now: Option<&Meta>, side: Side) -> Change {
+    match (synced, now) {
+        (None, None) => Change::None,
+        (None, Some(_)) => Change::Created,
+        (Some(_), None) => Change::Deleted,
+        (Some(synced), Some(now)) => {
+            let modified = if side == Side::Src { synced.src_modified } else { synced.dest_modified };
+            if synced.dir != now.dir || (!now.dir && (synced.size != now.size || modified != now.modified)) {
+                Change::Modified
+            } else {
+                Change::None
+            }
+        }
+    }
+}
+
+/// `~/.local/state/ifop/sync/<hash of both folders>.json`.
+fn state_path(src: &Path, dest: &Path) -> PathBuf {
+    let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+    let pair = format!("{}\n{}", absolute(src).display(), absolute(dest).display());
+    // FNV-1a: the same name from one build to the next.
+    let hash = pair.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3));
+    crate::journal::state_dir().join("sync").join(format!("{:016x}.json", hash))
+}
+
+fn state_error(e: io::Error, path: &Path) -> Error {
+    Error::Platform { path: Some(path.to_owned()), op: None, code: e.raw_os_error().unwrap_or(0), message: e.to_string() }
+}
+
+fn read_state(path: &Path) -> Result<State> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| state_error(e.into(), path)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(State::default()),
+        Err(e) => Err(state_error(e, path)),
+    }
+}
+
+fn write_state(path: &Path, state: &State) -> Result<()> {
+    let write = || -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_vec_pretty(state)?)?;
+        fs::rename(&temp, path)
+    };
+    write().map_err(|e| state_error(e, path))
+}
+
+/// The decisions of a two-way sync.
+struct Reconciler<'a> {
+    roots: [&'a Path; 2],
+    scans: [BTreeMap<String, Meta>; 2],
+    state: &'a BTreeMap<String, Synced>,
+    compare: Difference,
+    policy: ConflictPolicy,
+    handled: HashSet<String>,
+    /// On one side, from one key to another.
+    renames: Vec<(Side, String, String)>,
+    deletes: Vec<(Side, String)>,
+    folders: BTreeSet<(String, Side)>,
+    /// From one side to the other.
+    copies: Vec<(Side, String)>,
+    /// The key and the name the `dest` version gets.
+    keep_both: Vec<(String, String)>,
+    skipped: Vec<String>,
+    failed: Vec<String>,
+}
+
+impl Reconciler<'_> {
+    fn root(&self, side: Side) -> &Path {
+        self.roots[side as usize]
+    }
+
+    fn scan(&self, side: Side) -> &BTreeMap<String, Meta> {
+        &self.scans[side as usize]
+    }
+
+    fn change(&self, key: &str, side: Side) -> Change {
+        change(self.state.get(key), self.scan(side).get(key), side)
+    }
+
+    /// A file gone from its synced place on one side and found, same size and
+    /// time, at a new one is renamed on the other side too.
+    fn renames(&mut self) {
+        for side in [Side::Src, Side::Dest] {
+            let other = side.other();
+            let created: Vec<(String, Meta)> = self.scan(side).iter()
+                .filter(|(key, meta)| !meta.dir && !self.state.contains_key(*key))
+                .map(|(key, meta)| (key.clone(), *meta))
+                .collect();
+            let deleted: Vec<(String, Synced)> = self.state.iter()
+                .filter(|(key, synced)| !synced.dir && !self.scan(side).contains_key(*key))
+                .map(|(key, synced)| (key.clone(), *synced))
+                .collect();
+            for (from, synced) in deleted {
+                let modified = if side == Side::Src { synced.src_modified } else { synced.dest_modified };
+                let found: Vec<&String> = created.iter()
+                    .filter(|(key, meta)| !self.handled.contains(key) && meta.size == synced.size && meta.modified == modified)
+                    .map(|(key, _)| key)
+                    .collect();
+                let [to] = found[..] else {
+                    continue;
+                };
+                let parent = to.rsplit_once('/').map(|(parent, _)| parent);
+                let parent_there = parent.is_none_or(|parent| self.scan(other).get(parent).is_some_and(|meta| meta.dir));
+                if self.change(&from, other) == Change::None && !self.scan(other).contains_key(to) && parent_there {
+                    let to = to.clone();
+                    self.handled.insert(from.clone());
+                    self.handled.insert(to.clone());
+                    self.renames.push((other, from, to));
+                }
+            }
+        }
+    }
+
+    /// The `from` side of `key` goes to the other one.
+    fn propagate(&mut self, from: Side, key: &str) {
+        let to = from.other();
+        let Some(meta) = self.scan(from).get(key).copied() else {
+            self.deletes.push((to, key.to_owned()));
+            return;
+        };
+        match self.scan(to).get(key) {
+            Some(there) if there.dir != meta.dir => self.deletes.push((to, key.to_owned())),
+            Some(_) if meta.dir => return,
+            _ => {}
+        }
+        if meta.dir {
+            self.folders.insert((key.to_owned(), to));
+        } else {
+            self.copies.push((from, key.to_owned()));
+        }
+    }
+
+    fn same(&self, key: &str) -> bool {
+        match (self.scan(Side::Src).get(key), self.scan(Side::Dest).get(key)) {
+            (None, None) => true,
+            (Some(src), Some(dest)) => src.dir == dest.dir && (src.dir || (src.size == dest.size
+                && !different(&at(self.root(Side::Src), key), &at(self.root(Side::Dest), key), self.compare))),
+            _ => false,
+        }
+    }
+
+    fn conflict(&mut self, key: &str) {
+        let (src, dest) = (self.scan(Side::Src).get(key).copied(), self.scan(Side::Dest).get(key).copied());
+        let winner = match (src, dest, self.policy) {
+            (_, _, ConflictPolicy::Skip) => return self.skipped.push(key.to_owned()),
+            (_, _, ConflictPolicy::Fail) => return self.failed.push(key.to_owned()),
+            (Some(_), None, _) => Side::Src,
+            (None, Some(_), ConflictPolicy::Overwrite | ConflictPolicy::OverwriteIfDifferent(_)) => Side::Src,
+            (None, _, _) => Side::Dest,
+            (Some(_), Some(_), ConflictPolicy::KeepBoth(naming)) => return self.keep_both(key, naming),
+            (Some(src), Some(dest), ConflictPolicy::OverwriteIfNewer) if dest.modified > src.modified => Side::Dest,
+            _ => Side::Src,
+        };
+        self.propagate(winner, key);
+    }
+
+    fn keep_both(&mut self, key: &str, naming: KeepBothNaming) {
+        let (src_root, dest_root) = (self.root(Side::Src).to_owned(), self.root(Side::Dest).to_owned());
+        let target = at(&dest_root, key);
+        let mut view = View::default();
+        let renamed = loop {
+            let candidate = keep_both_name(&view, &target, naming);
+            let candidate_key = key_of(&dest_root, &candidate);
+            if !at(&src_root, &candidate_key).exists() {
+                break candidate_key;
+            }
+            view.create(&candidate);
+        };
+        // What is inside goes with the folder.
+        let inside: Vec<String> = self.scans.iter().flat_map(|scan| scan.keys()).filter(|other| under(other, key)).cloned().collect();
+        self.handled.extend(inside);
+        self.keep_both.push((key.to_owned(), renamed));
+    }
+
+    fn reconcile(&mut self) {
+        self.renames();
+        let keys: BTreeSet<String> = self.scans.iter().flat_map(|scan| scan.keys()).chain(self.state.keys()).cloned().collect();
+        for key in &keys {
+            if self.handled.contains(key) {
+                continue;
+            }
+            match (self.change(key, Side::Src), self.change(key, Side::Dest)) {
+                (Change::None, Change::None) => {}
+                (_, Change::None) => self.propagate(Side::Src, key),
+                (Change::None, _) => self.propagate(Side::Dest, key),
+                _ if self.same(key) => {}
+                _ => self.conflict(key),
+            }
+        }
+        self.settle_folders();
+    }
+
+    /// A folder deleted on one side is kept on the other, and created again,
+    /// when something inside it changed there.
+    fn settle_folders(&mut self) {
+        let busy: Vec<&String> = self.copies.iter().map(|(_, key)| key)
+            .chain(self.folders.iter().map(|(key, _)| key))
+            .chain(self.keep_both.iter().map(|(key, _)| key))
+            .chain(self.renames.iter().flat_map(|(_, from, to)| [from, to]))
+            .chain(self.skipped.iter())
+            .chain(self.failed.iter())
+            .collect();
+        let mut kept = Vec::new();
+        let mut deletes: Vec<(Side, String)> = Vec::new();
+        for (side, key) in &self.deletes {
+            let is_dir = self.scan(*side).get(key).is_some_and(|meta| meta.dir);
+            if is_dir && busy.iter().any(|busy| under(busy, key)) {
+                kept.push((key.clone(), side.other()));
+            } else if !deletes.iter().any(|(deleted_side, deleted)| deleted_side == side && under(key, deleted)) {
+                deletes.push((*side, key.clone()));
+            }
+        }
+        self.deletes = deletes;
+        self.folders.extend(kept);
+    }
+
+    fn operation(&self, flags: Option<OperationFlags>) -> FileOperation {
+        let mut operation = FileOperation::new(flags);
+        operation.on_conflict(ConflictPolicy::Overwrite);
+        if !self.root(Side::Dest).exists() {
+            let (parent, name) = parent_and_name(self.root(Side::Dest));
+            operation.new_folder(&parent, &name);
+        }
+        for (side, from, to) in &self.renames {
+            let (parent, name) = parent_and_name(&at(self.root(*side), to));
+            operation.move_as(&text(&at(self.root(*side), from)), &parent, &name);
+        }
+        for (side, key) in &self.deletes {
+            operation.delete(&text(&at(self.root(*side), key)));
+        }
+        for (key, side) in &self.folders {
+            let (parent, name) = parent_and_name(&at(self.root(*side), key));
+            operation.new_folder(&parent, &name);
+        }
+        for (key, renamed) in &self.keep_both {
+            let (src, dest) = (at(self.root(Side::Src), key), at(self.root(Side::Dest), key));
+            let (dest_parent, new_name) = parent_and_name(&at(self.root(Side::Dest), renamed));
+            operation.rename(&text(&dest), &new_name);
+            operation.copy(&text(&at(self.root(Side::Dest), renamed)), &parent_and_name(&src).0);
+            operation.copy(&text(&src), &dest_parent);
+        }
+        for (from, key) in &self.copies {
+            operation.copy(&text(&at(self.root(*from), key)), &parent_and_name(&at(self.root(from.other()), key)).0);
+        }
+        operation
+    }
+}
+
+fn key_of(root: &Path, path: &Path) -> String {
+    key(path.strip_prefix(root).unwrap_or(path))
+}
+
+/// The state, its file, and the decisions for `src` and `dest`.
+fn reconcile<T>(src: &str, dest: &str, options: &SyncOptions, then: impl FnOnce(&Reconciler, PathBuf, State) -> Result<T>) -> Result<T> {
+    let (src_root, dest_root) = (Path::new(src), Path::new(dest));
+    for root in [src_root, dest_root] {
+        if root.exists() && !root.is_dir() {
+            return Err(Error::InvalidPath { path: root.to_owned(), op: OperationKind::Copy });
+        }
+    }
+    if !src_root.exists() {
+        return Err(Error::NotFound { path: src_root.to_owned(), op: OperationKind::Copy });
+    }
+    let state_file = options.state_file.clone().unwrap_or_else(|| state_path(src_root, dest_root));
+    let state = read_state(&state_file)?;
+    let filter = options.filter.clone().unwrap_or_default();
+    let mut reconciler = Reconciler {
+        roots: [src_root, dest_root],
+        scans: [scan(&filter, src_root), scan(&filter, dest_root)],
+        state: &state.items,
+        compare: options.compare,
+        policy: options.on_conflict.unwrap_or(ConflictPolicy::KeepBoth(KeepBothNaming::Numbered)),
+        handled: HashSet::new(),
+        renames: Vec::new(),
+        deletes: Vec::new(),
+        folders: BTreeSet::new(),
+        copies: Vec::new(),
+        keep_both: Vec::new(),
+        skipped: Vec::new(),
+        failed: Vec::new(),
+    };
+    reconciler.reconcile();
+    then(&reconciler, state_file, State { src: src_root.to_owned(), dest: dest_root.to_owned(), items: BTreeMap::new() })
+}
+
+/// ### The operation that syncs folders `src` and `dest` both ways
+/// What was created, modified, renamed or deleted on one side since the last
+/// [`sync_bidirectional`] is done on the other; both sides changed is a
+/// conflict for [`SyncOptions::on_conflict`]. Without a state from an earlier
+/// run, items on both sides are conflicts unless they are the same. Skipped
+/// and failing conflicts are not in the operation. `dest` is created when it
+/// does not exist.
+/// ```rust
+/// let operation = ifop::sync_bidirectional_operation("c:\\work", "\\\\server\\share\\work", &ifop::SyncOptions::new(), None).unwrap();
+/// println!("{}", operation.plan().unwrap());
+/// ```
+pub fn sync_bidirectional_operation(src: &str, dest: &str, options: &SyncOptions, flags: Option<OperationFlags>) -> Result<FileOperation> {
+    reconcile(src, dest, options, |reconciler, _, _| Ok(reconciler.operation(flags)))
+}
+
+/// ### Sync folders `src` and `dest` both ways
+/// Performs [`sync_bidirectional_operation`] and keeps the synced tree for the
+/// next run. The report lists what changed, then the conflicts left as they
+/// are: `Ok` when skipped, [`Error::Conflict`] with [`ConflictPolicy::Fail`].
+/// Their items, and the items that failed, are synced again the next time.
+/// ```rust
+/// let options = ifop::SyncOptions::new().on_conflict(ifop::ConflictPolicy::OverwriteIfNewer);
+/// let report = ifop::sync_bidirectional("c:\\work", "\\\\server\\share\\work", &options, None).unwrap();
+/// for item in report.failures() {
+///     println!("{} {:?}", item.path.display(), item.result);
+/// }
+/// ```
+pub fn sync_bidirectional(src: &str, dest: &str, options: &SyncOptions, flags: Option<OperationFlags>) -> Result<OperationReport> {
+    let (src_root, dest_root) = (Path::new(src), Path::new(dest));
+    reconcile(src, dest, options, |reconciler, state_file, mut state| {
+        let mut report = reconciler.operation(flags).perform()?;
+        for key in &reconciler.skipped {
+            report.push(OperationKind::Copy, at(src_root, key), None, Ok(()));
+        }
+        for key in &reconciler.failed {
+            let path = at(dest_root, key);
+            report.push(OperationKind::Copy, at(src_root, key), None, Err(Error::Conflict { path, op: OperationKind::Copy }));
+        }
+
+        // Left out of the new state as synced: they keep the old one.
+        let mut unsettled: HashSet<String> = reconciler.skipped.iter().chain(&reconciler.failed).cloned().collect();
+        for item in report.failures() {
+            let path = &item.path;
+            let root = if path.starts_with(src_root) { src_root } else { dest_root };
+            unsettled.insert(key_of(root, path));
+        }
+        let filter = options.filter.clone().unwrap_or_default();
+        let (src_now, dest_now) = (scan(&filter, src_root), scan(&filter, dest_root));
+        for (key, src) in &src_now {
+            if unsettled.contains(key) {
+                if let Some(synced) = reconciler.state.get(key) {
+                    state.items.insert(key.clone(), *synced);
+                }
+                continue;
+            }
+            match dest_now.get(key) {
+                Some(dest) if dest.dir == src.dir && dest.size == src.size => {
+                    let synced = Synced { dir: src.dir, size: src.size, src_modified: src.modified, dest_modified: dest.modified };
+                    state.items.insert(key.clone(), synced);
+                }
+                _ => {}
+            }
+        }
+        for key in unsettled.iter().filter(|key| !src_now.contains_key(*key)) {
+            if let Some(synced) = reconciler.state.get(key) {
+                state.items.insert(key.clone(), *synced);
+            }
+        }
+        write_state(&state_file, &state)?;
+        Ok(report)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_sync() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_sync");
+        let _ = fs::remove_dir_all(&folder);
+        let (src, dest) = (folder.join("src"), folder.join("dest"));
+        fs::create_dir_all(src.join("sub")).unwrap();
+        for (file, content) in [("same", "1"), ("changed", "22"), ("sub/new", "3"), ("kind", "4"), ("skip.tmp", "5")] {
+            fs::write(src.join(file), content).unwrap();
+        }
+        let options = SyncOptions::new().filter(Filter::new().exclude("*.tmp"));
+        let sync = |options: &SyncOptions| sync(src.to_str().unwrap(), dest.to_str().unwrap(), options, None).unwrap();
+
+        let report = sync(&options);
+        assert!(report.is_ok());
+        assert_eq!(fs::read_to_string(dest.join("sub").join("new")).unwrap(), "3");
+        assert!(!dest.join("skip.tmp").exists());
+        // In sync: nothing to do.
+        assert!(sync(&options).items.is_empty());
+
+        fs::write(src.join("changed"), "changed").unwrap();
+        fs::remove_file(dest.join("kind")).unwrap();
+        fs::create_dir(dest.join("kind")).unwrap();
+        fs::write(dest.join("extra"), "").unwrap();
+        fs::write(dest.join("kept.tmp"), "").unwrap();
+        let report = sync(&options);
+        assert_eq!(report.items.len(), 3);
+        assert_eq!(fs::read_to_string(dest.join("changed")).unwrap(), "changed");
+        assert!(dest.join("kind").is_file());
+        assert!(dest.join("extra").exists());
+
+        let report = sync(&options.delete_extraneous(true));
+        assert_eq!(report.items.iter().map(|item| item.op).collect::<Vec<_>>(), vec![OperationKind::Delete]);
+        assert!(!dest.join("extra").exists());
+        // Left out by the filter, so not extraneous.
+        assert!(dest.join("kept.tmp").exists());
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn test_sync_bidirectional() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_sync_bidirectional");
+        let _ = fs::remove_dir_all(&folder);
+        let (src, dest) = (folder.join("src"), folder.join("dest"));
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("file"), "1").unwrap();
+        fs::write(src.join("sub").join("inner"), "2").unwrap();
+        let options = SyncOptions::new().state_file(folder.join("state.json"));
+        let sync = || sync_bidirectional(src.to_str().unwrap(), dest.to_str().unwrap(), &options, None).unwrap();
+
+        assert!(sync().is_ok());
+        assert_eq!(fs::read_to_string(dest.join("sub").join("inner")).unwrap(), "2");
+        assert!(sync().items.is_empty());
+
+        // Each side's changes go to the other one.
+        fs::write(src.join("file"), "changed").unwrap();
+        fs::remove_file(dest.join("sub").join("inner")).unwrap();
+        fs::write(dest.join("new"), "3").unwrap();
+        assert_eq!(sync().items.len(), 3);
+        assert_eq!(fs::read_to_string(dest.join("file")).unwrap(), "changed");
+        assert!(!src.join("sub").join("inner").exists());
+        assert_eq!(fs::read_to_string(src.join("new")).unwrap(), "3");
+
+        // A rename stays a rename.
+        fs::rename(src.join("new"), src.join("sub").join("renamed")).unwrap();
+        let report = sync();
+        assert_eq!(report.items.iter().map(|item| item.op).collect::<Vec<_>>(), vec![OperationKind::Move]);
+        assert!(dest.join("sub").join("renamed").exists() && !dest.join("new").exists());
+
+        // Both sides changed: both kept, the dest version renamed.
+        fs::write(src.join("file"), "src").unwrap();
+        fs::write(dest.join("file"), "dest!").unwrap();
+        assert!(sync().is_ok());
+        for side in [&src, &dest] {
+            assert_eq!(fs::read_to_string(side.join("file")).unwrap(), "src");
+            assert_eq!(fs::read_to_string(side.join("file (2)")).unwrap(), "dest!");
+        }
+        assert!(sync().items.is_empty());
+
+        fs::write(src.join("file"), "src again").unwrap();
+        fs::write(dest.join("file"), "dest").unwrap();
+        let failing = options.clone().on_conflict(ConflictPolicy::Fail);
+        let report = sync_bidirectional(src.to_str().unwrap(), dest.to_str().unwrap(), &failing, None).unwrap();
+        assert!(matches!(report.into_result(), Err(Error::Conflict { .. })));
+        assert_eq!(fs::read_to_string(dest.join("file")).unwrap(), "dest");
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,376 @@
+//! FreeDesktop.org Trash, used by the `std::fs` backend when deleting with
+//! `FOF_ALLOWUNDO` or `FOFX_RECYCLEONDELETE`.
+//!
+//! Items on the home file system go to `$XDG_DATA_HOME/Trash`, items on
+//! other mounts to `$topdir/.Trash/$uid` when the administrator prepared it,
+//! `$topdir/.Trash-$uid` otherwise. Every trashed item gets a `.trashinfo`
+//! file with its original path and deletion date.
+
+use std::fs;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use crate::plan::size_of;
+use crate::stdfs::{place, remove_item, resolve_target, Collision};
+use crate::{OperationFlags, OperationKind, TrashItem};
+
+/// `$XDG_DATA_HOME/Trash`, falling back to `~/.local/share/Trash`.
+fn home_trash() -> Option<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| Path::new(dir).is_absolute())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share")))?;
+    Some(data_home.join("Trash"))
+}
+
+/// Device of `path`, or of its nearest existing ancestor.
+fn device(path: &Path) -> Option<u64> {
+    path.ancestors().find_map(|dir| fs::symlink_metadata(dir).ok()).map(|meta| meta.dev())
+}
+
+/// The mount point holding `path`: the last ancestor on the same device.
+fn top_dir(path: &Path) -> PathBuf {
+    let dev = device(path);
+    let mut top = path;
+    for dir in path.ancestors().skip(1) {
+        if fs::metadata(dir).ok().map(|meta| meta.dev()) != dev {
+            break;
+        }
+        top = dir;
+    }
+    top.to_owned()
+}
+
+fn create_private_dir(dir: &Path) -> Result<()> {
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)
+}
+
+/// Trash folder for `path` and the base its `Path=` entries are relative to,
+/// `None` for the home trash that stores absolute paths.
+fn trash_for(path: &Path) -> Result<(PathBuf, Option<PathBuf>)> {
+    if let Some(home) = home_trash() {
+        if device(&home) == device(path) {
+            return Ok((home, None));
+        }
+    }
+
+    let top = top_dir(path);
+    let uid = unsafe { libc::getuid() };
+    // `$topdir/.Trash` only counts when it is a real sticky folder.
+    let shared = top.join(".Trash");
+    if let Ok(meta) = fs::symlink_metadata(&shared) {
+        if meta.is_dir() && meta.permissions().mode() & 0o1000 != 0 {
+            let trash = shared.join(uid.to_string());
+            if create_private_dir(&trash).is_ok() {
+                return Ok((trash, Some(top)));
+            }
+        }
+    }
+    Ok((top.join(format!(".Trash-{}", uid)), Some(top)))
+}
+
+/// Percent-encode everything but unreserved characters and `/`, as the
+/// `Path=` key requires.
+fn encode(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Local time as `YYYY-MM-DDThh:mm:ss`.
+fn deletion_date() -> String {
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec
+        )
+    }
+}
+
+/// Reserve a name in `info`: `name`, `name.2`, `name.3`... The `.trashinfo`
+/// file is created exclusively, so two processes never pick the same one.
+fn reserve(info: &Path, name: &str, content: &str) -> Result<(String, PathBuf)> {
+    let mut n = 1;
+    loop {
+        let candidate = if n == 1 { name.to_owned() } else { format!("{}.{}", name, n) };
+        let info_file = info.join(format!("{}.trashinfo", candidate));
+        match fs::OpenOptions::new().write(true).create_new(true).open(&info_file) {
+            Ok(mut file) => {
+                file.write_all(content.as_bytes())?;
+                return Ok((candidate, info_file));
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Move `path` to the trash, returning where it ended up.
+pub(crate) fn trash(path: &Path) -> Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    fs::symlink_metadata(&path)?;
+    let name = path.file_name().ok_or_else(|| Error::new(
+        ErrorKind::InvalidInput,
+        format!("{}: path has no file name", path.display())
+    ))?;
+
+    let (trash, base) = trash_for(&path)?;
+    let (files, info) = (trash.join("files"), trash.join("info"));
+    create_private_dir(&files)?;
+    create_private_dir(&info)?;
+
+    let original = match &base {
+        Some(base) => path.strip_prefix(base).unwrap_or(&path),
+        None => &path,
+    };
+    let content = format!("[Trash Info]\nPath={}\nDeletionDate={}\n", encode(original), deletion_date());
+    let (name, info_file) = reserve(&info, &name.to_string_lossy(), &content)?;
+
+    let target = files.join(name);
+    if let Err(e) = fs::rename(&path, &target) {
+        let _ = fs::remove_file(info_file);
+        return Err(e);
+    }
+    Ok(target)
+}
+
+/// Undo [`encode`]; malformed escapes are kept as they are.
+fn decode(value: &str) -> PathBuf {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match (bytes[i], hex.and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(std::ffi::OsString::from_vec(decoded))
+}
+
+/// `YYYY-MM-DDThh:mm:ss` in local time, as written by [`deletion_date`].
+fn parse_date(value: &str) -> Option<SystemTime> {
+    let number = |range: std::ops::Range<usize>| value.get(range).and_then(|part| part.parse::<i32>().ok());
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        tm.tm_year = number(0..4)? - 1900;
+        tm.tm_mon = number(5..7)? - 1;
+        tm.tm_mday = number(8..10)?;
+        tm.tm_hour = number(11..13)?;
+        tm.tm_min = number(14..16)?;
+        tm.tm_sec = number(17..19)?;
+        tm.tm_isdst = -1;
+        let time = libc::mktime(&mut tm);
+        u64::try_from(time).ok().map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+    }
+}
+
+/// Mount points from `/proc/self/mounts`, empty where there is none.
+fn mount_points() -> Vec<PathBuf> {
+    let mounts = fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+    mounts.lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        // Spaces and other separators are written as `\040` octal escapes.
+        .map(|dir| {
+            let mut bytes = Vec::new();
+            let mut rest = dir.as_bytes();
+            while let Some((&byte, tail)) = rest.split_first() {
+                let octal = tail.get(..3).and_then(|digits| std::str::from_utf8(digits).ok());
+                match octal.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+                    Some(value) if byte == b'\\' => {
+                        bytes.push(value);
+                        rest = &tail[3..];
+                    }
+                    _ => {
+                        bytes.push(byte);
+                        rest = tail;
+                    }
+                }
+            }
+            PathBuf::from(std::ffi::OsString::from_vec(bytes))
+        })
+        .collect()
+}
+
+/// Every trash folder of the user that exists, with its `Path=` base.
+fn trash_dirs() -> Vec<(PathBuf, Option<PathBuf>)> {
+    let uid = unsafe { libc::getuid() };
+    let home = home_trash();
+    let mut dirs: Vec<(PathBuf, Option<PathBuf>)> = home.iter().map(|home| (home.clone(), None)).collect();
+    for top in mount_points() {
+        for trash in [top.join(".Trash").join(uid.to_string()), top.join(format!(".Trash-{}", uid))] {
+            if trash.is_dir() && home.as_ref() != Some(&trash) && !dirs.iter().any(|(dir, _)| *dir == trash) {
+                dirs.push((trash, Some(top.clone())));
+            }
+        }
+    }
+    dirs
+}
+
+/// Read one `.trashinfo` file into an item, `None` if it is broken or its
+/// file is gone.
+fn read_info(info_file: &Path, files: &Path, base: Option<&Path>) -> Option<TrashItem> {
+    let name = info_file.file_name()?.as_bytes().strip_suffix(b".trashinfo")?;
+    let file = files.join(std::ffi::OsStr::from_bytes(name));
+    fs::symlink_metadata(&file).ok()?;
+
+    let content = fs::read_to_string(info_file).ok()?;
+    let mut lines = content.lines().map(str::trim);
+    lines.by_ref().find(|line| *line == "[Trash Info]")?;
+    let (mut path, mut deleted) = (None, None);
+    for line in lines.take_while(|line| !line.starts_with('[')) {
+        if let Some(value) = line.strip_prefix("Path=") {
+            path = Some(decode(value));
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            deleted = parse_date(value);
+        }
+    }
+    let path = path?;
+    let original_path = match base {
+        Some(base) if path.is_relative() => base.join(path),
+        _ => path,
+    };
+
+    Some(TrashItem { id: file.to_string_lossy().into_owned(), original_path, deleted, size: size_of(&file) })
+}
+
+pub(crate) fn list() -> crate::Result<Vec<TrashItem>> {
+    let mut items = Vec::new();
+    for (trash, base) in trash_dirs() {
+        let Ok(entries) = fs::read_dir(trash.join("info")) else {
+            continue;
+        };
+        let files = trash.join("files");
+        items.extend(entries.flatten().filter_map(|entry| read_info(&entry.path(), &files, base.as_deref())));
+    }
+    Ok(items)
+}
+
+/// `<trash>/info/<name>.trashinfo` for `<trash>/files/<name>`.
+fn info_file(file: &Path) -> PathBuf {
+    let mut name = file.file_name().unwrap_or_default().to_owned();
+    name.push(".trashinfo");
+    file.parent().and_then(Path::parent).unwrap_or(Path::new("/")).join("info").join(name)
+}
+
+fn error(path: &Path) -> impl FnOnce(Error) -> crate::Error + '_ {
+    move |e| crate::Error::from_io(e, path, OperationKind::Move)
+}
+
+pub(crate) fn restore(item: &TrashItem, flags: OperationFlags) -> crate::Result<PathBuf> {
+    let file = Path::new(&item.id);
+
+    let (target, collision) = resolve_target(item.original_path.clone(), flags).map_err(error(&item.original_path))?;
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(error(parent))?;
+    }
+    // What is there stays until the item took its place.
+    let replaced = match collision {
+        Collision::Overwrite => place(file, &target).map_err(error(file))?,
+        _ => fs::rename(file, &target).map(|_| None).map_err(error(file))?,
+    };
+    fs::remove_file(info_file(file)).map_err(error(file))?;
+    if let Some(replaced) = replaced {
+        remove_item(&replaced).map_err(error(&target))?;
+    }
+    Ok(target)
+}
+
+pub(crate) fn remove(items: &[TrashItem]) -> crate::Result<()> {
+    for item in items {
+        let file = Path::new(&item.id);
+        let error = |e| crate::Error::from_io(e, file, OperationKind::Delete);
+        remove_item(file).map_err(error)?;
+        fs::remove_file(info_file(file)).map_err(error)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(Path::new("/home/me/a file+1.txt")), "/home/me/a%20file%2B1.txt");
+        assert_eq!(encode(Path::new("docs/é")), "docs/%C3%A9");
+        assert_eq!(decode("/home/me/a%20file%2B1.txt"), Path::new("/home/me/a file+1.txt"));
+        assert_eq!(decode("docs/%C3%A9%zz"), Path::new("docs/é%zz"));
+    }
+
+    #[test]
+    fn test_trash_items() {
+        let folder = std::env::current_dir().unwrap().join("test").join("test_trash_items");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("src")).unwrap();
+        let _env = super::super::ENV_LOCK.lock().unwrap();
+        std::env::set_var("XDG_DATA_HOME", folder.join("data"));
+
+        let (file1, file2) = (folder.join("src").join("file 1"), folder.join("src").join("file2"));
+        fs::write(&file1, "one").unwrap();
+        fs::write(&file2, "two!").unwrap();
+        trash(&file1).unwrap();
+        trash(&file2).unwrap();
+        fs::remove_dir(folder.join("src")).unwrap();
+
+        let mine = || -> Vec<TrashItem> {
+            let mut items: Vec<TrashItem> = list().unwrap().into_iter().filter(|item| item.original_path.starts_with(&folder)).collect();
+            items.sort_by(|a, b| a.original_path.cmp(&b.original_path));
+            items
+        };
+        let items = mine();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].original_path, file1);
+        assert_eq!(items[0].size, 3);
+        let deleted = items[0].deleted.unwrap();
+        assert!(SystemTime::now().duration_since(deleted).unwrap() < Duration::from_secs(60));
+        assert_eq!(super::super::expired(items.clone(), Some(Duration::from_secs(3600))), []);
+        assert_eq!(super::super::expired(items.clone(), None).len(), 2);
+
+        // The folder it came from is created again.
+        assert_eq!(restore(&items[0], OperationFlags::empty()).unwrap(), file1);
+        assert_eq!(fs::read_to_string(&file1).unwrap(), "one");
+        fs::write(&file2, "taken").unwrap();
+        assert_eq!(
+            restore(&items[1], OperationFlags::empty()),
+            Err(crate::Error::Conflict { path: file2.clone(), op: OperationKind::Move })
+        );
+        // The item that is there survives a restore over it that fails.
+        let gone = TrashItem { id: format!("{}-gone", items[1].id), ..items[1].clone() };
+        assert!(restore(&gone, OperationFlags::FOF_NOCONFIRMATION).is_err());
+        assert_eq!(fs::read_to_string(&file2).unwrap(), "taken");
+        assert_eq!(fs::read_dir(folder.join("src")).unwrap().count(), 2);
+        assert_eq!(restore(&items[1], OperationFlags::FOF_RENAMEONCOLLISION).unwrap(), folder.join("src").join("file2 (2)"));
+        assert_eq!(mine(), []);
+        trash(&folder.join("src").join("file2 (2)")).unwrap();
+        let over = TrashItem { original_path: file2.clone(), ..mine()[0].clone() };
+        assert_eq!(restore(&over, OperationFlags::FOF_NOCONFIRMATION).unwrap(), file2);
+        assert_eq!(fs::read_to_string(&file2).unwrap(), "two!");
+        assert_eq!(fs::read_dir(folder.join("src")).unwrap().count(), 2);
+
+        fs::write(&file2, "two!").unwrap();
+        trash(&file2).unwrap();
+        remove(&mine()).unwrap();
+        assert_eq!(mine(), []);
+        assert_eq!(fs::read_dir(folder.join("data").join("Trash").join("files")).unwrap().count(), 0);
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+//! Items deleted with `FOF_ALLOWUNDO`: the Recycle Bin on Windows, the
+//! FreeDesktop.org Trash elsewhere.
+
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use crate::{Error, OperationFlags, OperationKind, Result};
+
+#[cfg(unix)]
+mod freedesktop;
+#[cfg(unix)]
+use freedesktop as imp;
+#[cfg(unix)]
+pub(crate) use freedesktop::trash;
+
+#[cfg(windows)]
+mod recycle_bin;
+#[cfg(windows)]
+use recycle_bin as imp;
+
+/// One item in the trash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrashItem {
+    /// Identifies the item for [`restore_trash_item`]: its path inside the
+    /// trash folder, or its parsing name in the Recycle Bin.
+    pub id: String,
+    /// Where the item was deleted from.
+    pub original_path: PathBuf,
+    /// `None` when the trash does not record it.
+    pub deleted: Option<SystemTime>,
+    /// Bytes of file content, folders included.
+    pub size: u64,
+}
+
+/// ### List the items in the trash
+/// ```rust
+/// for item in ifop::list_trash().unwrap() {
+///     println!("{} ({} bytes)", item.original_path.display(), item.size);
+/// }
+/// ```
+pub fn list_trash() -> Result<Vec<TrashItem>> {
+    imp::list()
+}
+
+/// Find `item` by id, or by original path; the most recently deleted wins.
+fn find(items: Vec<TrashItem>, item: &str) -> Result<TrashItem> {
+    let mut matches: Vec<TrashItem> = items.into_iter()
+        .filter(|entry| entry.id == item || entry.original_path.as_os_str() == item)
+        .collect();
+    matches.sort_by_key(|entry| entry.deleted);
+    matches.pop().ok_or_else(|| Error::NotFound { path: PathBuf::from(item), op: OperationKind::Move })
+}
+
+/// ### Restore an item from the trash to where it was deleted from
+/// `item` is a [`TrashItem::id`] or an original path. When the original path
+/// is taken, `FOF_RENAMEONCOLLISION` restores under a free name and
+/// `FOF_NOCONFIRMATION` replaces what is there; otherwise the restore fails
+/// with [`Error::Conflict`]. Returns the restored path.
+/// ```rust
+/// match ifop::restore_trash_item("c:\\src\\file.txt", None) {
+///     Ok(path) => {
+///         println!("restored to {}", path.display());
+///     }
+///     Err(e) => {
+///         println!("{}", e);
+///     }
+/// }
+/// ```
+pub fn restore_trash_item(item: &str, flags: Option<OperationFlags>) -> Result<PathBuf> {
+    let item = find(imp::list()?, item)?;
+    imp::restore(&item, flags.unwrap_or_default())
+}
+
+/// ### Permanently delete items from the trash
+/// With `older_than`, only items deleted longer ago than that. Returns the
+/// number of items removed.
+/// ```rust
+/// // Everything
+/// ifop::empty_trash(None).unwrap();
+///
+/// // Deleted more than 30 days ago
+/// ifop::empty_trash(Some(std::time::Duration::from_secs(30 * 24 * 60 * 60))).unwrap();
+/// ```
+pub fn empty_trash(older_than: Option<Duration>) -> Result<usize> {
+    let items = expired(imp::list()?, older_than);
+    imp::remove(&items)?;
+    Ok(items.len())
+}
+
+/// Items deleted more than `older_than` ago, all of them without a limit.
+/// Items without a deletion date are only taken without a limit.
+fn expired(items: Vec<TrashItem>, older_than: Option<Duration>) -> Vec<TrashItem> {
+    let Some(age) = older_than else {
+        return items;
+    };
+    let Some(cutoff) = SystemTime::now().checked_sub(age) else {
+        return Vec::new();
+    };
+    items.into_iter().filter(|item| item.deleted.is_some_and(|deleted| deleted <= cutoff)).collect()
+}
+
+/// Tests that point `XDG_DATA_HOME` somewhere else hold this.
+#[cfg(all(test, unix))]
+pub(crate) static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
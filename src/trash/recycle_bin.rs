@@ -0,0 +1,136 @@
+//! Recycle Bin through its shell folder: the items are enumerated as
+//! `IShellItem`s and restored or deleted with `IFileOperation`.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use windows::core::*;
+use windows::Win32::Foundation::FILETIME;
+use windows::Win32::Storage::EnhancedStorage::{PKEY_ItemNameDisplay, PKEY_Size};
+use windows::Win32::System::Com::CoTaskMemFree;
+use windows::Win32::UI::Shell::PropertiesSystem::PROPERTYKEY;
+use windows::Win32::UI::Shell::*;
+use crate::shell::{get_operation, normalize, perform_advised};
+use crate::{OperationFlags, OperationKind, OperationReport, ProgressSink, TrashItem};
+
+/// `PSGUID_DISPLACED`: folder the item was deleted from, and when.
+const DISPLACED_FROM: PROPERTYKEY = PROPERTYKEY { fmtid: GUID::from_u128(0x9b174b33_40ff_11d2_a27e_00c04fc30871), pid: 2 };
+const DISPLACED_DATE: PROPERTYKEY = PROPERTYKEY { fmtid: GUID::from_u128(0x9b174b33_40ff_11d2_a27e_00c04fc30871), pid: 3 };
+
+fn error(e: Error) -> crate::Error {
+    crate::Error::from_windows(&e, None, None)
+}
+
+unsafe fn take_string(value: PWSTR) -> Option<String> {
+    let string = value.to_string().ok();
+    CoTaskMemFree(Some(value.0 as *const _));
+    string
+}
+
+/// 100 ns ticks since 1601 to `SystemTime`.
+fn system_time(time: FILETIME) -> Option<SystemTime> {
+    let ticks = (u64::from(time.dwHighDateTime) << 32) | u64::from(time.dwLowDateTime);
+    let since_epoch = ticks.checked_sub(116_444_736_000_000_000)?;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_nanos(since_epoch.saturating_mul(100)))
+}
+
+unsafe fn read_item(item: &IShellItem) -> Option<TrashItem> {
+    let item2: IShellItem2 = item.cast().ok()?;
+    let id = take_string(item.GetDisplayName(SIGDN_FILESYSPATH).ok()?)?;
+    let folder = take_string(item2.GetString(&DISPLACED_FROM).ok()?)?;
+    let name = take_string(item2.GetString(&PKEY_ItemNameDisplay).ok()?)?;
+    Some(TrashItem {
+        id,
+        original_path: Path::new(&folder).join(name),
+        deleted: item2.GetFileTime(&DISPLACED_DATE).ok().and_then(system_time),
+        size: item2.GetUInt64(&PKEY_Size).unwrap_or(0),
+    })
+}
+
+unsafe fn enumerate() -> Result<Vec<(TrashItem, IShellItem)>> {
+    let bin: IShellItem = SHGetKnownFolderItem(&FOLDERID_RecycleBinFolder, KF_FLAG_DEFAULT, None)?;
+    let items: IEnumShellItems = bin.BindToHandler(None, &BHID_EnumItems)?;
+    let mut result = Vec::new();
+    loop {
+        let mut slot = [None];
+        let mut fetched = 0;
+        items.Next(&mut slot, Some(&mut fetched))?;
+        let Some(item) = slot[0].take().filter(|_| fetched == 1) else {
+            break;
+        };
+        if let Some(entry) = read_item(&item) {
+            result.push((entry, item));
+        }
+    }
+    Ok(result)
+}
+
+pub(crate) fn list() -> crate::Result<Vec<TrashItem>> {
+    unsafe { enumerate() }.map(|items| items.into_iter().map(|(item, _)| item).collect()).map_err(error)
+}
+
+unsafe fn shell_item(item: &TrashItem) -> crate::Result<IShellItem> {
+    let items = enumerate().map_err(error)?;
+    items.into_iter()
+        .find(|(entry, _)| entry.id == item.id)
+        .map(|(_, shell_item)| shell_item)
+        .ok_or_else(|| crate::Error::NotFound { path: PathBuf::from(&item.id), op: OperationKind::Move })
+}
+
+/// Remembers where the restored item ended up.
+#[derive(Default)]
+struct Restored(Option<PathBuf>);
+
+impl ProgressSink for Restored {
+    fn post_item(&mut self, _op: OperationKind, _path: &Path, result_path: Option<&Path>, _status: &crate::Result<()>) {
+        self.0 = result_path.map(Path::to_owned);
+    }
+}
+
+pub(crate) fn restore(item: &TrashItem, flags: OperationFlags) -> crate::Result<PathBuf> {
+    let target = &item.original_path;
+    let replace = OperationFlags::FOF_RENAMEONCOLLISION | OperationFlags::FOF_NOCONFIRMATION;
+    // Same rule as the FreeDesktop trash instead of a shell prompt.
+    if std::fs::symlink_metadata(target).is_ok() && !flags.intersects(replace) {
+        return Err(crate::Error::Conflict { path: target.clone(), op: OperationKind::Move });
+    }
+    let folder = target.parent().unwrap_or(Path::new(""));
+    let name = target.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    std::fs::create_dir_all(folder).map_err(|e| crate::Error::from_io(e, folder, OperationKind::Move))?;
+
+    unsafe {
+        let shell_item = shell_item(item)?;
+        let operation = get_operation(Some(flags)).map_err(error)?;
+        let dest: IShellItem = SHCreateItemFromParsingName(&HSTRING::from(folder.to_string_lossy().as_ref()), None)
+            .map_err(|e| crate::Error::from_windows(&e, Some(folder), Some(OperationKind::Move)))?;
+        operation.MoveItem(&shell_item, &dest, &HSTRING::from(name), None)
+            .map_err(|e| crate::Error::from_windows(&e, Some(Path::new(&item.id)), Some(OperationKind::Move)))?;
+
+        let mut report = OperationReport::default();
+        report.push(OperationKind::Move, PathBuf::from(&item.id), None, Ok(()));
+        let mut restored = Restored::default();
+        let mut outcomes = perform_advised(&operation, &report, &mut restored).map_err(error)?;
+        if let Some((Err(e), _)) = outcomes.get_mut(&(OperationKind::Move, normalize(Path::new(&item.id)))).and_then(|results| results.pop_front()) {
+            return Err(e);
+        }
+        if operation.GetAnyOperationsAborted().is_ok_and(|aborted| aborted.as_bool()) {
+            return Err(crate::Error::Aborted { path: Some(target.clone()), op: Some(OperationKind::Move) });
+        }
+        Ok(restored.0.unwrap_or_else(|| target.clone()))
+    }
+}
+
+pub(crate) fn remove(items: &[TrashItem]) -> crate::Result<()> {
+    if items.is_empty() {
+        return Ok(());
+    }
+    unsafe {
+        let operation = get_operation(Some(OperationFlags::FOF_NO_UI)).map_err(error)?;
+        for (entry, shell_item) in enumerate().map_err(error)? {
+            if items.iter().any(|item| item.id == entry.id) {
+                operation.DeleteItem(&shell_item, None)
+                    .map_err(|e| crate::Error::from_windows(&e, Some(Path::new(&entry.id)), Some(OperationKind::Delete)))?;
+            }
+        }
+        operation.PerformOperations().map_err(error)
+    }
+}
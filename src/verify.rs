@@ -0,0 +1,281 @@
+//! Checksums of copied files, compared with their source once the copy is
+//! done.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::operation::Step;
+use crate::plan::same_volume;
+use crate::{imp, CopyOptions, Error, MoveStrategy, OperationFlags, OperationKind, OperationReport, ProgressSink, Result};
+
+/// The hash [`crate::FileOperation::verify`] compares files with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Checksum {
+    /// BLAKE3, the faster one.
+    #[default]
+    Blake3,
+    Sha256,
+}
+
+const CHECKSUMS: [(&str, Checksum); 2] = [("blake3", Checksum::Blake3), ("sha256", Checksum::Sha256)];
+
+/// A name that is not one of the checksums.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseChecksumError {
+    pub name: String,
+}
+
+impl fmt::Display for ParseChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = CHECKSUMS.iter().map(|(name, _)| *name).collect();
+        write!(f, "unknown checksum `{}`, expected one of {}", self.name, names.join(", "))
+    }
+}
+
+impl std::error::Error for ParseChecksumError {}
+
+impl FromStr for Checksum {
+    type Err = ParseChecksumError;
+
+    /// `blake3` or `sha256`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let name = s.trim().replace('-', "");
+        CHECKSUMS.iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(&name))
+            .map(|(_, checksum)| *checksum)
+            .ok_or_else(|| ParseChecksumError { name: s.to_owned() })
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = CHECKSUMS.iter().find(|(_, checksum)| checksum == self).map_or("", |(name, _)| *name);
+        f.write_str(name)
+    }
+}
+
+fn read_chunks(path: &Path, mut update: impl FnMut(&[u8])) -> io::Result<()> {
+    let mut file = fs::File::open(path)?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            return Ok(());
+        }
+        update(&buf[..read]);
+    }
+}
+
+/// The file's checksum as lowercase hex.
+pub(crate) fn hash_file(path: &Path, checksum: Checksum) -> io::Result<String> {
+    match checksum {
+        Checksum::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            read_chunks(path, |chunk| {
+                hasher.update(chunk);
+            })?;
+            Ok(hasher.finalize().to_hex().to_string())
+        }
+        Checksum::Sha256 => {
+            let mut hasher = Sha256::new();
+            read_chunks(path, |chunk| hasher.update(chunk))?;
+            Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+        }
+    }
+}
+
+/// With `FOFX_KEEPNEWERFILE`, the newer target was kept, not copied over.
+fn kept(src: &fs::Metadata, target: &Path, flags: OperationFlags) -> bool {
+    if !flags.contains(OperationFlags::FOFX_KEEPNEWERFILE) {
+        return false;
+    }
+    let target_modified = fs::metadata(target).and_then(|meta| meta.modified());
+    matches!((src.modified(), target_modified), (Ok(src), Ok(target)) if target > src)
+}
+
+/// The source of a move checked out at `target`, without the files whose
+/// newer target was kept: those stay, with the folders holding them.
+fn remove_moved(src: &Path, target: &Path, flags: OperationFlags, depth: usize) -> Result<()> {
+    let op = OperationKind::Move;
+    let meta = fs::symlink_metadata(src).map_err(|e| Error::from_io(e, src, op))?;
+    if !meta.is_dir() {
+        if kept(&meta, target, flags) {
+            return Ok(());
+        }
+        return fs::remove_file(src).map_err(|e| Error::from_io(e, src, op));
+    }
+    if depth > 0 && flags.contains(OperationFlags::FOF_NORECURSION) {
+        return Ok(());
+    }
+    for entry in fs::read_dir(src).map_err(|e| Error::from_io(e, src, op))? {
+        let entry = entry.map_err(|e| Error::from_io(e, src, op))?;
+        remove_moved(&entry.path(), &target.join(entry.file_name()), flags, depth + 1)?;
+    }
+    match fs::remove_dir(src) {
+        Err(e) if e.kind() != io::ErrorKind::DirectoryNotEmpty => Err(Error::from_io(e, src, op)),
+        _ => Ok(()),
+    }
+}
+
+/// Every file of `src` against its copy at `target`. Links are copied as
+/// links and not followed.
+pub(crate) fn verify_item(src: &Path, target: &Path, checksum: Checksum, flags: OperationFlags, op: OperationKind, depth: usize) -> Result<()> {
+    let meta = fs::symlink_metadata(src).map_err(|e| Error::from_io(e, src, op))?;
+    if meta.is_dir() {
+        if depth > 0 && flags.contains(OperationFlags::FOF_NORECURSION) {
+            return Ok(());
+        }
+        for entry in fs::read_dir(src).map_err(|e| Error::from_io(e, src, op))? {
+            let entry = entry.map_err(|e| Error::from_io(e, src, op))?;
+            verify_item(&entry.path(), &target.join(entry.file_name()), checksum, flags, op, depth + 1)?;
+        }
+        return Ok(());
+    }
+    if meta.file_type().is_symlink() {
+        return Ok(());
+    }
+    if kept(&meta, target, flags) {
+        return Ok(());
+    }
+    let expected = hash_file(src, checksum).map_err(|e| Error::from_io(e, src, op))?;
+    let actual = hash_file(target, checksum).map_err(|e| Error::from_io(e, target, op))?;
+    if expected != actual {
+        return Err(Error::Mismatch { path: target.to_owned(), op });
+    }
+    Ok(())
+}
+
+/// `steps` with every copy checked against its source, copied again up to
+/// `retries` times on a mismatch. A move to another volume is done as a
+/// checked copy, and its source deleted only once the copy checked out,
+/// except for the files `FOFX_KEEPNEWERFILE` kept; on the same volume a move
+/// is a rename and copies nothing.
+pub(crate) fn perform(
+    steps: &[Step],
+    flags: Option<OperationFlags>,
+    checksum: Checksum,
+    retries: u32,
+    options: &CopyOptions,
+    sink: &mut dyn ProgressSink
+) -> Result<OperationReport> {
+    let given = flags.unwrap_or_default();
+    let mut moved = vec![false; steps.len()];
+    let run: Vec<Step> = steps.iter().zip(moved.iter_mut())
+        .map(|(step, moved)| match step {
+            Step::Move { src, dest, name } if !same_volume(Path::new(src), Path::new(dest)) => {
+                *moved = true;
+                Step::Copy { src: src.clone(), dest: dest.clone(), name: name.clone() }
+            }
+            step => step.clone(),
+        })
+        .collect();
+    let mut report = imp::perform(&run, flags, options, sink)?;
+
+    let again_flags = (given - OperationFlags::FOF_RENAMEONCOLLISION - OperationFlags::FOFX_KEEPNEWERFILE) | OperationFlags::FOF_NOCONFIRMATION;
+    // The source of a move goes away as with the move itself, not to the trash.
+    let delete_flags = (given - OperationFlags::FOF_ALLOWUNDO - OperationFlags::FOFX_RECYCLEONDELETE) | OperationFlags::FOF_NOCONFIRMATION;
+    for (item, (step, &moved)) in report.items.iter_mut().zip(run.iter().zip(&moved)) {
+        let Step::Copy { src, dest, name } = step else {
+            continue;
+        };
+        if item.result.is_err() {
+            continue;
+        }
+        let op = if moved { OperationKind::Move } else { OperationKind::Copy };
+        let src_path = Path::new(src);
+        let target = item.result_path.clone().unwrap_or_else(|| {
+            Path::new(dest).join(name.as_deref().map(Path::new).or(src_path.file_name().map(Path::new)).unwrap_or(Path::new("")))
+        });
+        let mut result = verify_item(src_path, &target, checksum, given, op, 0);
+        for _ in 0..retries {
+            if !matches!(result, Err(Error::Mismatch { .. })) {
+                break;
+            }
+            let again = Step::Copy {
+                src: src.clone(),
+                dest: target.parent().map(|parent| parent.to_string_lossy().into_owned()).unwrap_or_default(),
+                name: target.file_name().map(|name| name.to_string_lossy().into_owned()),
+            };
+            result = imp::perform(&[again], Some(again_flags), options, &mut ())?
+                .into_result()
+                .and_then(|()| verify_item(src_path, &target, checksum, given, op, 0));
+        }
+        if moved {
+            item.op = OperationKind::Move;
+            item.strategy = Some(MoveStrategy::CopyDelete);
+            if result.is_ok() && given.contains(OperationFlags::FOFX_KEEPNEWERFILE) {
+                result = remove_moved(src_path, &target, given, 0);
+            } else if result.is_ok() {
+                result = imp::perform(&[Step::Delete { target: src.clone() }], Some(delete_flags), options, &mut ())?.into_result();
+            }
+        }
+        item.result = result;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileOperation;
+
+    #[test]
+    fn test_verify() {
+        assert_eq!("SHA-256".parse(), Ok(Checksum::Sha256));
+        assert!("md5".parse::<Checksum>().is_err());
+
+        let folder = std::env::current_dir().unwrap().join("test").join("test_verify");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("src").join("sub")).unwrap();
+        fs::create_dir_all(folder.join("dest")).unwrap();
+        fs::write(folder.join("src").join("sub").join("file"), "content").unwrap();
+        let sha256 = hash_file(&folder.join("src").join("sub").join("file"), Checksum::Sha256).unwrap();
+        assert_eq!(sha256, "ed7002b439e9ac845f22357d822bac1444730fbdb6016d3ec9432297b9ec9f73");
+
+        let (src, dest) = (folder.join("src"), folder.join("dest"));
+        let report = FileOperation::new(None)
+            .verify(Checksum::Blake3, 0)
+            .copy(src.to_str().unwrap(), dest.to_str().unwrap())
+            .perform()
+            .unwrap();
+        assert!(report.is_ok());
+
+        // A copy that does not match its source.
+        fs::write(dest.join("src").join("sub").join("file"), "damaged").unwrap();
+        assert_eq!(
+            verify_item(&src, &dest.join("src"), Checksum::Sha256, OperationFlags::empty(), OperationKind::Copy, 0),
+            Err(Error::Mismatch { path: dest.join("src").join("sub").join("file"), op: OperationKind::Copy })
+        );
+
+        let report = FileOperation::new(None)
+            .verify(Checksum::Sha256, 1)
+            .move_as(src.to_str().unwrap(), dest.to_str().unwrap(), "moved")
+            .perform()
+            .unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.items[0].op, OperationKind::Move);
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dest.join("moved").join("sub").join("file")).unwrap(), "content");
+
+        // The newer target of `old` was kept: its source stays, with its folder.
+        let (src, moved) = (folder.join("src2"), dest.join("moved"));
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("sub").join("file"), "content").unwrap();
+        fs::write(src.join("old"), "old").unwrap();
+        fs::write(moved.join("old"), "newer").unwrap();
+        let earlier = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        fs::File::options().write(true).open(src.join("old")).unwrap().set_modified(earlier).unwrap();
+        remove_moved(&src, &moved, OperationFlags::FOFX_KEEPNEWERFILE, 0).unwrap();
+        assert_eq!(fs::read_to_string(src.join("old")).unwrap(), "old");
+        assert!(!src.join("sub").exists());
+        remove_moved(&src, &moved, OperationFlags::empty(), 0).unwrap();
+        assert!(!src.exists());
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}